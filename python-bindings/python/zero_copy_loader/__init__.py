@@ -5,8 +5,9 @@ for zero-copy I/O operations.
 """
 
 from ._zero_copy_loader import PyDataLoader
+import json
 import numpy as np
-from typing import List, Union, Optional, Tuple
+from typing import Any, Dict, Iterator, List, Union, Optional, Tuple
 
 __all__ = ["DataLoader", "to_numpy", "to_torch"]
 
@@ -21,15 +22,132 @@ class DataLoader:
         >>> loader = DataLoader(["shard1.bin", "shard2.bin"])
         >>> sample = loader.get_sample(0)
         >>> array = to_numpy(sample, dtype=np.float32, shape=(224, 224, 3))
+
+        Shards written with a schema return typed arrays directly:
+
+        >>> image = loader.get_arrays(0)["jpg"]
     """
 
-    def __init__(self, shard_paths: List[str]):
+    def __init__(
+        self,
+        shard_paths: List[str],
+        expected_schema: Optional[Dict[str, Any]] = None,
+        prefetch_backend: Optional[str] = None,
+        max_in_flight_bytes: Optional[int] = None,
+        evict_window: Optional[int] = None,
+        max_open_shards: Optional[int] = None,
+        populate: bool = False,
+        huge_pages: bool = False,
+        access_pattern: Optional[str] = None,
+        lock: bool = False,
+        storage: Optional[str] = None,
+        direct_io_uring: bool = False,
+        shuffle: Optional[str] = None,
+        seed: int = 0,
+        window_shards: int = 4,
+        shuffle_buffer: int = 1024,
+        rank: int = 0,
+        world_size: int = 1,
+        partition: Optional[str] = None,
+        drop_last: bool = False,
+        prefetch_ahead: int = 256,
+    ):
         """Initialize the data loader.
 
         Args:
             shard_paths: List of paths to shard files
+            expected_schema: Schema the training job expects, in the same form
+                as ``schema``. Shards whose schema lacks or disagrees with any
+                of its fields are rejected.
+            prefetch_backend: One of "io_uring", "fadvise", "thread_pool" or
+                "noop". By default the first available of io_uring, fadvise
+                and noop is used. "thread_pool" reads pages from worker
+                threads and suits network mounts and FUSE filesystems.
+            max_in_flight_bytes: Upper bound on bytes the "thread_pool"
+                backend reads at once (default 64 MiB).
+            evict_window: When streaming sequentially, keep only this many
+                bytes of samples passed to ``mark_consumed`` in the page
+                cache and evict older ones. By default nothing is evicted.
+            max_open_shards: Open shards lazily and keep at most this many
+                mapped at once, for datasets with more shards than the
+                file-descriptor or mapping limits allow. Only each shard's
+                index stays in memory. By default every shard stays mapped.
+            populate: Read every page in when a shard is mapped
+                (``MAP_POPULATE``, Linux only).
+            huge_pages: Back the mappings with transparent huge pages
+                (``MADV_HUGEPAGE``, Linux only).
+            access_pattern: "sequential" or "random" to tune kernel
+                readahead with ``madvise``. By default it is left alone.
+            lock: ``mlock`` every mapped shard into memory. Subject to
+                ``RLIMIT_MEMLOCK``; see ``lock_shard`` for single shards.
+            storage: "mmap" (default) or "direct". "direct" reads samples
+                with ``O_DIRECT`` into aligned buffers, bypassing the page
+                cache and page faults on large sequential scans. Returned
+                samples are copies either way. The mmap-only options
+                (``populate``, ``huge_pages``, ``access_pattern``, ``lock``,
+                ``evict_window``) have no effect with "direct".
+            direct_io_uring: Submit "direct" reads through io_uring when
+                available.
+            shuffle: Sample order per epoch: "sequential" (default),
+                "random" for a full permutation, "shard" to shuffle the
+                shard order and then the samples within each shard, or
+                "block" to shuffle the shard order and then the samples of
+                ``window_shards`` shards at a time, which keeps page-cache
+                locality. "shard" and "block" also make ``prefetch_next``
+                follow the shuffled shard order.
+            seed: Shuffle seed. The same seed and epoch give the same order
+                across runs and between Rust and Python.
+            window_shards: Number of shards "block" reads from at once.
+            shuffle_buffer: Size of the "block" shuffle buffer, in samples.
+            rank: Rank of this process in distributed training.
+            world_size: Number of ranks. With more than one, each rank reads
+                a disjoint share of every epoch's order, and all ranks get
+                the same number of samples.
+            partition: "samples" (default) splits each epoch's order into
+                contiguous slices. "shards" assigns whole shards to ranks,
+                balanced by sample count, so each rank reads samples only
+                from its own shards. Every shard is still opened once to
+                build the index.
+            drop_last: Drop the samples left over when evening out the
+                ranks. By default short ranks are padded: with "samples"
+                from the start of the epoch's global order, with "shards" by
+                repeating their own order from the start.
+            prefetch_ahead: Number of samples iteration prefetches ahead of
+                the current position. 0 disables it.
+
+        Raises:
+            RuntimeError: If a shard does not match ``expected_schema`` or the
+                requested prefetch backend is unavailable
+            ValueError: If ``prefetch_backend``, ``access_pattern``,
+                ``storage``, ``shuffle`` or ``partition`` is not a known
+                name, or ``rank`` is not below ``world_size``
         """
-        self._loader = PyDataLoader(shard_paths)
+        schema_json = None if expected_schema is None else json.dumps(expected_schema)
+        self._loader = PyDataLoader(
+            shard_paths,
+            schema_json,
+            prefetch_backend,
+            max_in_flight_bytes,
+            evict_window,
+            max_open_shards,
+            populate,
+            huge_pages,
+            access_pattern,
+            lock,
+            storage,
+            direct_io_uring,
+            shuffle,
+            seed,
+            window_shards,
+            shuffle_buffer,
+            rank,
+            world_size,
+            partition,
+            drop_last,
+            prefetch_ahead,
+        )
+        schema = self._loader.schema()
+        self._schema = None if schema is None else json.loads(schema)
 
     def get_sample(self, index: int) -> memoryview:
         """Get a sample by index (zero-copy).
@@ -43,6 +161,115 @@ class DataLoader:
         bytes_obj = self._loader.get_sample(index)
         return memoryview(bytes_obj)
 
+    def get_sample_decoded(self, index: int) -> memoryview:
+        """Get a sample by index, decompressing it if it was stored compressed.
+
+        Uncompressed samples are returned exactly as with ``get_sample``.
+
+        Args:
+            index: Sample index
+
+        Returns:
+            memoryview: View of the decoded sample data
+        """
+        bytes_obj = self._loader.get_sample_decoded(index)
+        return memoryview(bytes_obj)
+
+    def get_fields(self, index: int) -> Dict[str, memoryview]:
+        """Get the named fields of a multi-field sample.
+
+        Fields missing from the sample are omitted. Compressed samples are
+        decompressed first.
+
+        Args:
+            index: Sample index
+
+        Returns:
+            Dict mapping field names (e.g. "jpg", "cls") to memoryviews
+        """
+        fields = self._loader.get_fields(index)
+        return {name: memoryview(data) for name, data in fields.items()}
+
+    @property
+    def schema(self) -> Optional[Dict[str, Any]]:
+        """Schema embedded in the shards, or None if they have none.
+
+        The schema has the form ``{"fields": [{"name", "dtype", "shape",
+        "endianness"}]}``. ``dtype`` uses NumPy names (e.g. "float32"), ``-1``
+        in ``shape`` is inferred from the sample size, and the field named
+        ``""`` describes the whole sample of a shard without fields.
+        """
+        return self._schema
+
+    def get_array(self, index: int, field: str = "") -> Optional[np.ndarray]:
+        """Get a field (or the whole sample) as an array typed by the schema.
+
+        Args:
+            index: Sample index
+            field: Field name, or "" for the whole sample
+
+        Returns:
+            NumPy array, or None if the sample lacks the field
+
+        Raises:
+            KeyError: If the schema does not describe the field
+        """
+        if field:
+            data = self.get_fields(index).get(field)
+        else:
+            data = self.get_sample_decoded(index)
+        if data is None:
+            return None
+        return self._typed(field, data)
+
+    def get_arrays(self, index: int) -> Dict[str, np.ndarray]:
+        """Get every field of a sample that the schema describes as a typed array.
+
+        Args:
+            index: Sample index
+
+        Returns:
+            Dict mapping field names to NumPy arrays
+        """
+        fields = self.get_fields(index)
+        return {
+            name: self._typed(name, data)
+            for name, data in fields.items()
+            if self._field_schema(name) is not None
+        }
+
+    def _field_schema(self, name: str) -> Optional[Dict[str, Any]]:
+        if self._schema is None:
+            return None
+        for field in self._schema["fields"]:
+            if field["name"] == name:
+                return field
+        return None
+
+    def _typed(self, name: str, data: memoryview) -> np.ndarray:
+        field = self._field_schema(name)
+        if field is None:
+            raise KeyError(f"No schema for field '{name}'")
+        byteorder = ">" if field.get("endianness") == "big" else "<"
+        dtype = np.dtype(field["dtype"]).newbyteorder(byteorder)
+        shape = field.get("shape") or (-1,)
+        return to_numpy(data, dtype, tuple(shape))
+
+    def get_by_key(self, key: str) -> memoryview:
+        """Get a sample by its key (zero-copy).
+
+        Args:
+            key: Sample key stored in the shard index
+
+        Returns:
+            memoryview: Zero-copy view of the sample data
+
+        Raises:
+            KeyError: If no shard contains the key
+        """
+        bytes_obj = self._loader.get_by_key(key)
+        return memoryview(bytes_obj)
+
     def get_batch(self, indices: List[int]) -> List[memoryview]:
         """Get multiple samples at once (zero-copy).
 
@@ -55,17 +282,265 @@ class DataLoader:
         bytes_list = self._loader.get_batch(indices)
         return [memoryview(b) for b in bytes_list]
 
-    def prefetch_next(self, count: int = 1) -> None:
+    def __iter__(self) -> Iterator[memoryview]:
+        """Iterate over one epoch of samples in sampler order.
+
+        Each ``for`` loop reads the rest of the current epoch, or the next
+        epoch once the current one is used up. Samples ahead of the position
+        are prefetched as the loop goes, yielded samples count as consumed
+        for ``evict_window``, and a loop broken off early resumes where it
+        stopped.
+
+        Example:
+            >>> for epoch in range(3):
+            ...     for sample in loader:
+            ...         train_step(sample)
+        """
+        for batch in self.batches(1):
+            yield batch[0]
+
+    def __len__(self) -> int:
+        """Number of samples in an epoch (this rank's share when distributed)."""
+        return self._loader.epoch_len()
+
+    def batches(self, batch_size: int, drop_last: bool = False) -> Iterator[List[memoryview]]:
+        """Iterate over one epoch of samples in batches.
+
+        Behaves like ``__iter__`` but yields lists of ``batch_size`` samples.
+
+        Args:
+            batch_size: Number of samples per batch
+            drop_last: Skip the last batch of the epoch if it is short
+
+        Yields:
+            Lists of memoryview objects in sampler order
+
+        Raises:
+            RuntimeError: If a sample cannot be read
+        """
+        self._loader.begin_epoch()
+        while True:
+            batch = self._loader.next_batch(batch_size, drop_last)
+            if batch is None:
+                return
+            yield [memoryview(b) for b in batch]
+
+    def prefetch_next(self, count: int = 1) -> int:
         """Prefetch the next N shards asynchronously.
 
+        Shards are taken in the prefetch order (file order by default). At the
+        end of an epoch the cursor wraps to the start of the next one, so
+        multi-epoch training keeps prefetching.
+
         Args:
             count: Number of shards to prefetch
+
+        Returns:
+            Ticket to pass to ``poll_prefetch`` or ``wait_prefetch``
+        """
+        return self._loader.prefetch_next(count)
+
+    def prefetch_window(self) -> int:
+        """Prefetch the next ``window_shards`` shards in the shuffled order.
+
+        With ``shuffle="block"``, call this while consuming the current
+        window so the next one is already warm.
+
+        Returns:
+            Ticket to pass to ``poll_prefetch`` or ``wait_prefetch``
+        """
+        return self._loader.prefetch_window()
+
+    def prefetch_samples(self, indices: List[int]) -> int:
+        """Prefetch only the pages holding the given samples.
+
+        Useful with random sampling, where the next indices are known but
+        whole-shard prefetch would read far more than needed.
+
+        Args:
+            indices: Global sample indices that will be read next
+
+        Returns:
+            Ticket to pass to ``poll_prefetch`` or ``wait_prefetch``
         """
-        self._loader.prefetch_next(count)
+        return self._loader.prefetch_samples(indices)
 
-    def wait_prefetch(self) -> None:
-        """Wait for prefetch operations to complete."""
-        self._loader.wait_prefetch()
+    def wait_prefetch(self, ticket: Optional[int] = None) -> None:
+        """Wait for prefetch operations to complete.
+
+        Args:
+            ticket: Wait only for this prefetch request. By default waits
+                for all of them.
+
+        Raises:
+            RuntimeError: If a prefetch read failed
+        """
+        self._loader.wait_prefetch(ticket)
+
+    def lock_shard(self, shard: int) -> None:
+        """Pin the pages of a frequently read shard in memory (``mlock``).
+
+        With ``max_open_shards``, the shard is opened if it is not open, and a
+        shard reopened after leaving the LRU is no longer pinned.
+
+        Args:
+            shard: Shard index
+
+        Raises:
+            RuntimeError: If the shard cannot be locked, e.g. over
+                ``RLIMIT_MEMLOCK``, or is read with ``storage="direct"``
+        """
+        self._loader.lock_shard(shard)
+
+    def unlock_shard(self, shard: int) -> None:
+        """Unpin a shard pinned with ``lock_shard``.
+
+        Does nothing for a shard that ``max_open_shards`` has closed.
+
+        Args:
+            shard: Shard index
+        """
+        self._loader.unlock_shard(shard)
+
+    def mark_consumed(self, index: int) -> None:
+        """Tell the loader a sample has been read in sequential order.
+
+        With ``evict_window`` set, pages of consumed samples older than the
+        window are dropped from the page cache, keeping the resident
+        footprint bounded on datasets larger than RAM. Iterating the loader
+        or ``batches`` does this for every sample it yields.
+
+        Args:
+            index: Global index of the sample that was read
+        """
+        self._loader.mark_consumed(index)
+
+    def set_prefetch_order(self, order: List[int]) -> None:
+        """Set the shard order ``prefetch_next`` follows in every epoch.
+
+        Also moves the prefetch cursor back to the start of the first epoch.
+
+        Args:
+            order: Shard indices in the order they will be read
+        """
+        self._loader.set_prefetch_order(order)
+
+    def reset_prefetch(self) -> None:
+        """Move the prefetch cursor back to the start of the first epoch."""
+        self._loader.reset_prefetch()
+
+    @property
+    def prefetch_position(self) -> Tuple[int, int]:
+        """Prefetch cursor as ``(epoch, position within the epoch)``."""
+        return self._loader.prefetch_position()
+
+    def poll_prefetch(self, ticket: int) -> bool:
+        """Check whether a prefetch request has completed without blocking.
+
+        Args:
+            ticket: Ticket returned by ``prefetch_next`` or ``prefetch_samples``
+
+        Returns:
+            True once every read of the request has finished
+
+        Raises:
+            RuntimeError: If a read of the request failed
+        """
+        return self._loader.poll_prefetch(ticket)
+
+    def is_resident(self, shard: int) -> bool:
+        """Check whether every page of a shard is in the page cache.
+
+        Args:
+            shard: Shard index
+
+        Returns:
+            True if reading the shard will not touch the disk. Always False
+            with ``storage="direct"``, which bypasses the page cache, and for
+            a shard that ``max_open_shards`` has closed, which is not reopened
+            just to check.
+
+        Raises:
+            IndexError: If ``shard`` is out of range
+        """
+        return self._loader.is_resident(shard)
+
+    def sample_order(self, epoch: int) -> List[int]:
+        """Get the order samples are read in for an epoch.
+
+        Args:
+            epoch: Epoch number, starting at 0
+
+        Returns:
+            Global sample indices, reshuffled for each epoch from ``seed``.
+            With ``world_size`` above one, only this rank's share.
+        """
+        return self._loader.sample_order(epoch)
+
+    def next_indices(self, count: int) -> List[int]:
+        """Take the next sample indices of the current epoch's order.
+
+        Args:
+            count: Maximum number of indices to take
+
+        Returns:
+            Up to ``count`` global sample indices. After the epoch is used up,
+            the next call starts the following epoch.
+        """
+        return self._loader.next_indices(count)
+
+    def set_epoch(self, epoch: int) -> None:
+        """Move to the start of an epoch, as with ``DistributedSampler``.
+
+        Args:
+            epoch: Epoch number, starting at 0
+        """
+        self._loader.set_epoch(epoch)
+
+    @property
+    def epoch(self) -> int:
+        """Current epoch."""
+        return self._loader.epoch()
+
+    @property
+    def position(self) -> int:
+        """Position of the next sample in the current epoch's order."""
+        return self._loader.position()
+
+    def state_dict(self) -> Dict[str, Any]:
+        """Get the loader position for checkpointing.
+
+        Covers the sampler seed and settings, the epoch, the position in the
+        epoch and the prefetch position. The dict is JSON-serializable.
+
+        Returns:
+            State to pass to ``load_state_dict`` when resuming
+        """
+        return json.loads(self._loader.state())
+
+    def load_state_dict(self, state: Dict[str, Any]) -> None:
+        """Resume from a state returned by ``state_dict``.
+
+        The loader continues with exactly the samples that were left in the
+        saved epoch, using the saved seed even if this loader was built with
+        another one. The rank stays this loader's own, so a state saved on
+        rank 0 can be loaded on every rank.
+
+        Args:
+            state: State from ``state_dict``
+
+        Raises:
+            ValueError: If the state is malformed, has no sampler settings to
+                rebuild the order from, was saved with a different
+                ``world_size``, or was saved for a dataset with a different
+                number of samples per epoch
+        """
+        self._loader.restore(json.dumps(state))
+
+    @property
+    def prefetch_backend(self) -> str:
+        """Prefetch backend in use: "io_uring", "fadvise", "thread_pool" or "noop"."""
+        return self._loader.prefetch_backend()
 
     @property
     def total_samples(self) -> int:
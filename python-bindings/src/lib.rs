@@ -1,5 +1,12 @@
 use pyo3::prelude::*;
-use rust_core::{DataLoader, DataLoaderError};
+use pyo3::types::{PyBytes, PyDict};
+use rust_core::mmap::{AccessPattern, MmapConfig};
+use rust_core::prefetch::{PrefetchBackend, PrefetchConfig, PrefetchTicket};
+use rust_core::reader::ReaderConfig;
+use rust_core::sampler::{PartitionMode, SamplerConfig, ShuffleMode};
+use rust_core::schema::Schema;
+use rust_core::storage::{DirectConfig, StorageBackend};
+use rust_core::{DataLoader, DataLoaderConfig, DataLoaderError, LoaderState};
 use std::path::PathBuf;
 
 /// Pythonバインディング用のエラータイプ
@@ -31,14 +38,165 @@ pub struct PyDataLoader {
 #[pymethods]
 impl PyDataLoader {
     /// 新しいデータローダーを作成
+    ///
+    /// `expected_schema` はJSON文字列で、互換でないシャードがあればエラーになる。
+    /// `prefetch_backend` を省略すると使える実装方式を自動で選ぶ。
+    /// `storage` は "mmap"（既定）または "direct"（`O_DIRECT`、`direct_io_uring` でio_uringを使う）。
+    /// `shuffle` は "sequential"（既定）/ "random" / "shard" / "block" で、`seed` と合わせて読み込み順を決める。
+    /// `world_size` が2以上なら、`partition`（"samples" / "shards"）の単位で `rank` の取り分だけを読む。
     #[new]
-    fn new(shard_paths: Vec<String>) -> PyResult<Self> {
+    #[pyo3(signature = (
+        shard_paths,
+        expected_schema=None,
+        prefetch_backend=None,
+        max_in_flight_bytes=None,
+        evict_window=None,
+        max_open_shards=None,
+        populate=false,
+        huge_pages=false,
+        access_pattern=None,
+        lock=false,
+        storage=None,
+        direct_io_uring=false,
+        shuffle=None,
+        seed=0,
+        window_shards=4,
+        shuffle_buffer=1024,
+        rank=0,
+        world_size=1,
+        partition=None,
+        drop_last=false,
+        prefetch_ahead=256,
+    ))]
+    fn new(
+        shard_paths: Vec<String>,
+        expected_schema: Option<&str>,
+        prefetch_backend: Option<&str>,
+        max_in_flight_bytes: Option<u64>,
+        evict_window: Option<u64>,
+        max_open_shards: Option<usize>,
+        populate: bool,
+        huge_pages: bool,
+        access_pattern: Option<&str>,
+        lock: bool,
+        storage: Option<&str>,
+        direct_io_uring: bool,
+        shuffle: Option<&str>,
+        seed: u64,
+        window_shards: usize,
+        shuffle_buffer: usize,
+        rank: usize,
+        world_size: usize,
+        partition: Option<&str>,
+        drop_last: bool,
+        prefetch_ahead: usize,
+    ) -> PyResult<Self> {
         let paths: Vec<PathBuf> = shard_paths.iter().map(|s| PathBuf::from(s)).collect();
-        let loader = DataLoader::new(&paths)
+        let expected_schema = expected_schema
+            .map(serde_json::from_str::<Schema>)
+            .transpose()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        let backend = prefetch_backend
+            .map(|name| {
+                PrefetchBackend::from_name(name).ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unknown prefetch backend: {}", name))
+                })
+            })
+            .transpose()?;
+        let access = match access_pattern {
+            None | Some("normal") => AccessPattern::Normal,
+            Some("sequential") => AccessPattern::Sequential,
+            Some("random") => AccessPattern::Random,
+            Some(other) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Unknown access pattern: {}",
+                    other
+                )))
+            }
+        };
+        let storage = match storage {
+            None | Some("mmap") => StorageBackend::Mmap,
+            Some("direct") => StorageBackend::Direct(DirectConfig {
+                io_uring: direct_io_uring,
+                ..Default::default()
+            }),
+            Some(other) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Unknown storage backend: {}",
+                    other
+                )))
+            }
+        };
+        let shuffle = shuffle
+            .map(|name| {
+                ShuffleMode::from_name(name).ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unknown shuffle mode: {}", name))
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let partition = partition
+            .map(|name| {
+                PartitionMode::from_name(name).ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unknown partition mode: {}", name))
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
+        if rank >= world_size {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Rank {} is out of range for world size {}",
+                rank, world_size
+            )));
+        }
+        let mut prefetch = PrefetchConfig {
+            backend,
+            ..Default::default()
+        };
+        if let Some(max_in_flight_bytes) = max_in_flight_bytes {
+            prefetch.max_in_flight_bytes = max_in_flight_bytes;
+        }
+        let config = DataLoaderConfig {
+            reader: ReaderConfig {
+                expected_schema,
+                max_open_shards,
+                mmap: MmapConfig {
+                    populate,
+                    huge_pages,
+                    access,
+                    lock,
+                },
+                storage,
+                ..Default::default()
+            },
+            prefetch,
+            evict_window,
+            sampler: SamplerConfig {
+                shuffle,
+                seed,
+                window_shards,
+                shuffle_buffer,
+                rank,
+                world_size,
+                partition,
+                drop_last,
+            },
+            prefetch_ahead,
+        };
+        let loader = DataLoader::with_config(&paths, config)
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
         Ok(Self { loader })
     }
 
+    /// 型スキーマをJSON文字列で取得（スキーマを持たないシャードでは `None`）
+    fn schema(&self) -> PyResult<Option<String>> {
+        self.loader
+            .schema()
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
+    }
+
     /// 指定されたインデックスのサンプルを取得（ゼロコピーでmemoryviewを返す）
     fn get_sample(&self, index: usize) -> PyResult<PyObject> {
         let sample = self.loader.get_sample(index)
@@ -46,7 +204,44 @@ impl PyDataLoader {
 
         // Pythonのmemoryviewオブジェクトを作成（ゼロコピー）
         Python::with_gil(|py| {
-            let bytes = PyBytes::new(py, sample);
+            let bytes = PyBytes::new(py, &sample);
+            Ok(bytes.into())
+        })
+    }
+
+    /// 指定されたインデックスのサンプルを展開して取得（圧縮されたサンプルを展開）
+    fn get_sample_decoded(&self, index: usize) -> PyResult<PyObject> {
+        let sample = self.loader.get_sample_decoded(index)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
+
+        Python::with_gil(|py| {
+            let bytes = PyBytes::new(py, &sample);
+            Ok(bytes.into())
+        })
+    }
+
+    /// 指定されたサンプルのフィールドを辞書で取得（圧縮されたサンプルは展開する）
+    fn get_fields(&self, index: usize) -> PyResult<PyObject> {
+        let mut buf = Vec::new();
+        let view = self.loader.get_view_decoded(index, &mut buf)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
+
+        Python::with_gil(|py| {
+            let dict = PyDict::new(py);
+            for (name, data) in view.fields() {
+                dict.set_item(*name, PyBytes::new(py, data))?;
+            }
+            Ok(dict.into())
+        })
+    }
+
+    /// キーでサンプルを取得
+    fn get_by_key(&self, key: &str) -> PyResult<PyObject> {
+        let sample = self.loader.get_by_key(key)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!("{}", e)))?;
+
+        Python::with_gil(|py| {
+            let bytes = PyBytes::new(py, &sample);
             Ok(bytes.into())
         })
     }
@@ -59,25 +254,159 @@ impl PyDataLoader {
         Python::with_gil(|py| {
             let mut result = Vec::new();
             for sample in samples {
-                let bytes = PyBytes::new(py, sample);
+                let bytes = PyBytes::new(py, &sample);
                 result.push(bytes.into());
             }
             Ok(result)
         })
     }
 
-    /// 次のN個のシャードをプリフェッチ
-    fn prefetch_next(&mut self, count: usize) -> PyResult<()> {
-        self.loader.prefetch_next(count)
+    /// 次のN個のシャードをプリフェッチ（チケットを返す）
+    fn prefetch_next(&mut self, count: usize) -> PyResult<u64> {
+        let ticket = self.loader.prefetch_next(count)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
+        Ok(ticket.id())
+    }
+
+    /// サンプラーのウィンドウ分だけ次のシャードをプリフェッチ（チケットを返す）
+    fn prefetch_window(&mut self) -> PyResult<u64> {
+        let ticket = self.loader.prefetch_window()
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
-        Ok(())
+        Ok(ticket.id())
     }
 
-    /// プリフェッチの完了を待つ
-    fn wait_prefetch(&mut self) -> PyResult<()> {
-        self.loader.wait_prefetch()
+    /// 指定されたサンプルのページだけをプリフェッチ（チケットを返す）
+    fn prefetch_samples(&mut self, indices: Vec<usize>) -> PyResult<u64> {
+        let ticket = self.loader.prefetch_samples(&indices)
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
-        Ok(())
+        Ok(ticket.id())
+    }
+
+    /// プリフェッチの完了を待つ（チケットを指定するとそのプリフェッチだけを待つ）
+    #[pyo3(signature = (ticket=None))]
+    fn wait_prefetch(&mut self, ticket: Option<u64>) -> PyResult<()> {
+        match ticket {
+            Some(ticket) => self.loader.wait_prefetch_ticket(PrefetchTicket::from_id(ticket)),
+            None => self.loader.wait_prefetch(),
+        }
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
+    }
+
+    /// シャードのページをメモリに固定する
+    fn lock_shard(&self, shard: usize) -> PyResult<()> {
+        self.loader.lock_shard(shard)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
+    }
+
+    /// シャードのページの固定を解除する
+    fn unlock_shard(&self, shard: usize) -> PyResult<()> {
+        self.loader.unlock_shard(shard)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
+    }
+
+    /// 順次読み込みでサンプルを読み終えたことを伝える（古いページを追い出す）
+    fn mark_consumed(&mut self, index: usize) -> PyResult<()> {
+        self.loader.mark_consumed(index)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
+    }
+
+    /// シャードの読み込み順を設定し、プリフェッチの位置を先頭に戻す（全エポック共通）
+    fn set_prefetch_order(&mut self, order: Vec<usize>) {
+        self.loader.set_prefetch_order(move |_| order.clone());
+    }
+
+    /// プリフェッチの位置を最初のエポックの先頭に戻す
+    fn reset_prefetch(&mut self) {
+        self.loader.reset_prefetch();
+    }
+
+    /// プリフェッチの位置を (エポック, エポック内の位置) で取得
+    fn prefetch_position(&self) -> (u64, usize) {
+        let cursor = self.loader.prefetch_cursor();
+        (cursor.epoch(), cursor.position())
+    }
+
+    /// チケットのプリフェッチが完了したかどうか
+    fn poll_prefetch(&mut self, ticket: u64) -> PyResult<bool> {
+        self.loader.poll_prefetch(PrefetchTicket::from_id(ticket))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
+    }
+
+    /// シャードの全ページがページキャッシュに載っているかどうか
+    fn is_resident(&self, shard: usize) -> PyResult<bool> {
+        self.loader.is_resident(shard)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIndexError, _>(format!("{}", e)))
+    }
+
+    /// 使用中のプリフェッチの実装方式を取得（"io_uring" / "fadvise" / "thread_pool" / "noop"）
+    fn prefetch_backend(&self) -> &'static str {
+        self.loader.prefetch_backend().name()
+    }
+
+    /// 指定されたエポックのサンプルの読み込み順を取得
+    fn sample_order(&self, epoch: u64) -> Vec<usize> {
+        self.loader.sample_order(epoch)
+    }
+
+    /// 現在のエポックの読み込み順で次のサンプルのインデックスを最大 `count` 個取得
+    fn next_indices(&mut self, count: usize) -> Vec<usize> {
+        self.loader.next_indices(count)
+    }
+
+    /// 現在のエポックを読み終えていれば次のエポックの先頭に移る
+    fn begin_epoch(&mut self) -> bool {
+        self.loader.begin_epoch()
+    }
+
+    /// 現在のエポックの次のバッチを取得（エポックの終わりでは `None`）
+    fn next_batch(&mut self, batch_size: usize, drop_last: bool) -> PyResult<Option<Vec<PyObject>>> {
+        let Some(batch) = self.loader.next_batch(batch_size, drop_last) else {
+            return Ok(None);
+        };
+        let samples = batch
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
+
+        Python::with_gil(|py| {
+            let result = samples
+                .into_iter()
+                .map(|sample| PyBytes::new(py, &sample).into())
+                .collect();
+            Ok(Some(result))
+        })
+    }
+
+    /// 1エポックで読むサンプル数
+    fn epoch_len(&self) -> usize {
+        self.loader.epoch_len()
+    }
+
+    /// 指定されたエポックの先頭に移る
+    fn set_epoch(&mut self, epoch: u64) {
+        self.loader.set_epoch(epoch);
+    }
+
+    /// 現在のエポック
+    fn epoch(&self) -> u64 {
+        self.loader.epoch()
+    }
+
+    /// 現在のエポックの読み込み順で次に読む位置
+    fn position(&self) -> usize {
+        self.loader.position()
+    }
+
+    /// 再開用の状態をJSON文字列で取得
+    fn state(&self) -> PyResult<String> {
+        serde_json::to_string(&self.loader.state())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))
+    }
+
+    /// JSON文字列の状態から再開する
+    fn restore(&mut self, state: &str) -> PyResult<()> {
+        let state: LoaderState = serde_json::from_str(state)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        self.loader.restore(&state)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))
     }
 
     /// 総サンプル数を取得
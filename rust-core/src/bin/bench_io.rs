@@ -1,60 +1,20 @@
+use rust_core::format::{ShardHeader, ShardWriter};
+use rust_core::DataLoader;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::Read;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
-// Note: This benchmark should be in rust-core/src/bin/ or use rust-core as a dependency
-// For now, we'll use a simplified version that can be compiled separately
-
 fn create_test_shard(path: &PathBuf, num_samples: usize, sample_size: usize) -> std::io::Result<()> {
-    let mut file = File::create(path)?;
-    let mut buf = Vec::new();
-
-    // ヘッダー
-    let header_offset = 0;
-    let metadata_offset = ShardHeader::SIZE as u64;
-    let data_offset = metadata_offset + 10000; // メタデータ用のスペース
-    let header = ShardHeader::new(metadata_offset, data_offset);
-    header.write(&mut buf)?;
-
-    // メタデータ
-    let mut samples = Vec::new();
-    let mut current_offset = 0u64;
-    for _ in 0..num_samples {
-        samples.push(SampleMetadata {
-            offset: current_offset,
-            size: sample_size as u64,
-        });
-        current_offset += sample_size as u64;
-    }
-
-    let metadata = ShardMetadata {
-        num_samples: num_samples as u64,
-        samples,
-    };
-
-    let metadata_start = buf.len();
-    metadata.write(&mut buf)?;
-    let metadata_end = buf.len();
-
-    // データオフセットを更新
-    let data_offset = metadata_end as u64;
-    buf[header_offset + 4 + 2..header_offset + 4 + 2 + 8]
-        .copy_from_slice(&data_offset.to_le_bytes());
-
-    // パディング
-    while buf.len() < data_offset as usize {
-        buf.push(0);
-    }
+    let mut writer = ShardWriter::new(path)?;
 
     // サンプルデータを書き込む
     let sample_data = vec![0u8; sample_size];
     for _ in 0..num_samples {
-        buf.extend_from_slice(&sample_data);
+        writer.append(&sample_data)?;
     }
 
-    file.write_all(&buf)?;
-    file.flush()?;
+    writer.finish()?;
     Ok(())
 }
 
@@ -74,14 +34,14 @@ fn benchmark_standard_read(path: &PathBuf, num_samples: usize, sample_size: usiz
 
 fn benchmark_mmap_read(path: &PathBuf, num_samples: usize, sample_size: usize) -> Duration {
     use memmap2::MmapOptions;
-    use std::fs::File;
 
     let file = File::open(path).unwrap();
     let mmap = unsafe { MmapOptions::new().map(&file).unwrap() };
     let mut total_time = Duration::ZERO;
 
-    // データセクションの開始位置を仮定（実際にはヘッダーを読む必要がある）
-    let data_start = 1000; // 簡略化
+    // データセクションの開始位置をヘッダーから取得
+    let header = ShardHeader::read(&mut &mmap[..ShardHeader::SIZE]).unwrap();
+    let data_start = header.data_offset as usize;
 
     for i in 0..num_samples {
         let start = Instant::now();
@@ -0,0 +1,546 @@
+//! シャードの調査・保守用CLI
+//!
+//! ```text
+//! zcl info   [--json] <shard>...
+//! zcl verify [--json] <shard>...
+//! zcl cat    [--decode] [--field NAME] (<index> | --key KEY) <shard>...
+//! zcl ls     [--json] <shard>...
+//! zcl stats  [--json] <shard>...
+//! zcl merge   --output FILE <shard>...
+//! zcl split   --output DIR (--samples N | --bytes SIZE | --parts K) <shard>
+//! zcl reshard --output DIR [--prefix NAME] (--samples N | --bytes SIZE) <shard>...
+//! ```
+
+use rust_core::checksum::VerifyMode;
+use rust_core::codec::Codec;
+use rust_core::reader::{MultiShardReader, ReaderConfig, ShardReader};
+use rust_core::reshard::{self, ShardLimit};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::process::ExitCode;
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "\
+usage: zcl <command> [options] <shard>...
+
+commands:
+  info    ヘッダー・バージョン・サンプル数・サイズ分布を表示
+  verify  チェックサムを検証（失敗したシャードがあれば終了コード1）
+  cat     サンプルのバイト列を標準出力へ書き出す（<index> または --key KEY）
+  ls      サンプルの一覧を表示
+  stats   シャード全体の統計を表示
+  merge   シャードを1つにまとめる（--output FILE）
+  split   シャードを分割する（--output DIR、--samples / --bytes / --parts）
+  reshard シャードの集合を目標サイズに詰め直す（--output DIR、--samples / --bytes）
+
+options:
+  --json          JSONで出力
+  --decode        圧縮されたサンプルを展開して出力（cat）
+  --field NAME    指定したフィールドのみ出力（cat）
+  --key KEY       キーでサンプルを指定（cat）
+  --output PATH   出力先（merge: ファイル、split/reshard: ディレクトリ）
+  --samples N     出力シャードあたりの最大サンプル数
+  --bytes SIZE    出力シャードあたりの最大バイト数（K/M/G の接尾辞可）
+  --parts K       K個のシャードに分割（split）
+  --prefix NAME   出力ファイル名の接頭辞（reshard、既定は shard）";
+
+/// 値を取るオプション
+const VALUE_OPTIONS: &[&str] = &["field", "key", "output", "samples", "bytes", "parts", "prefix"];
+
+/// コマンドライン引数（フラグ・値付きオプション・位置引数）
+struct Args {
+    flags: Vec<String>,
+    options: HashMap<String, String>,
+    positional: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> CliResult<Self> {
+        let mut parsed = Self {
+            flags: Vec::new(),
+            options: HashMap::new(),
+            positional: Vec::new(),
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg.clone());
+                continue;
+            };
+            if let Some((name, value)) = name.split_once('=') {
+                parsed.options.insert(name.to_string(), value.to_string());
+            } else if VALUE_OPTIONS.contains(&name) {
+                let value = iter.next().ok_or_else(|| format!("--{} requires a value", name))?;
+                parsed.options.insert(name.to_string(), value.clone());
+            } else {
+                parsed.flags.push(name.to_string());
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn required(&self, name: &str) -> CliResult<&str> {
+        self.option(name).ok_or_else(|| format!("--{} is required", name).into())
+    }
+
+    /// 出力シャードの上限（--samples / --bytes）
+    fn limit(&self) -> CliResult<ShardLimit> {
+        let limit = ShardLimit {
+            max_samples: self.option("samples").map(str::parse).transpose()?,
+            max_bytes: self.option("bytes").map(parse_size).transpose()?,
+        };
+        if limit == ShardLimit::default() {
+            return Err("--samples or --bytes is required".into());
+        }
+        Ok(limit)
+    }
+
+    /// 位置引数のシャードパス（1つ以上必要）
+    fn shards(&self, skip: usize) -> CliResult<&[String]> {
+        let shards = self.positional.get(skip..).unwrap_or_default();
+        if shards.is_empty() {
+            return Err("no shard files given".into());
+        }
+        Ok(shards)
+    }
+}
+
+/// サイズの分布（2の累乗ごとのバケット）
+#[derive(Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+}
+
+impl Histogram {
+    fn add(&mut self, size: u64) {
+        let bucket = size.max(1).next_power_of_two().trailing_zeros() as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    fn to_json(&self) -> Value {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(bucket, &count)| json!({ "le": 1u64 << bucket, "count": count }))
+            .collect()
+    }
+
+    fn print(&self) {
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            if count > 0 {
+                println!("    <= {:>12} B: {}", 1u64 << bucket, count);
+            }
+        }
+    }
+}
+
+/// サンプルサイズの集計
+#[derive(Default)]
+struct SizeStats {
+    samples: u64,
+    stored_bytes: u64,
+    decoded_bytes: u64,
+    compressed: u64,
+    min: Option<u64>,
+    max: u64,
+    histogram: Histogram,
+}
+
+impl SizeStats {
+    fn add_shard(&mut self, reader: &ShardReader) -> CliResult<()> {
+        for index in 0..reader.num_samples() {
+            let sample = reader.sample_metadata(index)?;
+            let decoded = sample.decoded_size();
+            self.samples += 1;
+            self.stored_bytes += sample.size;
+            self.decoded_bytes += decoded;
+            if sample.codec != Codec::None {
+                self.compressed += 1;
+            }
+            self.min = Some(self.min.map_or(decoded, |min| min.min(decoded)));
+            self.max = self.max.max(decoded);
+            self.histogram.add(decoded);
+        }
+        Ok(())
+    }
+
+    fn mean(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.decoded_bytes as f64 / self.samples as f64
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "samples": self.samples,
+            "stored_bytes": self.stored_bytes,
+            "decoded_bytes": self.decoded_bytes,
+            "compressed_samples": self.compressed,
+            "min_size": self.min.unwrap_or(0),
+            "max_size": self.max,
+            "mean_size": self.mean(),
+            "histogram": self.histogram.to_json(),
+        })
+    }
+
+    fn print(&self) {
+        println!("  samples:       {}", self.samples);
+        println!("  stored bytes:  {}", self.stored_bytes);
+        println!("  decoded bytes: {}", self.decoded_bytes);
+        println!("  compressed:    {}", self.compressed);
+        println!(
+            "  sample size:   min {} / max {} / mean {:.1}",
+            self.min.unwrap_or(0),
+            self.max,
+            self.mean()
+        );
+        println!("  size histogram:");
+        self.histogram.print();
+    }
+}
+
+/// `64M` のような接尾辞付きのサイズを解釈する
+fn parse_size(value: &str) -> CliResult<u64> {
+    let (digits, unit) = match value.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((pos, _)) => value.split_at(pos),
+        None => (value, ""),
+    };
+    let scale: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("invalid size: {}", value).into()),
+    };
+    digits
+        .parse::<u64>()?
+        .checked_mul(scale)
+        .ok_or_else(|| format!("size too large: {}", value).into())
+}
+
+fn print_json(value: &Value) -> CliResult<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn cmd_info(args: &Args) -> CliResult<ExitCode> {
+    let mut shards = Vec::new();
+    for path in args.shards(0)? {
+        let reader = ShardReader::new(path)?;
+        let header = reader.header();
+        let mut stats = SizeStats::default();
+        stats.add_shard(&reader)?;
+
+        let info = json!({
+            "path": path,
+            "file_size": std::fs::metadata(path)?.len(),
+            "version": header.version,
+            "flags": header.flags,
+            "num_samples": reader.num_samples(),
+            "data_offset": header.data_offset,
+            "index_offset": header.index_offset,
+            "metadata_offset": header.metadata_offset,
+            "alignment": reader.alignment(),
+            "checksum": header.checksum_kind,
+            "fields": reader.field_names(),
+            "has_keys": reader.has_keys(),
+            "schema": reader.schema(),
+            "sizes": stats.to_json(),
+        });
+        if args.flag("json") {
+            shards.push(info);
+            continue;
+        }
+
+        println!("{}", path);
+        println!("  version:       {}", header.version);
+        println!("  flags:         0x{:04X}", header.flags);
+        println!("  file size:     {}", info["file_size"]);
+        println!("  alignment:     {}", reader.alignment());
+        println!("  checksum:      {:?}", header.checksum_kind);
+        if !reader.field_names().is_empty() {
+            println!("  fields:        {}", reader.field_names().join(", "));
+        }
+        println!("  keys:          {}", if reader.has_keys() { "yes" } else { "no" });
+        if let Some(schema) = reader.schema() {
+            println!("  schema:        {}", serde_json::to_string(schema)?);
+        }
+        stats.print();
+    }
+    if args.flag("json") {
+        print_json(&Value::Array(shards))?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cmd_verify(args: &Args) -> CliResult<ExitCode> {
+    let config = ReaderConfig {
+        verify: VerifyMode::OnOpen,
+        ..Default::default()
+    };
+    let mut results = Vec::new();
+    let mut failed = false;
+    for path in args.shards(0)? {
+        let result = ShardReader::with_config(path, config.clone());
+        let error = result.err().map(|e| e.to_string());
+        failed |= error.is_some();
+        if args.flag("json") {
+            results.push(json!({ "path": path, "ok": error.is_none(), "error": error }));
+        } else {
+            match error {
+                None => println!("{}: OK", path),
+                Some(error) => println!("{}: FAILED ({})", path, error),
+            }
+        }
+    }
+    if args.flag("json") {
+        print_json(&Value::Array(results))?;
+    }
+    Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+fn cmd_cat(args: &Args) -> CliResult<ExitCode> {
+    let (index, shards) = match args.option("key") {
+        Some(_) => (None, args.shards(0)?),
+        None => {
+            let index = args
+                .positional
+                .first()
+                .ok_or("cat requires <index> or --key KEY")?
+                .parse::<usize>()?;
+            (Some(index), args.shards(1)?)
+        }
+    };
+    let reader = MultiShardReader::new(shards)?;
+    let index = match (index, args.option("key")) {
+        (Some(index), _) => index,
+        (None, Some(key)) => reader
+            .find_key(key)?
+            .ok_or_else(|| format!("key not found: {}", key))?,
+        (None, None) => unreachable!(),
+    };
+
+    let mut buf = Vec::new();
+    let sample;
+    let bytes = match args.option("field") {
+        Some(field) => reader
+            .get_view_decoded(index, &mut buf)?
+            .field(field)
+            .ok_or_else(|| format!("sample {} has no field '{}'", index, field))?,
+        None if args.flag("decode") => reader.get_sample_decoded(index, &mut buf)?,
+        None => {
+            sample = reader.get_sample(index)?;
+            &sample
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(bytes)?;
+    stdout.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cmd_ls(args: &Args) -> CliResult<ExitCode> {
+    let mut entries = Vec::new();
+    let mut global_index = 0;
+    for path in args.shards(0)? {
+        let reader = ShardReader::new(path)?;
+        for index in 0..reader.num_samples() {
+            let sample = reader.sample_metadata(index)?;
+            let key = reader.sample_key(index);
+            if args.flag("json") {
+                entries.push(json!({
+                    "index": global_index,
+                    "shard": path,
+                    "shard_index": index,
+                    "offset": sample.offset,
+                    "size": sample.size,
+                    "decoded_size": sample.decoded_size(),
+                    "codec": sample.codec,
+                    "key": key,
+                }));
+            } else {
+                println!(
+                    "{:>8} {}:{} offset={} size={} decoded={} codec={:?}{}",
+                    global_index,
+                    path,
+                    index,
+                    sample.offset,
+                    sample.size,
+                    sample.decoded_size(),
+                    sample.codec,
+                    key.map(|key| format!(" key={}", key)).unwrap_or_default()
+                );
+            }
+            global_index += 1;
+        }
+    }
+    if args.flag("json") {
+        print_json(&Value::Array(entries))?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cmd_stats(args: &Args) -> CliResult<ExitCode> {
+    let shards = args.shards(0)?;
+    let mut stats = SizeStats::default();
+    let mut file_bytes = 0;
+    for path in shards {
+        let reader = ShardReader::new(path)?;
+        stats.add_shard(&reader)?;
+        file_bytes += std::fs::metadata(path)?.len();
+    }
+
+    if args.flag("json") {
+        let mut value = stats.to_json();
+        value["shards"] = json!(shards.len());
+        value["file_bytes"] = json!(file_bytes);
+        print_json(&value)?;
+    } else {
+        println!("{} shards, {} bytes on disk", shards.len(), file_bytes);
+        stats.print();
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cmd_merge(args: &Args) -> CliResult<ExitCode> {
+    let output = args.required("output")?;
+    let header = reshard::merge(args.shards(0)?, output)?;
+    println!("{}: {} samples", output, header.num_samples);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cmd_split(args: &Args) -> CliResult<ExitCode> {
+    let output = args.required("output")?;
+    let [input] = args.shards(0)? else {
+        return Err("split takes exactly one shard".into());
+    };
+    let limit = match args.option("parts") {
+        Some(parts) => {
+            let parts = parts.parse::<usize>()?.max(1);
+            let num_samples = ShardReader::new(input)?.num_samples();
+            ShardLimit::samples(num_samples.div_ceil(parts))
+        }
+        None => args.limit()?,
+    };
+    std::fs::create_dir_all(output)?;
+    for path in reshard::split(input, output, limit)? {
+        println!("{}", path.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cmd_reshard(args: &Args) -> CliResult<ExitCode> {
+    let output = args.required("output")?;
+    let limit = args.limit()?;
+    std::fs::create_dir_all(output)?;
+    let prefix = args.option("prefix").unwrap_or("shard");
+    for path in reshard::reshard(args.shards(0)?, output, prefix, limit)? {
+        println!("{}", path.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run(argv: &[String]) -> CliResult<ExitCode> {
+    let Some((command, rest)) = argv.split_first() else {
+        eprintln!("{}", USAGE);
+        return Ok(ExitCode::from(2));
+    };
+    let args = Args::parse(rest)?;
+    match command.as_str() {
+        "info" => cmd_info(&args),
+        "verify" => cmd_verify(&args),
+        "cat" => cmd_cat(&args),
+        "ls" => cmd_ls(&args),
+        "stats" => cmd_stats(&args),
+        "merge" => cmd_merge(&args),
+        "split" => cmd_split(&args),
+        "reshard" => cmd_reshard(&args),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(ExitCode::SUCCESS)
+        }
+        _ => Err(format!("unknown command: {}\n\n{}", command, USAGE).into()),
+    }
+}
+
+fn main() -> ExitCode {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    match run(&argv) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("zcl: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_core::format::ShardWriter;
+    use tempfile::NamedTempFile;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("64M").unwrap(), 64 << 20);
+        assert_eq!(parse_size("64mb").unwrap(), 64 << 20);
+        assert_eq!(parse_size("1GiB").unwrap(), 1 << 30);
+        assert_eq!(parse_size("2K").unwrap(), 2048);
+        assert_eq!(parse_size("10B").unwrap(), 10);
+        for invalid in ["", "M", "64X", "1.5G", "1BB", "1IBIB", "-1K", "99999999999T"] {
+            assert!(parse_size(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_args_parse() {
+        let args = Args::parse(&strings(&["--json", "--field", "image", "--bytes=64M", "a.bin", "b.bin"])).unwrap();
+        assert!(args.flag("json"));
+        assert!(!args.flag("decode"));
+        assert_eq!(args.option("field"), Some("image"));
+        assert_eq!(args.option("key"), None);
+        assert_eq!(args.shards(0).unwrap(), ["a.bin", "b.bin"]);
+        assert_eq!(args.shards(1).unwrap(), ["b.bin"]);
+        assert!(args.shards(2).is_err());
+        assert_eq!(args.limit().unwrap(), ShardLimit::bytes(64 << 20));
+        assert!(args.required("output").is_err());
+
+        assert!(Args::parse(&strings(&["--key"])).is_err());
+        assert!(Args::parse(&strings(&["cat"])).unwrap().limit().is_err());
+    }
+
+    #[test]
+    fn test_cat_missing_key() {
+        let file = NamedTempFile::new().unwrap();
+        let mut writer = ShardWriter::new(file.path()).unwrap();
+        writer.append_with_key("present", b"data").unwrap();
+        writer.finish().unwrap();
+
+        let path = file.path().to_str().unwrap();
+        let err = run(&strings(&["cat", "--key", "missing", path])).unwrap_err();
+        assert!(err.to_string().contains("key not found"));
+    }
+}
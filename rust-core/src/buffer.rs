@@ -1,5 +1,7 @@
 use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
 use std::slice;
+use std::sync::Mutex;
 
 /// ゼロコピーバッファ：mmapされたメモリ領域への型安全なアクセス
 pub struct ZeroCopyBuffer<'a> {
@@ -24,42 +26,41 @@ impl<'a> ZeroCopyBuffer<'a> {
 
     /// u16のスライスとして取得（リトルエンディアン）
     pub fn as_u16(&self) -> Result<&'a [u16], BufferError> {
-        if self.data.len() % 2 != 0 {
-            return Err(BufferError::InvalidAlignment);
-        }
-        Ok(unsafe { slice::from_raw_parts(self.data.as_ptr() as *const u16, self.data.len() / 2) })
+        self.cast::<u16>()
     }
 
     /// u32のスライスとして取得（リトルエンディアン）
     pub fn as_u32(&self) -> Result<&'a [u32], BufferError> {
-        if self.data.len() % 4 != 0 {
-            return Err(BufferError::InvalidAlignment);
-        }
-        Ok(unsafe { slice::from_raw_parts(self.data.as_ptr() as *const u32, self.data.len() / 4) })
+        self.cast::<u32>()
     }
 
     /// u64のスライスとして取得（リトルエンディアン）
     pub fn as_u64(&self) -> Result<&'a [u64], BufferError> {
-        if self.data.len() % 8 != 0 {
-            return Err(BufferError::InvalidAlignment);
-        }
-        Ok(unsafe { slice::from_raw_parts(self.data.as_ptr() as *const u64, self.data.len() / 8) })
+        self.cast::<u64>()
     }
 
     /// f32のスライスとして取得
     pub fn as_f32(&self) -> Result<&'a [f32], BufferError> {
-        if self.data.len() % 4 != 0 {
-            return Err(BufferError::InvalidAlignment);
-        }
-        Ok(unsafe { slice::from_raw_parts(self.data.as_ptr() as *const f32, self.data.len() / 4) })
+        self.cast::<f32>()
     }
 
     /// f64のスライスとして取得
     pub fn as_f64(&self) -> Result<&'a [f64], BufferError> {
-        if self.data.len() % 8 != 0 {
+        self.cast::<f64>()
+    }
+
+    /// 長さと先頭ポインタが `T` の境界に揃っているか確認してから型付きスライスに変換
+    fn cast<T>(&self) -> Result<&'a [T], BufferError> {
+        let size = std::mem::size_of::<T>();
+        if !self.data.len().is_multiple_of(size) || !self.is_aligned_to(std::mem::align_of::<T>()) {
             return Err(BufferError::InvalidAlignment);
         }
-        Ok(unsafe { slice::from_raw_parts(self.data.as_ptr() as *const f64, self.data.len() / 8) })
+        Ok(unsafe { slice::from_raw_parts(self.data.as_ptr() as *const T, self.data.len() / size) })
+    }
+
+    /// 先頭ポインタが指定された境界に揃っているかどうか
+    pub fn is_aligned_to(&self, alignment: usize) -> bool {
+        (self.data.as_ptr() as usize).is_multiple_of(alignment)
     }
 
     /// バッファのサイズを取得
@@ -127,6 +128,103 @@ impl<'a, T> TypedBuffer<'a, T> {
     }
 }
 
+/// 再利用可能なバイトバッファのプール（展開用の一時バッファなど）
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_pooled: usize,
+}
+
+impl BufferPool {
+    /// 最大 `max_pooled` 個のバッファを保持するプールを作成
+    pub fn new(max_pooled: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            max_pooled,
+        }
+    }
+
+    /// バッファを取得（空きがなければ新しく確保）
+    pub fn acquire(&self) -> PooledBuffer<'_> {
+        let buf = self
+            .buffers
+            .lock()
+            .map(|mut buffers| buffers.pop())
+            .unwrap_or(None)
+            .unwrap_or_default();
+        PooledBuffer { buf, pool: self }
+    }
+
+    /// プールに戻されているバッファの数
+    pub fn pooled(&self) -> usize {
+        self.buffers.lock().map(|buffers| buffers.len()).unwrap_or(0)
+    }
+
+    fn release(&self, mut buf: Vec<u8>) {
+        if let Ok(mut buffers) = self.buffers.lock() {
+            if buffers.len() < self.max_pooled {
+                buf.clear();
+                buffers.push(buf);
+            }
+        }
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+/// プールから借りたバッファ（ドロップ時にプールへ戻る）
+pub struct PooledBuffer<'a> {
+    buf: Vec<u8>,
+    pool: &'a BufferPool,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.release(std::mem::take(&mut self.buf));
+    }
+}
+
+/// 展開済みのサンプル：無圧縮ならmmapへの参照、圧縮されていればプールのバッファ
+pub enum DecodedSample<'a> {
+    Borrowed(&'a [u8]),
+    Pooled(PooledBuffer<'a>),
+}
+
+impl DecodedSample<'_> {
+    /// ゼロコピー（mmapへの参照）かどうか
+    pub fn is_zero_copy(&self) -> bool {
+        matches!(self, DecodedSample::Borrowed(_))
+    }
+}
+
+impl Deref for DecodedSample<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            DecodedSample::Borrowed(data) => data,
+            DecodedSample::Pooled(buf) => buf.as_slice(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,5 +257,29 @@ mod tests {
         let data = vec![1u8, 2, 3]; // 3 bytes - not aligned for u16
         let buffer = ZeroCopyBuffer::from_slice(&data);
         assert_eq!(buffer.as_u16(), Err(BufferError::InvalidAlignment));
+
+        // 先頭ポインタがずれたスライスは型付きスライスにできない
+        let words = [0u64; 3];
+        let bytes = unsafe { slice::from_raw_parts(words.as_ptr() as *const u8, 24) };
+        let buffer = ZeroCopyBuffer::from_slice(&bytes[4..20]);
+        assert_eq!(buffer.as_u32().unwrap().len(), 4);
+        assert_eq!(buffer.as_u64(), Err(BufferError::InvalidAlignment));
+        assert_eq!(buffer.as_f64(), Err(BufferError::InvalidAlignment));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::new(1);
+        let mut first = pool.acquire();
+        first.extend_from_slice(b"decoded");
+        let second = pool.acquire();
+        drop(first);
+        drop(second);
+        // 上限を超えたバッファは破棄される
+        assert_eq!(pool.pooled(), 1);
+        let reused = pool.acquire();
+        assert!(reused.is_empty());
+        assert!(reused.capacity() >= 7);
+        assert_eq!(pool.pooled(), 0);
+    }
+}
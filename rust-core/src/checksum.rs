@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// チェックサムのアルゴリズム
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChecksumKind {
+    /// チェックサムなし
+    #[default]
+    None,
+    /// CRC32C（Castagnoli）
+    Crc32c,
+    /// xxHash64（シード0）
+    XxHash64,
+}
+
+impl ChecksumKind {
+    /// ヘッダーに格納するタグ
+    pub fn to_u8(self) -> u8 {
+        match self {
+            ChecksumKind::None => 0,
+            ChecksumKind::Crc32c => 1,
+            ChecksumKind::XxHash64 => 2,
+        }
+    }
+
+    /// ヘッダーのタグから復元
+    pub fn from_u8(tag: u8) -> io::Result<Self> {
+        match tag {
+            0 => Ok(ChecksumKind::None),
+            1 => Ok(ChecksumKind::Crc32c),
+            2 => Ok(ChecksumKind::XxHash64),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown checksum kind: {}", tag),
+            )),
+        }
+    }
+
+    /// チェックサムが有効かどうか
+    pub fn is_enabled(self) -> bool {
+        self != ChecksumKind::None
+    }
+
+    /// データのチェックサムを計算（CRC32Cは下位32ビットに格納）
+    pub fn compute(self, data: &[u8]) -> u64 {
+        match self {
+            ChecksumKind::None => 0,
+            ChecksumKind::Crc32c => crc32c::crc32c(data) as u64,
+            ChecksumKind::XxHash64 => xxhash_rust::xxh64::xxh64(data, 0),
+        }
+    }
+}
+
+/// 検証のタイミング
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum VerifyMode {
+    /// 検証しない
+    #[default]
+    Off,
+    /// シャードを開いたときにヘッダー・インデックス・全サンプルを検証
+    OnOpen,
+    /// 開いたときにヘッダーとインデックスを、読み込みのたびにサンプルを検証
+    OnRead,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_kinds() {
+        let data = b"123456789";
+        // CRC32Cの標準チェック値
+        assert_eq!(ChecksumKind::Crc32c.compute(data), 0xE3069283);
+        assert_eq!(ChecksumKind::None.compute(data), 0);
+        assert_ne!(ChecksumKind::XxHash64.compute(data), ChecksumKind::XxHash64.compute(b"12345678"));
+
+        for kind in [ChecksumKind::None, ChecksumKind::Crc32c, ChecksumKind::XxHash64] {
+            assert_eq!(ChecksumKind::from_u8(kind.to_u8()).unwrap(), kind);
+        }
+        assert!(ChecksumKind::from_u8(9).is_err());
+    }
+}
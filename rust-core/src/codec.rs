@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// 展開後のサイズの上限（インデックスの値を信用して確保しすぎないため）
+pub const MAX_DECODED_SIZE: usize = u32::MAX as usize;
+
+/// サンプルの圧縮コーデック
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Codec {
+    /// 無圧縮（ゼロコピーで読み出せる）
+    #[default]
+    None,
+    /// Zstandard
+    Zstd,
+    /// LZ4（ブロック形式）
+    Lz4,
+}
+
+impl Codec {
+    /// インデックスに格納するタグ
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Zstd => 1,
+            Codec::Lz4 => 2,
+        }
+    }
+
+    /// インデックスのタグから復元
+    pub fn from_u8(tag: u8) -> io::Result<Self> {
+        match tag {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Zstd),
+            2 => Ok(Codec::Lz4),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown codec: {}", tag),
+            )),
+        }
+    }
+
+    /// このビルドでコーデックが使えるかどうか
+    pub fn is_available(self) -> bool {
+        match self {
+            Codec::None => true,
+            Codec::Zstd => cfg!(feature = "zstd"),
+            Codec::Lz4 => cfg!(feature = "lz4"),
+        }
+    }
+
+    /// 圧縮率の上限（これを超える展開後サイズは壊れたインデックスとみなす）
+    pub fn max_ratio(self) -> usize {
+        match self {
+            Codec::None => 1,
+            // RLEブロックは数バイトで128KiBに展開される
+            Codec::Zstd => 1 << 15,
+            // LZ4ブロックの理論上の最大圧縮率は約255倍
+            Codec::Lz4 => 255,
+        }
+    }
+
+    /// `data` を圧縮して `out` に書き込む（`out` は上書きされる）
+    pub fn compress(self, data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        out.clear();
+        match self {
+            Codec::None => {
+                out.extend_from_slice(data);
+                Ok(())
+            }
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => {
+                *out = zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+                Ok(())
+            }
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => {
+                *out = lz4_flex::block::compress(data);
+                Ok(())
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    /// `data` を展開して `out` に書き込む（`out` は `decoded_size` バイトになる）
+    ///
+    /// `decoded_size` が上限や `data` の圧縮率の上限を超える場合は確保せずにエラーを返す。
+    pub fn decompress(self, data: &[u8], decoded_size: usize, out: &mut Vec<u8>) -> io::Result<()> {
+        let limit = data.len().saturating_mul(self.max_ratio()).min(MAX_DECODED_SIZE);
+        if decoded_size > limit {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Decoded size {} exceeds the limit of {} bytes for {} stored bytes",
+                    decoded_size,
+                    limit,
+                    data.len()
+                ),
+            ));
+        }
+        out.clear();
+        out.resize(decoded_size, 0);
+        let written = match self {
+            Codec::None => {
+                if data.len() != decoded_size {
+                    return Err(size_mismatch(decoded_size, data.len()));
+                }
+                out.copy_from_slice(data);
+                decoded_size
+            }
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => zstd::bulk::decompress_to_buffer(data, out.as_mut_slice())?,
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => lz4_flex::block::decompress_into(data, out)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+            #[allow(unreachable_patterns)]
+            _ => return Err(self.unsupported()),
+        };
+        if written != decoded_size {
+            return Err(size_mismatch(decoded_size, written));
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)] // すべてのコーデックが有効なビルドでは使われない
+    fn unsupported(self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Codec {:?} is not enabled in this build", self),
+        )
+    }
+}
+
+fn size_mismatch(expected: usize, actual: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Decoded size mismatch: expected {} bytes, got {}", expected, actual),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codec_roundtrip() {
+        let data = b"abcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabc".repeat(16);
+        for codec in [Codec::None, Codec::Zstd, Codec::Lz4] {
+            if !codec.is_available() {
+                continue;
+            }
+            let mut compressed = Vec::new();
+            codec.compress(&data, &mut compressed).unwrap();
+            if codec != Codec::None {
+                assert!(compressed.len() < data.len());
+            }
+
+            let mut decoded = Vec::new();
+            codec.decompress(&compressed, data.len(), &mut decoded).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(Codec::from_u8(codec.to_u8()).unwrap(), codec);
+        }
+    }
+
+    #[test]
+    fn test_decoded_size_mismatch() {
+        let mut decoded = Vec::new();
+        assert!(Codec::None.decompress(b"abc", 4, &mut decoded).is_err());
+    }
+
+    #[test]
+    fn test_decoded_size_limit() {
+        let mut decoded = Vec::new();
+        for codec in [Codec::Zstd, Codec::Lz4] {
+            let err = codec.decompress(b"abcd", usize::MAX, &mut decoded).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            let err = codec.decompress(b"abcd", 4 * codec.max_ratio() + 1, &mut decoded).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(decoded.capacity(), 0);
+        }
+    }
+}
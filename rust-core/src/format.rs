@@ -1,39 +1,142 @@
+use crate::checksum::ChecksumKind;
+use crate::codec::Codec;
+use crate::schema::Schema;
 use serde::{Deserialize, Serialize};
-use std::io::{self, Read, Write};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 /// マジックナンバー: "ZCLD" (Zero Copy Loader)
 pub const MAGIC: u32 = 0x5A434C44;
 
 /// フォーマットバージョン
-pub const FORMAT_VERSION: u16 = 1;
+pub const FORMAT_VERSION: u16 = 2;
+
+/// JSONメタデータにインデックスを持つ旧フォーマットのバージョン
+pub const FORMAT_VERSION_V1: u16 = 1;
+
+/// バイナリインデックスの1エントリあたりの基本サイズ（offset: u64, size: u64）
+pub const INDEX_ENTRY_SIZE: u32 = 8 + 8;
+
+/// フラグ: インデックスの各エントリがサンプルのチェックサム（u64）を持つ
+pub const FLAG_SAMPLE_CHECKSUMS: u16 = 1 << 0;
+
+/// フラグ: インデックスの各エントリがコーデック（u8 + 7バイトのパディング）と展開後のサイズ（u64）を持つ
+pub const FLAG_COMPRESSION: u16 = 1 << 1;
+
+/// フラグ: インデックスの後にサンプルキーのテーブルを持つ
+pub const FLAG_KEYS: u16 = 1 << 2;
+
+/// フラグ: インデックスの各エントリが名前付きフィールドごとの (offset, size) を持つ
+///
+/// オフセットは（展開後の）サンプル先頭からの相対位置で、欠けているフィールドは
+/// オフセットが `u64::MAX` になる。フィールド名はメタデータの `fields` に並ぶ。
+pub const FLAG_FIELDS: u16 = 1 << 3;
+
+/// 欠けているフィールドを表すオフセット
+const ABSENT_FIELD: u64 = u64::MAX;
 
 /// シャードファイルのヘッダー
+///
+/// v2のレイアウト: `[ヘッダー][データ][インデックス][キーテーブル][メタデータ]`
+/// （キーテーブルは `FLAG_KEYS` のときのみ）。
+/// インデックスは (offset, size) のリトルエンディアン固定長レコードの配列で、
+/// mmapから直接読み出せる。v1ではメタデータ（JSON）がデータより前に置かれ、
+/// `index_offset` / `num_samples` / `index_entry_size` は0になる。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ShardHeader {
     pub magic: u32,
     pub version: u16,
+    pub flags: u16,
     pub metadata_offset: u64,
     pub data_offset: u64,
+    pub index_offset: u64,
+    pub num_samples: u64,
+    pub index_entry_size: u32,
+    pub checksum_kind: ChecksumKind,
+    /// インデックスとメタデータ（`index_offset` からファイル末尾まで）のチェックサム
+    pub index_checksum: u64,
+    /// このフィールドを0としてエンコードしたヘッダーのチェックサム
+    pub header_checksum: u64,
+    /// キーテーブルの位置（`FLAG_KEYS` のときのみ有効）
+    pub key_table_offset: u64,
+    /// サンプルあたりのフィールド数（`FLAG_FIELDS` のときのみ有効）
+    pub num_fields: u32,
+    /// データセクションと各サンプル・フィールドの先頭のアライメント（バイト、0は指定なし）
+    pub alignment: u32,
 }
 
 impl ShardHeader {
-    pub const SIZE: usize = 4 + 2 + 8 + 8; // 22 bytes
+    /// v2ヘッダーのサイズ（将来の拡張用の予約領域を含む）
+    pub const SIZE: usize = 128;
+
+    /// v1ヘッダーのサイズ
+    pub const V1_SIZE: usize = 4 + 2 + 8 + 8; // 22 bytes
+
+    /// 予約領域を除いたv2ヘッダーのサイズ
+    const V2_USED: usize = 4 + 2 + 2 + 8 + 8 + 8 + 8 + 4 + 1 + 3 + 8 + 8 + 8 + 4 + 4;
 
     pub fn new(metadata_offset: u64, data_offset: u64) -> Self {
         Self {
             magic: MAGIC,
             version: FORMAT_VERSION,
+            flags: 0,
             metadata_offset,
             data_offset,
+            index_offset: 0,
+            num_samples: 0,
+            index_entry_size: INDEX_ENTRY_SIZE,
+            checksum_kind: ChecksumKind::None,
+            index_checksum: 0,
+            header_checksum: 0,
+            key_table_offset: 0,
+            num_fields: 0,
+            alignment: 1,
+        }
+    }
+
+    /// v1形式のヘッダーを作成（後方互換性のテスト用）
+    pub fn v1(metadata_offset: u64, data_offset: u64) -> Self {
+        Self {
+            version: FORMAT_VERSION_V1,
+            index_entry_size: 0,
+            alignment: 0,
+            ..Self::new(metadata_offset, data_offset)
+        }
+    }
+
+    /// バージョンに応じたヘッダーのサイズ
+    pub fn encoded_size(&self) -> usize {
+        if self.version == FORMAT_VERSION_V1 {
+            Self::V1_SIZE
+        } else {
+            Self::SIZE
         }
     }
 
     pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         writer.write_u32::<LittleEndian>(self.magic)?;
         writer.write_u16::<LittleEndian>(self.version)?;
+        if self.version == FORMAT_VERSION_V1 {
+            writer.write_u64::<LittleEndian>(self.metadata_offset)?;
+            writer.write_u64::<LittleEndian>(self.data_offset)?;
+            return Ok(());
+        }
+        writer.write_u16::<LittleEndian>(self.flags)?;
         writer.write_u64::<LittleEndian>(self.metadata_offset)?;
         writer.write_u64::<LittleEndian>(self.data_offset)?;
+        writer.write_u64::<LittleEndian>(self.index_offset)?;
+        writer.write_u64::<LittleEndian>(self.num_samples)?;
+        writer.write_u32::<LittleEndian>(self.index_entry_size)?;
+        writer.write_u8(self.checksum_kind.to_u8())?;
+        writer.write_all(&[0u8; 3])?;
+        writer.write_u64::<LittleEndian>(self.index_checksum)?;
+        writer.write_u64::<LittleEndian>(self.header_checksum)?;
+        writer.write_u64::<LittleEndian>(self.key_table_offset)?;
+        writer.write_u32::<LittleEndian>(self.num_fields)?;
+        writer.write_u32::<LittleEndian>(self.alignment)?;
+        writer.write_all(&[0u8; Self::SIZE - Self::V2_USED])?;
         Ok(())
     }
 
@@ -46,54 +149,465 @@ impl ShardHeader {
             ));
         }
         let version = reader.read_u16::<LittleEndian>()?;
-        if version != FORMAT_VERSION {
-            return Err(io::Error::new(
+        match version {
+            FORMAT_VERSION_V1 => {
+                let metadata_offset = reader.read_u64::<LittleEndian>()?;
+                let data_offset = reader.read_u64::<LittleEndian>()?;
+                Ok(Self::v1(metadata_offset, data_offset))
+            }
+            FORMAT_VERSION => {
+                let flags = reader.read_u16::<LittleEndian>()?;
+                let metadata_offset = reader.read_u64::<LittleEndian>()?;
+                let data_offset = reader.read_u64::<LittleEndian>()?;
+                let index_offset = reader.read_u64::<LittleEndian>()?;
+                let num_samples = reader.read_u64::<LittleEndian>()?;
+                let index_entry_size = reader.read_u32::<LittleEndian>()?;
+                let checksum_kind = ChecksumKind::from_u8(reader.read_u8()?)?;
+                let mut padding = [0u8; 3];
+                reader.read_exact(&mut padding)?;
+                let index_checksum = reader.read_u64::<LittleEndian>()?;
+                let header_checksum = reader.read_u64::<LittleEndian>()?;
+                let key_table_offset = reader.read_u64::<LittleEndian>()?;
+                let num_fields = reader.read_u32::<LittleEndian>()?;
+                let alignment = reader.read_u32::<LittleEndian>()?;
+                let mut reserved = [0u8; Self::SIZE - Self::V2_USED];
+                reader.read_exact(&mut reserved)?;
+                Ok(Self {
+                    magic,
+                    version,
+                    flags,
+                    metadata_offset,
+                    data_offset,
+                    index_offset,
+                    num_samples,
+                    index_entry_size,
+                    checksum_kind,
+                    index_checksum,
+                    header_checksum,
+                    key_table_offset,
+                    num_fields,
+                    alignment,
+                })
+            }
+            _ => Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 format!("Unsupported format version: {}", version),
-            ));
+            )),
+        }
+    }
+
+    /// `header_checksum` を0としてエンコードしたヘッダーのチェックサムを計算
+    pub fn compute_checksum(&self) -> u64 {
+        let mut buf = Vec::with_capacity(Self::SIZE);
+        let header = Self {
+            header_checksum: 0,
+            ..self.clone()
+        };
+        // Vecへの書き込みは失敗しない
+        let _ = header.write(&mut buf);
+        self.checksum_kind.compute(&buf)
+    }
+
+    /// キーテーブルの範囲（`FLAG_KEYS` がなければ `None`）
+    pub fn key_table_range(&self) -> Option<(u64, u64)> {
+        (self.flags & FLAG_KEYS != 0).then_some((self.key_table_offset, self.metadata_offset))
+    }
+
+    /// インデックスセクションのサイズ（バイト）
+    pub fn index_size(&self) -> Option<u64> {
+        self.num_samples.checked_mul(self.index_entry_size as u64)
+    }
+
+    /// インデックスエントリのレイアウトを取得
+    pub fn index_layout(&self) -> IndexLayout {
+        IndexLayout {
+            flags: self.flags,
+            num_fields: self.field_count(),
+            entry_size: self.index_entry_size as usize,
+        }
+    }
+
+    /// データのアライメント（指定がなければ1）
+    pub fn data_alignment(&self) -> u64 {
+        self.alignment.max(1) as u64
+    }
+
+    /// インデックスに格納されたフィールド数（`FLAG_FIELDS` がなければ0）
+    pub fn field_count(&self) -> usize {
+        if self.flags & FLAG_FIELDS != 0 {
+            self.num_fields as usize
+        } else {
+            0
         }
-        let metadata_offset = reader.read_u64::<LittleEndian>()?;
-        let data_offset = reader.read_u64::<LittleEndian>()?;
-        Ok(Self {
-            magic,
-            version,
-            metadata_offset,
-            data_offset,
-        })
     }
 
     pub fn validate(&self) -> Result<(), String> {
         if self.magic != MAGIC {
             return Err(format!("Invalid magic number: 0x{:08X}", self.magic));
         }
-        if self.version != FORMAT_VERSION {
-            return Err(format!("Unsupported version: {}", self.version));
-        }
-        if self.metadata_offset >= self.data_offset {
-            return Err("Invalid offset order".to_string());
+        match self.version {
+            FORMAT_VERSION_V1 => {
+                if self.metadata_offset >= self.data_offset {
+                    return Err("Invalid offset order".to_string());
+                }
+            }
+            FORMAT_VERSION => {
+                if self.data_offset < Self::SIZE as u64 || self.index_offset < self.data_offset {
+                    return Err("Invalid offset order".to_string());
+                }
+                if (self.index_entry_size as usize) < IndexLayout::min_entry_size(self.flags, self.field_count()) {
+                    return Err(format!("Invalid index entry size: {}", self.index_entry_size));
+                }
+                if !self.data_alignment().is_power_of_two()
+                    || !self.data_offset.is_multiple_of(self.data_alignment())
+                {
+                    return Err(format!("Invalid data alignment: {}", self.alignment));
+                }
+                if (self.flags & FLAG_SAMPLE_CHECKSUMS != 0) && !self.checksum_kind.is_enabled() {
+                    return Err("Sample checksums present without a checksum kind".to_string());
+                }
+                let index_end = self
+                    .index_size()
+                    .and_then(|size| self.index_offset.checked_add(size))
+                    .ok_or_else(|| "Index size overflow".to_string())?;
+                if index_end > self.metadata_offset {
+                    return Err("Index overlaps metadata".to_string());
+                }
+                if self.flags & FLAG_KEYS != 0
+                    && (self.key_table_offset < index_end || self.key_table_offset > self.metadata_offset)
+                {
+                    return Err("Invalid key table offset".to_string());
+                }
+            }
+            _ => return Err(format!("Unsupported version: {}", self.version)),
         }
         Ok(())
     }
 }
 
 /// サンプルのメタデータ（インデックス内のエントリ）
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SampleMetadata {
     pub offset: u64,  // データセクション内のオフセット
     pub size: u64,    // サンプルのサイズ（バイト）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u64>, // 格納されたバイト列のチェックサム（ヘッダーのchecksum_kindで計算）
+    #[serde(default, skip_serializing_if = "is_uncompressed")]
+    pub codec: Codec, // サンプルの圧縮コーデック
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uncompressed_size: Option<u64>, // 展開後のサイズ（圧縮されている場合のみ）
+}
+
+fn is_uncompressed(codec: &Codec) -> bool {
+    *codec == Codec::None
+}
+
+impl SampleMetadata {
+    /// 無圧縮・チェックサムなしのエントリを作成
+    pub fn new(offset: u64, size: u64) -> Self {
+        Self {
+            offset,
+            size,
+            checksum: None,
+            codec: Codec::None,
+            uncompressed_size: None,
+        }
+    }
+
+    /// 展開後のサイズを取得
+    pub fn decoded_size(&self) -> u64 {
+        self.uncompressed_size.unwrap_or(self.size)
+    }
+}
+
+/// バイナリインデックスのエントリのレイアウト
+///
+/// 基本の (offset, size) に続いて、ヘッダーのフラグで有効になった列が
+/// チェックサム・圧縮・フィールドの順に並ぶ。
+/// `entry_size` は基本列と有効な列の合計以上で、後方互換のために余りは無視する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexLayout {
+    pub flags: u16,
+    pub num_fields: usize,
+    pub entry_size: usize,
+}
+
+impl IndexLayout {
+    /// フラグから最小のレイアウトを作成
+    pub fn for_flags(flags: u16) -> Self {
+        Self::with_fields(flags, 0)
+    }
+
+    /// フラグとフィールド数から最小のレイアウトを作成
+    pub fn with_fields(flags: u16, num_fields: usize) -> Self {
+        Self {
+            flags,
+            num_fields,
+            entry_size: Self::min_entry_size(flags, num_fields),
+        }
+    }
+
+    /// フラグで有効な列を格納するのに必要なエントリサイズ
+    pub fn min_entry_size(flags: u16, num_fields: usize) -> usize {
+        let mut size = INDEX_ENTRY_SIZE as usize;
+        if flags & FLAG_SAMPLE_CHECKSUMS != 0 {
+            size += 8;
+        }
+        if flags & FLAG_COMPRESSION != 0 {
+            size += 16;
+        }
+        if flags & FLAG_FIELDS != 0 {
+            size += num_fields * 16;
+        }
+        size
+    }
+
+    fn checksum_offset(&self) -> Option<usize> {
+        (self.flags & FLAG_SAMPLE_CHECKSUMS != 0).then_some(INDEX_ENTRY_SIZE as usize)
+    }
+
+    fn compression_offset(&self) -> Option<usize> {
+        let offset = Self::min_entry_size(self.flags & FLAG_SAMPLE_CHECKSUMS, 0);
+        (self.flags & FLAG_COMPRESSION != 0).then_some(offset)
+    }
+
+    fn field_offset(&self, field: usize) -> Option<usize> {
+        if self.flags & FLAG_FIELDS == 0 || field >= self.num_fields {
+            return None;
+        }
+        let base = Self::min_entry_size(self.flags & (FLAG_SAMPLE_CHECKSUMS | FLAG_COMPRESSION), 0);
+        Some(base + field * 16)
+    }
+
+    /// バイナリインデックスのエントリとして書き込む
+    pub fn write_entry<W: Write>(&self, sample: &SampleMetadata, writer: &mut W) -> io::Result<()> {
+        self.write_entry_with_fields(sample, &[], writer)
+    }
+
+    /// フィールドの範囲（サンプル先頭からの (offset, size)、欠けていれば `None`）付きで書き込む
+    pub fn write_entry_with_fields<W: Write>(
+        &self,
+        sample: &SampleMetadata,
+        fields: &[Option<(u64, u64)>],
+        writer: &mut W,
+    ) -> io::Result<()> {
+        let mut entry = vec![0u8; self.entry_size];
+        entry[0..8].copy_from_slice(&sample.offset.to_le_bytes());
+        entry[8..16].copy_from_slice(&sample.size.to_le_bytes());
+        if let Some(at) = self.checksum_offset() {
+            entry[at..at + 8].copy_from_slice(&sample.checksum.unwrap_or(0).to_le_bytes());
+        }
+        if let Some(at) = self.compression_offset() {
+            entry[at] = sample.codec.to_u8();
+            entry[at + 8..at + 16].copy_from_slice(&sample.decoded_size().to_le_bytes());
+        }
+        for field in 0..self.num_fields {
+            if let Some(at) = self.field_offset(field) {
+                let (offset, size) = fields.get(field).copied().flatten().unwrap_or((ABSENT_FIELD, 0));
+                entry[at..at + 8].copy_from_slice(&offset.to_le_bytes());
+                entry[at + 8..at + 16].copy_from_slice(&size.to_le_bytes());
+            }
+        }
+        writer.write_all(&entry)
+    }
+
+    /// エントリからフィールドの範囲を読み出す（欠けていれば `None`）
+    pub fn read_field(&self, entry: &[u8], field: usize) -> Option<(u64, u64)> {
+        let at = self.field_offset(field)?;
+        let offset = read_u64_le(&entry[at..at + 8]);
+        if offset == ABSENT_FIELD {
+            return None;
+        }
+        Some((offset, read_u64_le(&entry[at + 8..at + 16])))
+    }
+
+    /// バイナリインデックスのエントリから読み出す（`entry` は `entry_size` バイト）
+    pub fn read_entry(&self, entry: &[u8]) -> io::Result<SampleMetadata> {
+        let mut sample = SampleMetadata::new(read_u64_le(&entry[0..8]), read_u64_le(&entry[8..16]));
+        sample.checksum = self.checksum_offset().map(|at| read_u64_le(&entry[at..at + 8]));
+        if let Some(at) = self.compression_offset() {
+            sample.codec = Codec::from_u8(entry[at])?;
+            if sample.codec != Codec::None {
+                sample.uncompressed_size = Some(read_u64_le(&entry[at + 8..at + 16]));
+            }
+        }
+        Ok(sample)
+    }
+}
+
+fn read_u64_le(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+/// バイナリインデックスへのゼロコピービュー
+///
+/// mmapされた領域をそのまま参照し、エントリはアクセス時にデコードする。
+#[derive(Debug, Clone, Copy)]
+pub struct PackedIndex<'a> {
+    bytes: &'a [u8],
+    layout: IndexLayout,
+}
+
+impl<'a> PackedIndex<'a> {
+    pub fn new(bytes: &'a [u8], layout: IndexLayout) -> io::Result<Self> {
+        if layout.entry_size < IndexLayout::min_entry_size(layout.flags, layout.num_fields)
+            || !bytes.len().is_multiple_of(layout.entry_size)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid index layout: len={}, entry_size={}", bytes.len(), layout.entry_size),
+            ));
+        }
+        Ok(Self { bytes, layout })
+    }
+
+    /// エントリ数を取得
+    pub fn len(&self) -> usize {
+        self.bytes.len() / self.layout.entry_size
+    }
+
+    /// 空かどうか
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// 指定されたインデックスのエントリを取得（範囲外なら `None`）
+    pub fn get(&self, index: usize) -> Option<io::Result<SampleMetadata>> {
+        Some(self.layout.read_entry(self.entry(index)?))
+    }
+
+    /// 指定されたサンプルのフィールドの範囲を取得（範囲外や欠けていれば `None`）
+    pub fn field(&self, index: usize, field: usize) -> Option<(u64, u64)> {
+        self.layout.read_field(self.entry(index)?, field)
+    }
+
+    fn entry(&self, index: usize) -> Option<&'a [u8]> {
+        let start = index.checked_mul(self.layout.entry_size)?;
+        self.bytes.get(start..start + self.layout.entry_size)
+    }
+}
+
+/// サンプルキーのテーブルへのゼロコピービュー
+///
+/// レイアウト（すべてリトルエンディアン）:
+/// `[キー付きサンプル数: u64][キーのオフセット: (num_samples + 1) × u64]`
+/// `[キー順のサンプル番号: キー付きサンプル数 × u64][キーのバイト列]`。
+/// 空のキーは「キーなし」を表し、検索対象に含まれない。
+#[derive(Debug, Clone, Copy)]
+pub struct KeyTable<'a> {
+    offsets: &'a [u8],
+    sorted: &'a [u8],
+    blob: &'a [u8],
+}
+
+impl<'a> KeyTable<'a> {
+    /// キーテーブルのバイト列を解釈する
+    pub fn new(bytes: &'a [u8], num_samples: usize) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid key table: {}", msg));
+        if bytes.len() < 8 {
+            return Err(invalid("truncated"));
+        }
+        let num_keyed = read_u64_le(&bytes[0..8]) as usize;
+        if num_keyed > num_samples {
+            return Err(invalid("too many keys"));
+        }
+        let offsets_end = 8 + (num_samples + 1) * 8;
+        let sorted_end = offsets_end + num_keyed * 8;
+        if bytes.len() < sorted_end {
+            return Err(invalid("truncated"));
+        }
+        let table = Self {
+            offsets: &bytes[8..offsets_end],
+            sorted: &bytes[offsets_end..sorted_end],
+            blob: &bytes[sorted_end..],
+        };
+        if read_u64_le(&table.offsets[num_samples * 8..]) as usize > table.blob.len() {
+            return Err(invalid("key bytes out of range"));
+        }
+        Ok(table)
+    }
+
+    /// キーテーブルをエンコードする（`keys` はサンプル順、空文字列はキーなし）
+    pub fn encode(keys: &[String]) -> io::Result<Vec<u8>> {
+        let mut sorted: Vec<usize> = (0..keys.len()).filter(|&i| !keys[i].is_empty()).collect();
+        sorted.sort_unstable_by(|&a, &b| keys[a].as_bytes().cmp(keys[b].as_bytes()));
+        if let Some(pair) = sorted.windows(2).find(|pair| keys[pair[0]] == keys[pair[1]]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Duplicate sample key: {}", keys[pair[0]]),
+            ));
+        }
+
+        let blob_len: usize = keys.iter().map(|key| key.len()).sum();
+        let mut buf = Vec::with_capacity(8 + (keys.len() + 1 + sorted.len()) * 8 + blob_len);
+        buf.write_u64::<LittleEndian>(sorted.len() as u64)?;
+        let mut offset = 0u64;
+        buf.write_u64::<LittleEndian>(offset)?;
+        for key in keys {
+            offset += key.len() as u64;
+            buf.write_u64::<LittleEndian>(offset)?;
+        }
+        for &index in &sorted {
+            buf.write_u64::<LittleEndian>(index as u64)?;
+        }
+        for key in keys {
+            buf.write_all(key.as_bytes())?;
+        }
+        Ok(buf)
+    }
+
+    /// 指定されたサンプルのキーを取得（キーなしなら `None`）
+    pub fn key(&self, index: usize) -> Option<&'a str> {
+        let start = read_u64_le(self.offsets.get(index * 8..index * 8 + 8)?) as usize;
+        let end = read_u64_le(self.offsets.get(index * 8 + 8..index * 8 + 16)?) as usize;
+        let key = self.blob.get(start..end)?;
+        if key.is_empty() {
+            return None;
+        }
+        std::str::from_utf8(key).ok()
+    }
+
+    /// キーからサンプル番号を二分探索する
+    pub fn find(&self, key: &str) -> Option<usize> {
+        let num_keyed = self.sorted.len() / 8;
+        let (mut lo, mut hi) = (0, num_keyed);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let index = read_u64_le(&self.sorted[mid * 8..mid * 8 + 8]) as usize;
+            let candidate = self.key(index).unwrap_or("");
+            match candidate.as_bytes().cmp(key.as_bytes()) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(index),
+            }
+        }
+        None
+    }
 }
 
 /// シャードのメタデータ
+///
+/// v1ではサンプルごとのエントリをJSONで保持する。v2ではエントリはバイナリ
+/// インデックスに置かれ、`samples` は空になる。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ShardMetadata {
     pub num_samples: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub samples: Vec<SampleMetadata>,
+    /// サンプルのフィールド名（インデックスのフィールド列の順）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    /// サンプル（またはフィールド）の型スキーマ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Schema>,
 }
 
 impl ShardMetadata {
     pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         let json = serde_json::to_vec(self).map_err(|e| {
-            io::Error::new(io::ErrorKind::Other, format!("Serialization error: {}", e))
+            io::Error::other(format!("Serialization error: {}", e))
         })?;
         writer.write_u64::<LittleEndian>(json.len() as u64)?;
         writer.write_all(&json)?;
@@ -110,6 +624,368 @@ impl ShardMetadata {
     }
 }
 
+/// シャード書き出しの設定
+#[derive(Debug, Clone, Default)]
+pub struct WriterConfig {
+    /// ヘッダー・インデックス・各サンプルのチェックサム
+    pub checksum: ChecksumKind,
+    /// サンプルの圧縮コーデック（圧縮で小さくならないサンプルは無圧縮で格納）
+    pub codec: Codec,
+    /// サンプルのフィールド名（`append_fields` で使う名前をすべて宣言する）
+    pub fields: Vec<String>,
+    /// 型スキーマ（追加されるサンプルのサイズが形状に合うか検証される）
+    pub schema: Option<Schema>,
+    /// データセクションと各サンプル・フィールドの先頭を揃える境界（2の累乗、0は指定なし）
+    ///
+    /// 64バイトやページサイズを指定すると、mmap上の型付きビューが揃った位置から始まる。
+    pub alignment: u32,
+}
+
+/// シャードファイルを書き出すライター
+///
+/// `append` でサンプルをデータセクションへ順次書き出し、`finish` でインデックスと
+/// メタデータを書き込んでヘッダーを確定させる。
+pub struct ShardWriter {
+    out: BufWriter<File>,
+    config: WriterConfig,
+    samples: Vec<SampleMetadata>,
+    field_ranges: Vec<Option<(u64, u64)>>,
+    keys: Vec<String>,
+    data_offset: u64,
+    data_len: u64,
+    scratch: Vec<u8>,
+}
+
+impl ShardWriter {
+    /// 指定されたパスにシャードを作成（既存ファイルは上書き）
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::with_config(path, WriterConfig::default())
+    }
+
+    /// 設定を指定してシャードを作成
+    pub fn with_config<P: AsRef<Path>>(path: P, config: WriterConfig) -> io::Result<Self> {
+        if !config.codec.is_available() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Codec {:?} is not enabled in this build", config.codec),
+            ));
+        }
+        if let Some(schema) = &config.schema {
+            schema.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            for field in &schema.fields {
+                let declared = if config.fields.is_empty() {
+                    field.name.is_empty()
+                } else {
+                    config.fields.contains(&field.name)
+                };
+                if !declared {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Schema field '{}' does not match the declared fields", field.name),
+                    ));
+                }
+            }
+        }
+        if config.alignment > 1 && !config.alignment.is_power_of_two() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Alignment must be a power of two: {}", config.alignment),
+            ));
+        }
+        let mut out = BufWriter::new(File::create(path)?);
+
+        // ヘッダー用のスペースを確保（finishで更新）し、データセクションの先頭を揃える
+        let data_offset = align_up(ShardHeader::SIZE as u64, config.alignment);
+        out.write_all(&vec![0u8; data_offset as usize])?;
+
+        Ok(Self {
+            out,
+            config,
+            samples: Vec::new(),
+            field_ranges: Vec::new(),
+            keys: Vec::new(),
+            data_offset,
+            data_len: 0,
+            scratch: Vec::new(),
+        })
+    }
+
+    /// サンプルを追加し、シャード内のインデックスを返す
+    pub fn append(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.check_schema("", data)?;
+        self.field_ranges.extend(std::iter::repeat_n(None, self.config.fields.len()));
+        self.append_sample(data)
+    }
+
+    /// 名前付きフィールドからなるサンプルを追加し、シャード内のインデックスを返す
+    ///
+    /// フィールドは連結して1つのサンプルとして格納する。宣言されていないフィールド名は
+    /// エラーになり、渡されなかったフィールドは欠けているものとして記録される。
+    pub fn append_fields(&mut self, fields: &[(&str, &[u8])]) -> io::Result<usize> {
+        let mut ranges = vec![None; self.config.fields.len()];
+        let mut data = Vec::with_capacity(fields.iter().map(|(_, bytes)| bytes.len()).sum());
+        for (name, bytes) in fields {
+            let field = self
+                .config
+                .fields
+                .iter()
+                .position(|declared| declared == name)
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("Undeclared field: {}", name))
+                })?;
+            if ranges[field].is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Duplicate field: {}", name),
+                ));
+            }
+            self.check_schema(name, bytes)?;
+            data.resize(align_up(data.len() as u64, self.config.alignment) as usize, 0);
+            ranges[field] = Some((data.len() as u64, bytes.len() as u64));
+            data.extend_from_slice(bytes);
+        }
+        self.field_ranges.extend(ranges);
+        self.append_sample(&data)
+    }
+
+    /// キー付きで名前付きフィールドからなるサンプルを追加する
+    pub fn append_fields_with_key(&mut self, key: &str, fields: &[(&str, &[u8])]) -> io::Result<usize> {
+        if key.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Sample key must not be empty"));
+        }
+        let index = self.append_fields(fields)?;
+        self.record_key(index, key);
+        Ok(index)
+    }
+
+    /// キー付きでサンプルを追加し、シャード内のインデックスを返す
+    ///
+    /// キーはシャード内で一意でなければならない（重複は `finish` でエラーになる）。
+    pub fn append_with_key(&mut self, key: &str, data: &[u8]) -> io::Result<usize> {
+        if key.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Sample key must not be empty"));
+        }
+        let index = self.append(data)?;
+        self.record_key(index, key);
+        Ok(index)
+    }
+
+    fn check_schema(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        let field = self.config.schema.as_ref().and_then(|schema| schema.field(name));
+        if let Some(field) = field {
+            field
+                .resolve_shape(data.len())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        }
+        Ok(())
+    }
+
+    fn record_key(&mut self, index: usize, key: &str) {
+        // キーなしで追加されたサンプルは空のキーで埋める
+        self.keys.resize(index, String::new());
+        self.keys.push(key.to_string());
+    }
+
+    fn append_sample(&mut self, data: &[u8]) -> io::Result<usize> {
+        let padded = align_up(self.data_len, self.config.alignment);
+        self.out.write_all(&vec![0u8; (padded - self.data_len) as usize])?;
+        self.data_len = padded;
+
+        let mut sample = SampleMetadata::new(self.data_len, data.len() as u64);
+
+        let mut stored = data;
+        if self.config.codec != Codec::None {
+            self.config.codec.compress(data, &mut self.scratch)?;
+            if self.scratch.len() < data.len() {
+                stored = &self.scratch;
+                sample.size = stored.len() as u64;
+                sample.codec = self.config.codec;
+                sample.uncompressed_size = Some(data.len() as u64);
+            }
+        }
+
+        self.out.write_all(stored)?;
+        let checksum = self.config.checksum;
+        sample.checksum = checksum.is_enabled().then(|| checksum.compute(stored));
+        self.data_len += sample.size;
+
+        let index = self.samples.len();
+        self.samples.push(sample);
+        Ok(index)
+    }
+
+    /// 格納済みのバイト列をデータセクションへコピーし、その先頭のオフセットを返す
+    ///
+    /// Linuxでは `src` から `copy_file_range` でカーネル内コピーし、使えなければ
+    /// `bytes`（`src` の同じ範囲の内容）を書き込む。`COPY_FILE_RANGE_MIN` より小さい範囲は
+    /// 出力バッファのフラッシュを避けるため、そのままバッファに書き込む。
+    pub(crate) fn copy_data(&mut self, src: &File, src_offset: u64, bytes: &[u8]) -> io::Result<u64> {
+        let padded = align_up(self.data_len, self.config.alignment);
+        self.out.write_all(&vec![0u8; (padded - self.data_len) as usize])?;
+        self.data_len = padded;
+
+        let copied = if bytes.len() < COPY_FILE_RANGE_MIN {
+            0
+        } else {
+            copy_file_range(src, src_offset, &mut self.out, bytes.len() as u64)?
+        };
+        self.out.write_all(&bytes[copied as usize..])?;
+        self.data_len += bytes.len() as u64;
+        Ok(padded)
+    }
+
+    /// `copy_data` で書き込んだサンプルをインデックスに登録する
+    pub(crate) fn append_stored(
+        &mut self,
+        sample: SampleMetadata,
+        fields: &[Option<(u64, u64)>],
+        key: Option<&str>,
+    ) -> usize {
+        let index = self.samples.len();
+        self.samples.push(sample);
+        self.field_ranges.extend_from_slice(fields);
+        if let Some(key) = key {
+            self.record_key(index, key);
+        }
+        index
+    }
+
+    /// 追加済みのサンプル数を取得
+    pub fn num_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// サンプルのアライメントを取得（指定がなければ1）
+    pub fn alignment(&self) -> u32 {
+        self.config.alignment.max(1)
+    }
+
+    /// サンプルのチェックサムの種類を取得
+    pub fn checksum_kind(&self) -> ChecksumKind {
+        self.config.checksum
+    }
+
+    /// 追加済みのデータサイズを取得（バイト）
+    pub fn data_len(&self) -> u64 {
+        self.data_len
+    }
+
+    /// インデックスとメタデータを書き出し、ヘッダーを確定させる
+    pub fn finish(mut self) -> io::Result<ShardHeader> {
+        let checksum_kind = self.config.checksum;
+        let mut flags = 0;
+        if checksum_kind.is_enabled() {
+            flags |= FLAG_SAMPLE_CHECKSUMS;
+        }
+        if self.config.codec != Codec::None || self.samples.iter().any(|s| s.codec != Codec::None) {
+            flags |= FLAG_COMPRESSION;
+        }
+        if !self.keys.is_empty() {
+            flags |= FLAG_KEYS;
+        }
+        let num_fields = self.config.fields.len();
+        if num_fields > 0 {
+            flags |= FLAG_FIELDS;
+        }
+        let layout = IndexLayout::with_fields(flags, num_fields);
+        let data_offset = self.data_offset;
+        let index_offset = data_offset + self.data_len;
+
+        // インデックス・キーテーブル・メタデータを組み立てる
+        let mut tail = Vec::with_capacity(self.samples.len() * layout.entry_size);
+        for (index, sample) in self.samples.iter().enumerate() {
+            let fields = &self.field_ranges[index * num_fields..(index + 1) * num_fields];
+            layout.write_entry_with_fields(sample, fields, &mut tail)?;
+        }
+        let mut key_table_offset = 0;
+        if flags & FLAG_KEYS != 0 {
+            self.keys.resize(self.samples.len(), String::new());
+            key_table_offset = index_offset + tail.len() as u64;
+            tail.extend_from_slice(&KeyTable::encode(&self.keys)?);
+        }
+        let metadata_offset = index_offset + tail.len() as u64;
+        let metadata = ShardMetadata {
+            num_samples: self.samples.len() as u64,
+            samples: Vec::new(),
+            fields: std::mem::take(&mut self.config.fields),
+            schema: self.config.schema.take(),
+        };
+        metadata.write(&mut tail)?;
+        self.out.write_all(&tail)?;
+
+        // ヘッダーを確定させる
+        let mut header = ShardHeader {
+            flags,
+            index_offset,
+            num_samples: self.samples.len() as u64,
+            index_entry_size: layout.entry_size as u32,
+            checksum_kind,
+            index_checksum: checksum_kind.compute(&tail),
+            key_table_offset,
+            num_fields: num_fields as u32,
+            alignment: self.config.alignment.max(1),
+            ..ShardHeader::new(metadata_offset, data_offset)
+        };
+        header.header_checksum = header.compute_checksum();
+        self.out.seek(SeekFrom::Start(0))?;
+        header.write(&mut self.out)?;
+
+        let file = self.out.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(header)
+    }
+}
+
+/// `copy_file_range` を使う最小のバイト数（これより小さい範囲はバッファ経由で書き込む）
+const COPY_FILE_RANGE_MIN: usize = 64 << 10;
+
+/// `src` の範囲を `out` の現在位置へカーネル内でコピーし、コピーできたバイト数を返す
+///
+/// 途中で `copy_file_range` が使えなくなった場合は、残りを呼び出し側が書き込む。
+#[cfg(target_os = "linux")]
+fn copy_file_range(src: &File, src_offset: u64, out: &mut BufWriter<File>, len: u64) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    out.flush()?;
+    let mut off_in = src_offset as libc::loff_t;
+    let mut copied = 0;
+    while copied < len {
+        let n = unsafe {
+            libc::copy_file_range(
+                src.as_raw_fd(),
+                &mut off_in,
+                out.get_ref().as_raw_fd(),
+                std::ptr::null_mut(),
+                (len - copied) as usize,
+                0,
+            )
+        };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::ENOSYS | libc::EXDEV | libc::EINVAL | libc::EOPNOTSUPP | libc::EPERM) => Ok(copied),
+                _ => Err(err),
+            };
+        }
+        if n == 0 {
+            break;
+        }
+        copied += n as u64;
+    }
+    Ok(copied)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_file_range(_src: &File, _src_offset: u64, _out: &mut BufWriter<File>, _len: u64) -> io::Result<u64> {
+    Ok(0)
+}
+
+/// `value` を `alignment` の倍数に切り上げる（0と1は揃えない）
+fn align_up(value: u64, alignment: u32) -> u64 {
+    let alignment = alignment.max(1) as u64;
+    value.div_ceil(alignment) * alignment
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,10 +1011,12 @@ mod tests {
         let metadata = ShardMetadata {
             num_samples: 3,
             samples: vec![
-                SampleMetadata { offset: 0, size: 100 },
-                SampleMetadata { offset: 100, size: 200 },
-                SampleMetadata { offset: 300, size: 150 },
+                SampleMetadata::new(0, 100),
+                SampleMetadata::new(100, 200),
+                SampleMetadata::new(300, 150),
             ],
+            fields: Vec::new(),
+            schema: None,
         };
 
         let mut buf = Vec::new();
@@ -149,4 +1027,139 @@ mod tests {
         assert_eq!(metadata.num_samples, read_metadata.num_samples);
         assert_eq!(metadata.samples.len(), read_metadata.samples.len());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_v1_header_roundtrip() {
+        let header = ShardHeader::v1(22, 200);
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), ShardHeader::V1_SIZE);
+
+        let read_header = ShardHeader::read(&mut Cursor::new(&buf)).unwrap();
+        assert_eq!(read_header.version, FORMAT_VERSION_V1);
+        assert_eq!(read_header.metadata_offset, 22);
+        assert_eq!(read_header.data_offset, 200);
+        read_header.validate().unwrap();
+    }
+
+    #[test]
+    fn test_packed_index() {
+        let layout = IndexLayout::for_flags(0);
+        let first = SampleMetadata::new(0, 7);
+        let second = SampleMetadata::new(7, 3);
+        let mut buf = Vec::new();
+        layout.write_entry(&first, &mut buf).unwrap();
+        layout.write_entry(&second, &mut buf).unwrap();
+
+        let index = PackedIndex::new(&buf, layout).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get(1).unwrap().unwrap(), second);
+        assert!(index.get(2).is_none());
+        assert!(PackedIndex::new(&buf[..20], layout).is_err());
+
+        // チェックサム列付きのレイアウト
+        let layout = IndexLayout::for_flags(FLAG_SAMPLE_CHECKSUMS);
+        let sample = SampleMetadata {
+            checksum: Some(42),
+            ..SampleMetadata::new(0, 7)
+        };
+        let mut buf = Vec::new();
+        layout.write_entry(&sample, &mut buf).unwrap();
+        assert_eq!(buf.len(), 24);
+        assert_eq!(PackedIndex::new(&buf, layout).unwrap().get(0).unwrap().unwrap(), sample);
+
+        // チェックサム列と圧縮列の両方を持つレイアウト
+        let layout = IndexLayout::for_flags(FLAG_SAMPLE_CHECKSUMS | FLAG_COMPRESSION);
+        let sample = SampleMetadata {
+            checksum: Some(42),
+            codec: Codec::Zstd,
+            uncompressed_size: Some(100),
+            ..SampleMetadata::new(0, 7)
+        };
+        let mut buf = Vec::new();
+        layout.write_entry(&sample, &mut buf).unwrap();
+        assert_eq!(buf.len(), 40);
+        assert_eq!(PackedIndex::new(&buf, layout).unwrap().get(0).unwrap().unwrap(), sample);
+    }
+
+    #[test]
+    fn test_shard_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shard.bin");
+
+        let mut writer = ShardWriter::new(&path).unwrap();
+        assert_eq!(writer.append(b"sample1").unwrap(), 0);
+        assert_eq!(writer.append(b"").unwrap(), 1);
+        assert_eq!(writer.append(b"sample3").unwrap(), 2);
+        assert_eq!(writer.num_samples(), 3);
+        writer.finish().unwrap();
+
+        let buf = std::fs::read(&path).unwrap();
+        let header = ShardHeader::read(&mut Cursor::new(&buf)).unwrap();
+        header.validate().unwrap();
+        assert_eq!(header.version, FORMAT_VERSION);
+        assert_eq!(header.num_samples, 3);
+
+        let mut cursor = Cursor::new(&buf[header.metadata_offset as usize..]);
+        let metadata = ShardMetadata::read(&mut cursor).unwrap();
+        assert_eq!(metadata.num_samples, 3);
+        assert!(metadata.samples.is_empty());
+
+        let index_start = header.index_offset as usize;
+        let index_end = index_start + header.index_size().unwrap() as usize;
+        let index = PackedIndex::new(&buf[index_start..index_end], header.index_layout()).unwrap();
+
+        let data = &buf[header.data_offset as usize..];
+        let sample = index.get(2).unwrap().unwrap();
+        assert_eq!(&data[sample.offset as usize..(sample.offset + sample.size) as usize], b"sample3");
+        assert_eq!(index.get(1).unwrap().unwrap().size, 0);
+    }
+
+    #[test]
+    fn test_aligned_shard_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aligned.bin");
+
+        let config = WriterConfig {
+            fields: vec!["a".to_string(), "b".to_string()],
+            alignment: 256,
+            ..Default::default()
+        };
+        let mut writer = ShardWriter::with_config(&path, config).unwrap();
+        writer.append_fields(&[("a", b"odd"), ("b", &[1u8; 8])]).unwrap();
+        writer.append_fields(&[("b", &[2u8; 8])]).unwrap();
+        let header = writer.finish().unwrap();
+        assert_eq!(header.alignment, 256);
+        assert_eq!(header.data_offset, 256);
+
+        let buf = std::fs::read(&path).unwrap();
+        let index_start = header.index_offset as usize;
+        let index_end = index_start + header.index_size().unwrap() as usize;
+        let index = PackedIndex::new(&buf[index_start..index_end], header.index_layout()).unwrap();
+        assert_eq!(index.get(1).unwrap().unwrap().offset, 512);
+        assert_eq!(index.field(0, 1), Some((256, 8)));
+
+        let config = WriterConfig {
+            alignment: 48,
+            ..Default::default()
+        };
+        assert!(ShardWriter::with_config(&path, config).is_err());
+    }
+
+    #[test]
+    fn test_key_table() {
+        let keys = vec!["b".to_string(), String::new(), "a".to_string(), "c/d".to_string()];
+        let bytes = KeyTable::encode(&keys).unwrap();
+        let table = KeyTable::new(&bytes, keys.len()).unwrap();
+
+        assert_eq!(table.key(0), Some("b"));
+        assert_eq!(table.key(1), None);
+        assert_eq!(table.find("a"), Some(2));
+        assert_eq!(table.find("c/d"), Some(3));
+        assert_eq!(table.find(""), None);
+        assert_eq!(table.find("z"), None);
+
+        let duplicates = vec!["x".to_string(), "x".to_string()];
+        assert!(KeyTable::encode(&duplicates).is_err());
+    }
+}
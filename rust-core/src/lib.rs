@@ -1,12 +1,29 @@
 pub mod buffer;
+pub mod checksum;
+pub mod codec;
 pub mod format;
 pub mod mmap;
 pub mod prefetch;
 pub mod reader;
+pub mod reshard;
+pub mod sampler;
+pub mod schema;
+pub mod storage;
 
-use reader::{MultiShardReader, ReaderError};
-use prefetch::{create_prefetcher, Prefetcher, PrefetchError};
+use buffer::{BufferPool, DecodedSample};
+use codec::Codec;
+use mmap::EvictionWindow;
+use reader::{MultiShardReader, ReaderConfig, ReaderError, SampleRef, SampleView, ShardRef};
+use prefetch::{
+    create_prefetcher_with_config, PrefetchBackend, PrefetchConfig, PrefetchError, PrefetchFallback, PrefetchRange,
+    PrefetchCursor, PrefetchTicket, Prefetcher,
+};
+use sampler::{create_sampler, Sampler, SamplerConfig, SamplerError};
+use schema::{Schema, TypedView};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::path::PathBuf;
+use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,62 +32,281 @@ pub enum DataLoaderError {
     Reader(#[from] ReaderError),
     #[error("Prefetch error: {0}")]
     Prefetch(#[from] PrefetchError),
+    #[error("Shard index out of bounds: {0}")]
+    ShardOutOfBounds(usize),
+    #[error("Shard {0} is not memory-mapped")]
+    NotMapped(usize),
+    #[error("Sampler error: {0}")]
+    Sampler(#[from] SamplerError),
+    #[error("Invalid loader state: {0}")]
+    InvalidState(String),
+}
+
+/// データローダーの設定
+#[derive(Debug, Clone)]
+pub struct DataLoaderConfig {
+    /// シャード読み込みの設定（チェックサム検証など）
+    pub reader: ReaderConfig,
+    /// プリフェッチャーの設定（実装方式の選択など）
+    pub prefetch: PrefetchConfig,
+    /// 読み終えたサンプルを直近このバイト数だけ残してページキャッシュから追い出す
+    /// （`None` なら追い出さない。`mark_consumed` で読み終えたことを伝える）
+    pub evict_window: Option<u64>,
+    /// サンプルの読み込み順（シャッフルの方式とシード）
+    pub sampler: SamplerConfig,
+    /// `iter` / `batches` で読み込み順の何サンプル先までプリフェッチしておくか（0なら行わない）
+    pub prefetch_ahead: usize,
+}
+
+impl Default for DataLoaderConfig {
+    fn default() -> Self {
+        Self {
+            reader: ReaderConfig::default(),
+            prefetch: PrefetchConfig::default(),
+            evict_window: None,
+            sampler: SamplerConfig::default(),
+            prefetch_ahead: 256,
+        }
+    }
+}
+
+/// 学習を中断した位置から再開するためのローダーの状態
+///
+/// serdeでシリアライズでき、`DataLoader::restore` に渡すと同じ読み込み順の残りから続ける。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoaderState {
+    /// サンプラーの設定（シード、シャッフル、ランク。`set_sampler` で差し替えた場合は `None`）
+    pub sampler: Option<SamplerConfig>,
+    /// サンプラーの種類（`Sampler::kind`。設定のないサンプラーで再開するときの確認用）
+    #[serde(default)]
+    pub sampler_kind: String,
+    /// 1エポックのサンプル数（再開時にデータセットが変わっていないことの確認用）
+    pub epoch_len: usize,
+    /// 現在のエポック
+    pub epoch: u64,
+    /// 現在のエポックの読み込み順で次に読む位置
+    pub position: usize,
+    /// プリフェッチのエポック
+    pub prefetch_epoch: u64,
+    /// プリフェッチのエポック内の位置
+    pub prefetch_position: usize,
 }
 
 /// ゼロコピーデータローダー（メインAPI）
 pub struct DataLoader {
     reader: MultiShardReader,
     prefetcher: Box<dyn Prefetcher>,
+    prefetch_fallbacks: Vec<PrefetchFallback>,
     shard_paths: Vec<PathBuf>,
-    current_shard_index: usize,
+    prefetch_cursor: PrefetchCursor,
+    eviction: Option<EvictionWindow>,
+    sampler: Arc<dyn Sampler>,
+    sampler_config: Option<SamplerConfig>,
+    epoch: u64,
+    position: usize,
+    epoch_order: Option<Vec<usize>>,
+    prefetch_ahead: usize,
+    prefetched: usize,
+    ahead_tickets: VecDeque<PrefetchTicket>,
+    decode_buffers: BufferPool,
 }
 
 impl DataLoader {
     /// 新しいデータローダーを作成
     pub fn new<P: AsRef<std::path::Path>>(shard_paths: &[P]) -> Result<Self, DataLoaderError> {
+        Self::with_config(shard_paths, DataLoaderConfig::default())
+    }
+
+    /// 設定を指定してデータローダーを作成
+    pub fn with_config<P: AsRef<std::path::Path>>(
+        shard_paths: &[P],
+        config: DataLoaderConfig,
+    ) -> Result<Self, DataLoaderError> {
         let paths: Vec<PathBuf> = shard_paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
-        let reader = MultiShardReader::new(&paths)?;
-        let prefetcher = create_prefetcher(32)?; // デフォルトのキュー深度
+        let reader = MultiShardReader::with_config(&paths, config.reader)?;
+        let (prefetcher, prefetch_fallbacks) = create_prefetcher_with_config(&config.prefetch)?;
+        let sampler = create_sampler(&config.sampler, &reader.shard_sizes())?;
 
-        Ok(Self {
+        let mut loader = Self {
             reader,
             prefetcher,
+            prefetch_fallbacks,
+            prefetch_cursor: PrefetchCursor::new(paths.len()),
             shard_paths: paths,
-            current_shard_index: 0,
-        })
+            eviction: config.evict_window.map(EvictionWindow::new),
+            sampler: Arc::from(sampler),
+            sampler_config: Some(config.sampler),
+            epoch: 0,
+            position: 0,
+            epoch_order: None,
+            prefetch_ahead: config.prefetch_ahead,
+            prefetched: 0,
+            ahead_tickets: VecDeque::new(),
+            decode_buffers: BufferPool::default(),
+        };
+        loader.follow_sampler();
+        Ok(loader)
+    }
+
+    /// サンプラーがシャード単位で読む順序を持つなら、プリフェッチをその順序に合わせる
+    fn follow_sampler(&mut self) {
+        let num_shards = self.shard_paths.len();
+        self.prefetch_cursor = if self.sampler.shard_order(0).is_some() {
+            let sampler = Arc::clone(&self.sampler);
+            PrefetchCursor::with_order(Box::new(move |epoch| sampler.shard_order(epoch).unwrap_or_default()))
+        } else {
+            PrefetchCursor::new(num_shards)
+        };
     }
 
     /// 指定されたインデックスのサンプルを取得（ゼロコピー）
-    pub fn get_sample(&self, index: usize) -> Result<&[u8], DataLoaderError> {
+    ///
+    /// 遅延オープン（`ReaderConfig::max_open_shards`）では、返した値が読んだシャードの
+    /// マップを保持する。`O_DIRECT` では読み込んだバッファを保持し、破棄すると再利用する。
+    pub fn get_sample(&self, index: usize) -> Result<SampleRef<'_>, DataLoaderError> {
         self.reader.get_sample(index).map_err(DataLoaderError::Reader)
     }
 
+    /// 指定されたインデックスのサンプルを展開して取得
+    ///
+    /// 無圧縮のサンプルはゼロコピーのまま返し、圧縮されたサンプルはプールのバッファに展開する。
+    /// 遅延オープンのシャードや `O_DIRECT` のサンプルは、無圧縮でもプールのバッファにコピーする。
+    pub fn get_sample_decoded(&self, index: usize) -> Result<DecodedSample<'_>, DataLoaderError> {
+        if self.reader.sample_metadata(index)?.codec == Codec::None {
+            let sample = self.reader.get_sample(index)?;
+            if let Some(data) = sample.as_borrowed() {
+                return Ok(DecodedSample::Borrowed(data));
+            }
+            let mut buf = self.decode_buffers.acquire();
+            buf.clear();
+            buf.extend_from_slice(&sample);
+            return Ok(DecodedSample::Pooled(buf));
+        }
+        let mut buf = self.decode_buffers.acquire();
+        self.reader.get_sample_decoded(index, &mut buf)?;
+        Ok(DecodedSample::Pooled(buf))
+    }
+
+    /// 指定されたインデックスのサンプルを呼び出し側のバッファに展開して取得
+    pub fn get_sample_decoded_into<'a>(
+        &'a self,
+        index: usize,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<&'a [u8], DataLoaderError> {
+        self.reader
+            .get_sample_decoded(index, buf)
+            .map_err(DataLoaderError::Reader)
+    }
+
+    /// 指定されたサンプルのフィールドビューを取得（ゼロコピー）
+    pub fn get_view(&self, index: usize) -> Result<SampleView<'_>, DataLoaderError> {
+        self.reader.get_view(index).map_err(DataLoaderError::Reader)
+    }
+
+    /// 指定されたサンプルを呼び出し側のバッファに展開してフィールドビューを取得
+    pub fn get_view_decoded<'a>(
+        &'a self,
+        index: usize,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<SampleView<'a>, DataLoaderError> {
+        self.reader
+            .get_view_decoded(index, buf)
+            .map_err(DataLoaderError::Reader)
+    }
+
+    /// キーでサンプルを取得（ゼロコピー）
+    pub fn get_by_key(&self, key: &str) -> Result<SampleRef<'_>, DataLoaderError> {
+        self.reader.get_by_key(key).map_err(DataLoaderError::Reader)
+    }
+
+    /// 型スキーマを取得（スキーマを持たないシャードでは `None`）
+    pub fn schema(&self) -> Option<&Schema> {
+        self.reader.schema()
+    }
+
+    /// 指定されたサンプルのフィールドを型付きビューとして取得（ゼロコピー）
+    ///
+    /// `field` が空文字列ならサンプル全体を対象とする。
+    pub fn get_typed(&self, index: usize, field: &str) -> Result<Option<TypedView<'_>>, DataLoaderError> {
+        self.reader.get_typed(index, field).map_err(DataLoaderError::Reader)
+    }
+
+    /// 指定されたサンプルを呼び出し側のバッファに展開し、フィールドを型付きビューとして取得
+    pub fn get_typed_decoded_into<'a>(
+        &'a self,
+        index: usize,
+        field: &str,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<Option<TypedView<'a>>, DataLoaderError> {
+        self.reader
+            .get_typed_decoded(index, field, buf)
+            .map_err(DataLoaderError::Reader)
+    }
+
     /// 複数のサンプルを一度に取得
-    pub fn get_batch(&self, indices: &[usize]) -> Result<Vec<&[u8]>, DataLoaderError> {
+    pub fn get_batch(&self, indices: &[usize]) -> Result<Vec<SampleRef<'_>>, DataLoaderError> {
         self.reader.get_batch(indices).map_err(DataLoaderError::Reader)
     }
 
-    /// 次のN個のシャードをプリフェッチ
-    pub fn prefetch_next(&mut self, count: usize) -> Result<(), DataLoaderError> {
-        let num_shards = self.reader.num_shards();
-        if self.current_shard_index >= num_shards {
-            return Ok(()); // すべてのシャードを読み込み済み
-        }
-
-        let end_index = (self.current_shard_index + count).min(num_shards);
-        let paths_to_prefetch: Vec<PathBuf> = self
-            .shard_paths
-            [self.current_shard_index..end_index]
-            .iter()
-            .cloned()
-            .collect();
+    /// 読み込み順で次のN個のシャードをプリフェッチし、完了確認用のチケットを返す
+    ///
+    /// サンプラーがシャード単位で読む順序を持つ場合（`shard` / `block`）はその順序に従う。
+    /// エポックの終わりに達すると次のエポックの先頭から続ける。
+    pub fn prefetch_next(&mut self, count: usize) -> Result<PrefetchTicket, DataLoaderError> {
+        let shards = self.prefetch_cursor.advance(count);
+        let paths_to_prefetch = shards
+            .into_iter()
+            .map(|shard| {
+                self.shard_paths
+                    .get(shard)
+                    .cloned()
+                    .ok_or(DataLoaderError::ShardOutOfBounds(shard))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         self.prefetcher
             .prefetch_files(&paths_to_prefetch)
-            .map_err(DataLoaderError::Prefetch)?;
+            .map_err(DataLoaderError::Prefetch)
+    }
 
-        self.current_shard_index = end_index;
-        Ok(())
+    /// サンプラーが同時に読み進めるシャード数（`Sampler::window`）だけ次のシャードをプリフェッチする
+    ///
+    /// ブロックシャッフルでは、現在のウィンドウを読んでいる間に次のウィンドウを温めておける。
+    pub fn prefetch_window(&mut self) -> Result<PrefetchTicket, DataLoaderError> {
+        self.prefetch_next(self.sampler.window())
+    }
+
+    /// エポックごとのシャードの読み込み順を設定し、プリフェッチの位置を先頭に戻す
+    pub fn set_prefetch_order<F>(&mut self, order_fn: F)
+    where
+        F: Fn(u64) -> Vec<usize> + Send + Sync + 'static,
+    {
+        self.prefetch_cursor = PrefetchCursor::with_order(Box::new(order_fn));
+    }
+
+    /// プリフェッチの位置を最初のエポックの先頭に戻す
+    pub fn reset_prefetch(&mut self) {
+        self.prefetch_cursor.reset();
+    }
+
+    /// プリフェッチの位置を取得
+    pub fn prefetch_cursor(&self) -> &PrefetchCursor {
+        &self.prefetch_cursor
+    }
+
+    /// 指定されたサンプルのページだけをプリフェッチ
+    ///
+    /// グローバルインデックスをシャード内の格納範囲に変換し、同じシャードで隣接する
+    /// （1ページ以内の隙間の）範囲はまとめて発行する。
+    pub fn prefetch_samples(&mut self, indices: &[usize]) -> Result<PrefetchTicket, DataLoaderError> {
+        let ranges = self.sample_ranges(indices)?;
+        self.prefetcher
+            .prefetch_ranges(&ranges)
+            .map_err(DataLoaderError::Prefetch)
+    }
+
+    fn sample_ranges(&self, indices: &[usize]) -> Result<Vec<PrefetchRange>, DataLoaderError> {
+        sample_ranges(&self.reader, &self.shard_paths, indices)
     }
 
     /// プリフェッチの完了を待つ
@@ -78,6 +314,313 @@ impl DataLoader {
         self.prefetcher.wait().map_err(DataLoaderError::Prefetch)
     }
 
+    /// チケットのプリフェッチが完了したかどうか
+    pub fn poll_prefetch(&mut self, ticket: PrefetchTicket) -> Result<bool, DataLoaderError> {
+        self.prefetcher.poll(ticket).map_err(DataLoaderError::Prefetch)
+    }
+
+    /// チケットのプリフェッチの完了を待つ
+    pub fn wait_prefetch_ticket(&mut self, ticket: PrefetchTicket) -> Result<(), DataLoaderError> {
+        self.prefetcher.wait_ticket(ticket).map_err(DataLoaderError::Prefetch)
+    }
+
+    /// シャードの全ページがページキャッシュに載っているかどうか（`mincore` で確認）
+    ///
+    /// `O_DIRECT` で読むシャードはページキャッシュを使わないので常に `false`。遅延オープンで
+    /// 開いていないシャードは、確認のために開くことはせず `false` を返す。
+    #[cfg(unix)]
+    pub fn is_resident(&self, shard: usize) -> Result<bool, DataLoaderError> {
+        let Some(reader) = self.open_shard(shard)? else {
+            return Ok(false);
+        };
+        let Some(mmap) = reader.mmap() else {
+            return Ok(false);
+        };
+        let (resident, total) = mmap.resident_pages(0, mmap.len()).map_err(ReaderError::Mmap)?;
+        Ok(resident == total)
+    }
+
+    /// シャードのページをメモリに固定する（`mlock`、頻繁に読むシャード向け）
+    ///
+    /// 遅延オープンでは開いていなければ開く。LRUから外れて開き直したシャードは固定されない。
+    /// すべてのシャードを固定するには `MmapConfig::lock` を使う。
+    #[cfg(unix)]
+    pub fn lock_shard(&self, shard: usize) -> Result<(), DataLoaderError> {
+        self.check_shard(shard)?;
+        let reader = self.reader.shard(shard)?;
+        let mmap = reader.mmap().ok_or(DataLoaderError::NotMapped(shard))?;
+        mmap.lock().map_err(ReaderError::Mmap)?;
+        Ok(())
+    }
+
+    /// シャードのページの固定を解除する（遅延オープンで開いていなければ何もしない）
+    #[cfg(unix)]
+    pub fn unlock_shard(&self, shard: usize) -> Result<(), DataLoaderError> {
+        let Some(reader) = self.open_shard(shard)? else {
+            return Ok(());
+        };
+        let mmap = reader.mmap().ok_or(DataLoaderError::NotMapped(shard))?;
+        mmap.unlock().map_err(ReaderError::Mmap)?;
+        Ok(())
+    }
+
+    /// 開いているシャードのリーダーを取得（遅延オープンで開いていなければ `None`）
+    fn open_shard(&self, shard: usize) -> Result<Option<ShardRef<'_>>, DataLoaderError> {
+        self.check_shard(shard)?;
+        Ok(self.reader.open_shard(shard)?)
+    }
+
+    fn check_shard(&self, shard: usize) -> Result<(), DataLoaderError> {
+        if shard >= self.reader.num_shards() {
+            return Err(DataLoaderError::ShardOutOfBounds(shard));
+        }
+        Ok(())
+    }
+
+    /// 順次読み込みでサンプルを読み終えたことを伝える
+    ///
+    /// `evict_window` が設定されていれば、読み終えた範囲のうち直近のウィンドウより前のページを
+    /// ページキャッシュから追い出し、常駐するメモリ量を抑える（mmapで読むシャードのみ。遅延オープンで
+    /// 開いていないシャードは追い出すために開くことはしない）。`iter` / `batches` / `next_batch` で
+    /// 返したサンプルは自動で読み終えたものとして扱う。
+    pub fn mark_consumed(&mut self, index: usize) -> Result<(), DataLoaderError> {
+        match &mut self.eviction {
+            Some(eviction) => evict_consumed(&self.reader, eviction, index),
+            None => Ok(()),
+        }
+    }
+
+    /// 使用中のプリフェッチの実装方式を取得
+    pub fn prefetch_backend(&self) -> PrefetchBackend {
+        self.prefetcher.backend()
+    }
+
+    /// 使えなかったために飛ばしたプリフェッチの実装方式と理由を取得
+    pub fn prefetch_fallbacks(&self) -> &[PrefetchFallback] {
+        &self.prefetch_fallbacks
+    }
+
+    /// 指定されたエポックのサンプルの読み込み順（グローバルインデックス）を取得
+    ///
+    /// 同じシードとエポックからは、実行やプロセスをまたいでも同じ順序になる。
+    pub fn sample_order(&self, epoch: u64) -> Vec<usize> {
+        self.sampler.order(epoch)
+    }
+
+    /// サンプラーを取得
+    pub fn sampler(&self) -> &dyn Sampler {
+        self.sampler.as_ref()
+    }
+
+    /// サンプラーを差し替える（独自の読み込み順を使う場合）
+    ///
+    /// プリフェッチの順序もサンプラーのシャード順に合わせ、読み込みとプリフェッチの位置を
+    /// 現在のエポックの先頭に戻す。
+    pub fn set_sampler(&mut self, sampler: Box<dyn Sampler>) {
+        self.sampler = Arc::from(sampler);
+        self.sampler_config = None;
+        self.epoch_order = None;
+        self.follow_sampler();
+        self.set_epoch(self.epoch);
+    }
+
+    /// 現在のエポック
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// 現在のエポックの読み込み順で次に読む位置
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// 指定されたエポックの先頭に移る（プリフェッチも同じエポックの先頭から続ける）
+    pub fn set_epoch(&mut self, epoch: u64) {
+        self.move_to(epoch, 0);
+        self.prefetch_cursor.seek(epoch, 0);
+    }
+
+    /// 読み込みの位置を移す
+    fn move_to(&mut self, epoch: u64, position: usize) {
+        if epoch != self.epoch {
+            self.epoch_order = None;
+        }
+        self.epoch = epoch;
+        self.position = position;
+        self.prefetched = position;
+    }
+
+    /// 現在のエポックを読み終えていれば次のエポックの先頭に移る
+    ///
+    /// 移ったら `true` を返す。`iter` と `batches` は最初にこれを呼ぶ。
+    pub fn begin_epoch(&mut self) -> bool {
+        if self.sampler.is_empty() || self.position < self.sampler.len() {
+            return false;
+        }
+        self.move_to(self.epoch + 1, 0);
+        true
+    }
+
+    /// 現在のエポックの読み込み順で次のサンプルのインデックスを最大 `count` 個取得し、位置を進める
+    ///
+    /// エポックを読み終えた後に呼ぶと、次のエポックの先頭から返す。
+    pub fn next_indices(&mut self, count: usize) -> Vec<usize> {
+        if count > 0 {
+            self.begin_epoch();
+        }
+        let (epoch, sampler) = (self.epoch, &self.sampler);
+        let order = self.epoch_order.get_or_insert_with(|| sampler.order(epoch));
+        let start = self.position.min(order.len());
+        let end = start.saturating_add(count).min(order.len());
+        self.position = end;
+        order[start..end].to_vec()
+    }
+
+    /// 現在のエポックの残りのサンプルを読み込み順に返すイテレーター
+    ///
+    /// 現在のエポックを読み終えていれば次のエポックから始め、エポックの終わりで止まる。
+    /// 読み進めるにつれ、`DataLoaderConfig::prefetch_ahead` サンプル先までプリフェッチし、
+    /// 返したサンプルは `mark_consumed` と同じく読み終えたものとして扱う。
+    /// 読み込みの位置はローダーに残るので、途中でやめても `state` で再開できる。
+    pub fn iter(&mut self) -> Samples<'_> {
+        self.begin_epoch();
+        Samples(self.progress())
+    }
+
+    /// 現在のエポックの残りのサンプルを `batch_size` 個ずつ返すイテレーター
+    ///
+    /// `drop_last` ならエポックの最後の半端なバッチを返さずに読み飛ばす。
+    pub fn batches(&mut self, batch_size: usize, drop_last: bool) -> Batches<'_> {
+        self.begin_epoch();
+        Batches {
+            progress: self.progress(),
+            batch_size: batch_size.max(1),
+            drop_last,
+        }
+    }
+
+    /// 現在のエポックの次のバッチを取得（エポックの終わりでは `None`、次のエポックには進まない）
+    ///
+    /// `batches` と同じく先のサンプルをプリフェッチする。イテレーターを保持できない
+    /// 呼び出し側（Pythonバインディングなど）向け。
+    pub fn next_batch(
+        &mut self,
+        batch_size: usize,
+        drop_last: bool,
+    ) -> Option<Result<Vec<SampleRef<'_>>, DataLoaderError>> {
+        self.progress().next_batch(batch_size.max(1), drop_last)
+    }
+
+    /// 1エポックで読むサンプル数（分散学習ではこのランクの取り分）
+    pub fn epoch_len(&self) -> usize {
+        self.sampler.len()
+    }
+
+    /// 1エポックのバッチ数
+    pub fn num_batches(&self, batch_size: usize, drop_last: bool) -> usize {
+        let batch_size = batch_size.max(1);
+        if drop_last {
+            self.epoch_len() / batch_size
+        } else {
+            self.epoch_len().div_ceil(batch_size)
+        }
+    }
+
+    fn progress(&mut self) -> Progress<'_> {
+        let (epoch, sampler) = (self.epoch, &self.sampler);
+        let order = self.epoch_order.get_or_insert_with(|| sampler.order(epoch));
+        Progress {
+            reader: &self.reader,
+            shard_paths: &self.shard_paths,
+            prefetcher: self.prefetcher.as_mut(),
+            order,
+            position: &mut self.position,
+            prefetched: &mut self.prefetched,
+            tickets: &mut self.ahead_tickets,
+            ahead: self.prefetch_ahead,
+            eviction: self.eviction.as_mut(),
+        }
+    }
+
+    /// 再開用の状態を取得
+    pub fn state(&self) -> LoaderState {
+        LoaderState {
+            sampler: self.sampler_config.clone(),
+            sampler_kind: self.sampler.kind(),
+            epoch_len: self.sampler.len(),
+            epoch: self.epoch,
+            position: self.position,
+            prefetch_epoch: self.prefetch_cursor.epoch(),
+            prefetch_position: self.prefetch_cursor.position(),
+        }
+    }
+
+    /// `state` で取得した状態から再開する
+    ///
+    /// 状態にサンプラーの設定があればそれでサンプラーを作り直す（シードやシャッフルは状態に従う）。
+    /// ランクはローダーのものを使うので、ランク0で保存した状態を全ランクで読み込める。
+    /// ランク数が状態と異なれば `DataLoaderError::InvalidState` になる。設定がなければ（`set_sampler` で差し替えたサンプラー）作り直せないので、ローダーにも
+    /// 同じ種類のサンプラーが差し替えてある場合だけ再開する。確かめられない状態や、
+    /// 1エポックのサンプル数が合わない状態は `DataLoaderError::InvalidState` になる。
+    pub fn restore(&mut self, state: &LoaderState) -> Result<(), DataLoaderError> {
+        let config = match &state.sampler {
+            Some(config) => {
+                let (rank, world_size) = self
+                    .sampler_config
+                    .as_ref()
+                    .map_or((0, 1), |current| (current.rank, current.world_size));
+                if config.world_size != world_size {
+                    return Err(DataLoaderError::InvalidState(format!(
+                        "state is for {} ranks, but the loader has {}",
+                        config.world_size, world_size
+                    )));
+                }
+                Some(SamplerConfig {
+                    rank,
+                    world_size,
+                    ..config.clone()
+                })
+            }
+            None => None,
+        };
+        let sampler = match (&config, &self.sampler_config) {
+            (Some(config), current) if current.as_ref() != Some(config) => {
+                Some(create_sampler(config, &self.reader.shard_sizes())?)
+            }
+            (Some(_), _) => None,
+            (None, Some(_)) => {
+                return Err(DataLoaderError::InvalidState(
+                    "state is for a custom sampler, but the loader uses a configured one".to_string(),
+                ));
+            }
+            (None, None) if state.sampler_kind != self.sampler.kind() => {
+                return Err(DataLoaderError::InvalidState(format!(
+                    "state is for sampler {}, but the loader uses {}",
+                    state.sampler_kind,
+                    self.sampler.kind()
+                )));
+            }
+            (None, None) => None,
+        };
+        let epoch_len = sampler.as_ref().map_or(self.sampler.len(), |sampler| sampler.len());
+        if state.epoch_len != epoch_len || state.position > epoch_len {
+            return Err(DataLoaderError::InvalidState(format!(
+                "state is for {} samples per epoch at position {}, but the loader has {}",
+                state.epoch_len, state.position, epoch_len
+            )));
+        }
+
+        if let Some(sampler) = sampler {
+            self.sampler = Arc::from(sampler);
+            self.sampler_config = config;
+            self.follow_sampler();
+        }
+        self.epoch_order = None;
+        self.move_to(state.epoch, state.position);
+        self.prefetch_cursor.seek(state.prefetch_epoch, state.prefetch_position);
+        Ok(())
+    }
+
     /// 総サンプル数を取得
     pub fn total_samples(&self) -> usize {
         self.reader.total_samples()
@@ -89,61 +632,175 @@ impl DataLoader {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::format::{ShardHeader, ShardMetadata, SampleMetadata};
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+/// 読み終えたサンプルの範囲をウィンドウに加え、ウィンドウから外れたページを追い出す
+fn evict_consumed(
+    reader: &MultiShardReader,
+    eviction: &mut EvictionWindow,
+    index: usize,
+) -> Result<(), DataLoaderError> {
+    let (shard, offset, len) = reader.sample_range(index)?;
+    for (shard, offset, len) in eviction.push(shard, offset, len) {
+        #[cfg(unix)]
+        if let Some(reader) = reader.open_shard(shard)? {
+            if let Some(mmap) = reader.mmap() {
+                mmap.evict(offset as usize, len as usize).map_err(ReaderError::Mmap)?;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (shard, offset, len);
+    }
+    Ok(())
+}
 
-    fn create_test_shard(data: &[&[u8]]) -> NamedTempFile {
-        use format::SampleMetadata;
-        let mut file = NamedTempFile::new().unwrap();
-        let mut buf = Vec::new();
+/// サンプルの格納範囲を、同じシャードで隣接する（1ページ以内の隙間の）ものをまとめて
+/// プリフェッチ範囲に変換する
+fn sample_ranges(
+    reader: &MultiShardReader,
+    shard_paths: &[PathBuf],
+    indices: &[usize],
+) -> Result<Vec<PrefetchRange>, DataLoaderError> {
+    let mut spans = indices
+        .iter()
+        .map(|&index| reader.sample_range(index))
+        .collect::<Result<Vec<_>, _>>()?;
+    spans.sort_unstable();
 
-        // ヘッダー用のスペースを確保（後で更新）
-        let header_offset = 0;
-        buf.resize(ShardHeader::SIZE, 0);
+    let gap = mmap::page_size() as u64;
+    let mut merged: Vec<(usize, u64, u64)> = Vec::with_capacity(spans.len());
+    for (shard, offset, len) in spans {
+        match merged.last_mut() {
+            Some((last_shard, _, end)) if *last_shard == shard && offset <= *end + gap => {
+                *end = (*end).max(offset + len);
+            }
+            _ => merged.push((shard, offset, offset + len)),
+        }
+    }
+    Ok(merged
+        .into_iter()
+        .map(|(shard, start, end)| PrefetchRange::new(&shard_paths[shard], start, end - start))
+        .collect())
+}
 
-        // メタデータを準備
-        let mut samples = Vec::new();
-        let mut current_offset = 0u64;
-        for sample_data in data {
-            samples.push(SampleMetadata {
-                offset: current_offset,
-                size: sample_data.len() as u64,
-            });
-            current_offset += sample_data.len() as u64;
+/// 読み込み順に沿って読み進める状態（`iter` / `batches` / `next_batch` で共有）
+///
+/// リーダーは共有で借り、位置とプリフェッチャーだけを可変で借りるので、返すサンプルは
+/// イテレーターより長く使える。返したサンプルは読み終えたものとして `evict_window` に加える。
+/// プリフェッチと追い出しはヒントなので、失敗してもサンプルの読み込みは続ける。
+struct Progress<'a> {
+    reader: &'a MultiShardReader,
+    shard_paths: &'a [PathBuf],
+    prefetcher: &'a mut dyn Prefetcher,
+    order: &'a [usize],
+    position: &'a mut usize,
+    prefetched: &'a mut usize,
+    tickets: &'a mut VecDeque<PrefetchTicket>,
+    ahead: usize,
+    eviction: Option<&'a mut EvictionWindow>,
+}
+
+impl<'a> Progress<'a> {
+    fn remaining(&self) -> usize {
+        self.order.len().saturating_sub(*self.position)
+    }
+
+    /// 先読みが残り半分を切ったら、`ahead` サンプル先までプリフェッチする
+    ///
+    /// 失敗しても同じ範囲は再び発行しない。
+    fn prefetch_ahead(&mut self) -> Result<(), DataLoaderError> {
+        let start = (*self.prefetched).max(*self.position);
+        if self.ahead == 0 || start >= self.order.len() || start - *self.position > self.ahead / 2 {
+            return Ok(());
+        }
+        let end = self.position.saturating_add(self.ahead).min(self.order.len());
+        *self.prefetched = end;
+        // 結果を見ないチケットは、完了したものから片付ける（失敗しても読み込み時に分かる）
+        while let Some(&ticket) = self.tickets.front() {
+            if !self.prefetcher.poll(ticket).unwrap_or(true) {
+                break;
+            }
+            self.tickets.pop_front();
         }
+        let ranges = sample_ranges(self.reader, self.shard_paths, &self.order[start..end])?;
+        let ticket = self.prefetcher.prefetch_ranges(&ranges)?;
+        self.tickets.push_back(ticket);
+        Ok(())
+    }
 
-        let metadata = ShardMetadata {
-            num_samples: samples.len() as u64,
-            samples,
-        };
+    fn next_sample(&mut self) -> Option<Result<SampleRef<'a>, DataLoaderError>> {
+        let index = *self.order.get(*self.position)?;
+        let _ = self.prefetch_ahead();
+        *self.position += 1;
+        let sample = self.reader.get_sample(index).map_err(DataLoaderError::Reader);
+        if let (Ok(_), Some(eviction)) = (&sample, self.eviction.as_deref_mut()) {
+            let _ = evict_consumed(self.reader, eviction, index);
+        }
+        Some(sample)
+    }
+
+    fn next_batch(&mut self, batch_size: usize, drop_last: bool) -> Option<Result<Vec<SampleRef<'a>>, DataLoaderError>> {
+        let remaining = self.remaining();
+        if remaining == 0 || (drop_last && remaining < batch_size) {
+            *self.position = self.order.len();
+            return None;
+        }
+        let mut batch = Vec::with_capacity(batch_size.min(remaining));
+        while batch.len() < batch_size {
+            match self.next_sample() {
+                Some(Ok(sample)) => batch.push(sample),
+                Some(Err(e)) => return Some(Err(e)),
+                None => break,
+            }
+        }
+        Some(Ok(batch))
+    }
+}
+
+/// `DataLoader::iter` が返すイテレーター
+pub struct Samples<'a>(Progress<'a>);
 
-        // メタデータを書き込む
-        let metadata_start = buf.len();
-        metadata.write(&mut buf).unwrap();
-        let metadata_end = buf.len();
-        let metadata_offset = metadata_start as u64;
+impl<'a> Iterator for Samples<'a> {
+    type Item = Result<SampleRef<'a>, DataLoaderError>;
 
-        // データセクションの開始位置
-        let data_offset = metadata_end as u64;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_sample()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.0.remaining();
+        (remaining, Some(remaining))
+    }
+}
 
-        // ヘッダーを書き込む
-        let header = ShardHeader::new(metadata_offset, data_offset);
-        let mut header_buf = Vec::new();
-        header.write(&mut header_buf).unwrap();
-        buf[header_offset..header_offset + ShardHeader::SIZE]
-            .copy_from_slice(&header_buf);
+impl ExactSizeIterator for Samples<'_> {}
 
-        // データを書き込む
+/// `DataLoader::batches` が返すイテレーター
+pub struct Batches<'a> {
+    progress: Progress<'a>,
+    batch_size: usize,
+    drop_last: bool,
+}
+
+impl<'a> Iterator for Batches<'a> {
+    type Item = Result<Vec<SampleRef<'a>>, DataLoaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.progress.next_batch(self.batch_size, self.drop_last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::ShardWriter;
+    use tempfile::NamedTempFile;
+
+    fn create_test_shard(data: &[&[u8]]) -> NamedTempFile {
+        let file = NamedTempFile::new().unwrap();
+        let mut writer = ShardWriter::new(file.path()).unwrap();
         for sample_data in data {
-            buf.extend_from_slice(sample_data);
+            writer.append(sample_data).unwrap();
         }
-
-        file.write_all(&buf).unwrap();
-        file.flush().unwrap();
+        writer.finish().unwrap();
         file
     }
 
@@ -157,4 +814,326 @@ mod tests {
         assert_eq!(loader.get_sample(0).unwrap(), b"sample1");
         assert_eq!(loader.get_sample(2).unwrap(), b"sample3");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_sample_order() {
+        let file1 = create_test_shard(&[b"a", b"b", b"c"]);
+        let file2 = create_test_shard(&[b"d", b"e"]);
+        let paths = [file1.path(), file2.path()];
+
+        let loader = DataLoader::new(&paths).unwrap();
+        assert_eq!(loader.sample_order(3), vec![0, 1, 2, 3, 4]);
+
+        let config = DataLoaderConfig {
+            sampler: SamplerConfig {
+                shuffle: sampler::ShuffleMode::Shard,
+                seed: 9,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loader = DataLoader::with_config(&paths, config.clone()).unwrap();
+        let order = loader.sample_order(0);
+        assert_eq!(order, sampler::ShardShuffleSampler::new(vec![3, 2], 9).order(0));
+        // 別のローダーでも同じ順序になる
+        assert_eq!(DataLoader::with_config(&paths, config).unwrap().sample_order(0), order);
+    }
+
+    #[test]
+    fn test_loader_state() {
+        let file1 = create_test_shard(&[b"a", b"b", b"c"]);
+        let file2 = create_test_shard(&[b"d", b"e"]);
+        let paths = [file1.path(), file2.path()];
+        let config = |seed| DataLoaderConfig {
+            sampler: SamplerConfig {
+                shuffle: sampler::ShuffleMode::Random,
+                seed,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut loader = DataLoader::with_config(&paths, config(3)).unwrap();
+        let epoch0 = loader.sample_order(0);
+        assert_eq!(loader.next_indices(2), epoch0[..2]);
+        loader.prefetch_next(1).unwrap();
+        let state = loader.state();
+        let json = serde_json::to_string(&state).unwrap();
+
+        // 別のシードで作ったローダーでも、状態のシードで残りから続ける
+        let mut resumed = DataLoader::with_config(&paths, config(4)).unwrap();
+        resumed.restore(&serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(resumed.state(), state);
+        assert_eq!(resumed.next_indices(10), epoch0[2..]);
+        assert_eq!(loader.next_indices(10), epoch0[2..]);
+        assert_eq!(resumed.prefetch_cursor().position(), 1);
+
+        // 読み終えたら次のエポックへ進む
+        assert_eq!(resumed.next_indices(5), loader.sample_order(1));
+        assert_eq!((resumed.epoch(), resumed.position()), (1, 5));
+        resumed.set_epoch(4);
+        assert_eq!(resumed.next_indices(1), loader.sample_order(4)[..1]);
+
+        let mismatched = LoaderState { epoch_len: 4, ..state };
+        assert!(matches!(loader.restore(&mismatched), Err(DataLoaderError::InvalidState(_))));
+
+        // 差し替えたサンプラーの状態は、同じ種類のサンプラーを差し替えたローダーにだけ戻せる
+        loader.set_sampler(Box::new(sampler::RandomSampler::new(5, 7)));
+        let custom = loader.state();
+        assert_eq!((custom.sampler.as_ref(), custom.sampler_kind.as_str()), (None, "random"));
+        assert!(matches!(resumed.restore(&custom), Err(DataLoaderError::InvalidState(_))));
+        resumed.set_sampler(Box::new(sampler::SequentialSampler::new(5)));
+        assert!(matches!(resumed.restore(&custom), Err(DataLoaderError::InvalidState(_))));
+        resumed.set_sampler(Box::new(sampler::RandomSampler::new(5, 7)));
+        resumed.restore(&custom).unwrap();
+        assert_eq!(resumed.state(), custom);
+
+        // ランク0で保存した状態を読み込んでも、各ランクは自分の取り分を読む
+        let ranked = |rank, world_size, seed| DataLoaderConfig {
+            sampler: SamplerConfig {
+                rank,
+                world_size,
+                ..config(seed).sampler
+            },
+            ..Default::default()
+        };
+        let mut rank0 = DataLoader::with_config(&paths, ranked(0, 2, 3)).unwrap();
+        rank0.next_indices(1);
+        let saved = rank0.state();
+        let mut rank1 = DataLoader::with_config(&paths, ranked(1, 2, 4)).unwrap();
+        rank1.restore(&saved).unwrap();
+        let expected = DataLoader::with_config(&paths, ranked(1, 2, 3)).unwrap().sample_order(0);
+        assert_eq!(rank1.state().sampler.unwrap().rank, 1);
+        assert_eq!(rank1.next_indices(10), expected[1..]);
+        let mut other = DataLoader::with_config(&paths, ranked(0, 3, 3)).unwrap();
+        assert!(matches!(other.restore(&saved), Err(DataLoaderError::InvalidState(_))));
+    }
+
+    #[test]
+    fn test_iter_and_batches() {
+        let file1 = create_test_shard(&[b"a", b"b", b"c"]);
+        let file2 = create_test_shard(&[b"d", b"e"]);
+        let config = DataLoaderConfig {
+            sampler: SamplerConfig {
+                shuffle: sampler::ShuffleMode::Random,
+                seed: 1,
+                ..Default::default()
+            },
+            prefetch_ahead: 2,
+            ..Default::default()
+        };
+        let mut loader = DataLoader::with_config(&[file1.path(), file2.path()], config).unwrap();
+        let expected = |loader: &DataLoader, epoch| -> Vec<Vec<u8>> {
+            loader
+                .sample_order(epoch)
+                .into_iter()
+                .map(|index| loader.get_sample(index).unwrap().to_vec())
+                .collect()
+        };
+
+        // 1回のiterで1エポック分を読み、次のiterは次のエポックから
+        let epoch0 = expected(&loader, 0);
+        let samples: Vec<SampleRef> = loader.iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(samples, epoch0);
+        drop(samples);
+        assert_eq!(loader.iter().len(), 5);
+        assert_eq!(loader.epoch(), 1);
+
+        // 途中でやめた位置から続ける
+        assert_eq!(loader.batches(2, false).next().unwrap().unwrap().len(), 2);
+        let sizes: Vec<usize> = loader.batches(2, false).map(|batch| batch.unwrap().len()).collect();
+        assert_eq!(sizes, [2, 1]);
+        assert!(loader.next_batch(2, false).is_none());
+        assert_eq!(loader.epoch(), 1);
+
+        let epoch2 = expected(&loader, 2);
+        let batches: Vec<Vec<SampleRef>> = loader.batches(2, true).collect::<Result<_, _>>().unwrap();
+        assert_eq!(batches.into_iter().flatten().collect::<Vec<_>>(), epoch2[..4]);
+        assert_eq!((loader.epoch(), loader.position()), (2, 5));
+        assert_eq!((loader.num_batches(2, true), loader.num_batches(2, false)), (2, 3));
+        loader.wait_prefetch().unwrap();
+    }
+
+    #[test]
+    fn test_block_shuffle_prefetch() {
+        let files: Vec<NamedTempFile> = (0..5u8).map(|i| create_test_shard(&[&[i], &[i]])).collect();
+        let paths: Vec<&std::path::Path> = files.iter().map(|f| f.path()).collect();
+        let config = DataLoaderConfig {
+            sampler: SamplerConfig {
+                shuffle: sampler::ShuffleMode::Block,
+                seed: 5,
+                window_shards: 2,
+                shuffle_buffer: 3,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut loader = DataLoader::with_config(&paths, config).unwrap();
+        let shard_order = loader.sampler().shard_order(0).unwrap();
+
+        // プリフェッチはサンプラーのシャード順に、ウィンドウ単位で進む
+        let ticket = loader.prefetch_window().unwrap();
+        loader.wait_prefetch_ticket(ticket).unwrap();
+        assert_eq!(loader.prefetch_cursor().order(), shard_order);
+        assert_eq!(loader.prefetch_cursor().position(), 2);
+
+        loader.set_sampler(Box::new(sampler::SequentialSampler::new(10)));
+        assert_eq!(loader.prefetch_cursor().order(), [0, 1, 2, 3, 4]);
+        assert_eq!(loader.prefetch_cursor().position(), 0);
+    }
+
+    #[test]
+    fn test_prefetch_samples() {
+        let big = vec![0u8; 3 * mmap::page_size()];
+        let file1 = create_test_shard(&[b"a", b"b", &big, b"c"]);
+        let file2 = create_test_shard(&[b"d"]);
+
+        let mut loader = DataLoader::new(&[file1.path(), file2.path()]).unwrap();
+        // 隣接するサンプルは1つの範囲にまとめられ、離れたサンプルとシャードは分かれる
+        let ranges = loader.sample_ranges(&[4, 3, 0, 1]).unwrap();
+        let data_offset = format::ShardHeader::SIZE as u64;
+        assert_eq!(
+            ranges,
+            vec![
+                PrefetchRange::new(file1.path(), data_offset, 2),
+                PrefetchRange::new(file1.path(), data_offset + 2 + big.len() as u64, 1),
+                PrefetchRange::new(file2.path(), data_offset, 1),
+            ]
+        );
+
+        let ticket = loader.prefetch_samples(&[0, 2, 4]).unwrap();
+        loader.wait_prefetch_ticket(ticket).unwrap();
+        assert!(loader.poll_prefetch(ticket).unwrap());
+        loader.wait_prefetch().unwrap();
+        assert!(loader.prefetch_samples(&[5]).is_err());
+
+        let ticket = loader.prefetch_next(5).unwrap();
+        loader.wait_prefetch_ticket(ticket).unwrap();
+        assert_eq!(loader.prefetch_cursor().position(), 2);
+        // 2エポック目の先頭に戻って続ける
+        loader.prefetch_next(1).unwrap();
+        assert_eq!((loader.prefetch_cursor().epoch(), loader.prefetch_cursor().position()), (1, 1));
+
+        loader.set_prefetch_order(|epoch| if epoch == 0 { vec![1] } else { vec![1, 0] });
+        loader.prefetch_next(1).unwrap();
+        loader.prefetch_next(1).unwrap();
+        assert_eq!(loader.prefetch_cursor().order(), [1, 0]);
+        loader.reset_prefetch();
+        assert_eq!(loader.prefetch_cursor().order(), [1]);
+        loader.set_prefetch_order(|_| vec![5]);
+        assert!(loader.prefetch_next(1).is_err());
+        loader.wait_prefetch().unwrap();
+
+        // 書き込んだばかりのシャードはページキャッシュに載っている
+        assert!(loader.is_resident(1).unwrap());
+        // RLIMIT_MEMLOCK の小さい環境では固定できないことがある
+        if loader.lock_shard(1).is_ok() {
+            loader.unlock_shard(1).unwrap();
+        }
+        assert!(matches!(loader.lock_shard(2), Err(DataLoaderError::ShardOutOfBounds(2))));
+        assert!(matches!(loader.is_resident(2), Err(DataLoaderError::ShardOutOfBounds(2))));
+        assert!(matches!(loader.unlock_shard(2), Err(DataLoaderError::ShardOutOfBounds(2))));
+    }
+
+    #[test]
+    fn test_lazy_residency() {
+        let page = vec![3u8; mmap::page_size()];
+        let file1 = create_test_shard(&[&page]);
+        let file2 = create_test_shard(&[&page]);
+        let config = DataLoaderConfig {
+            reader: ReaderConfig {
+                max_open_shards: Some(1),
+                ..Default::default()
+            },
+            evict_window: Some(0),
+            ..Default::default()
+        };
+        let mut loader = DataLoader::with_config(&[file1.path(), file2.path()], config).unwrap();
+
+        // 開いていないシャードは確認や追い出しのために開かない
+        assert!(!loader.is_resident(0).unwrap());
+        loader.unlock_shard(1).unwrap();
+        loader.mark_consumed(0).unwrap();
+        assert_eq!(loader.reader.open_shards(), 0);
+
+        assert_eq!(loader.get_sample(1).unwrap(), page.as_slice());
+        assert!(loader.is_resident(1).unwrap());
+        loader.mark_consumed(1).unwrap();
+        assert_eq!(loader.reader.open_shards(), 1);
+    }
+
+    #[test]
+    fn test_evict_consumed() {
+        let page = vec![9u8; mmap::page_size()];
+        let file = create_test_shard(&[&page, &page, &page, &page]);
+        let config = DataLoaderConfig {
+            evict_window: Some(page.len() as u64),
+            ..Default::default()
+        };
+        let mut loader = DataLoader::with_config(&[file.path()], config).unwrap();
+        for index in 0..4 {
+            assert_eq!(loader.get_sample(index).unwrap(), page.as_slice());
+            loader.mark_consumed(index).unwrap();
+        }
+        assert_eq!(loader.eviction.as_ref().unwrap().retained(), page.len() as u64);
+        // 追い出した後も読み直せる
+        assert_eq!(loader.get_sample(0).unwrap(), page.as_slice());
+        assert!(loader.mark_consumed(4).is_err());
+
+        // イテレーターで読んだサンプルも読み終えたものとして扱う
+        let config = DataLoaderConfig {
+            evict_window: Some(page.len() as u64),
+            ..Default::default()
+        };
+        let mut loader = DataLoader::with_config(&[file.path()], config).unwrap();
+        assert_eq!(loader.iter().filter(|sample| sample.as_ref().unwrap() == &page).count(), 4);
+        assert_eq!(loader.eviction.as_ref().unwrap().retained(), page.len() as u64);
+    }
+
+    #[test]
+    fn test_iter_ignores_prefetch_errors() {
+        let file = create_test_shard(&[b"sample1", b"sample2", b"sample3"]);
+        // 先読みの範囲はエポックの途中からでもエポックの終わりで止まる
+        let config = DataLoaderConfig {
+            prefetch_ahead: usize::MAX,
+            ..Default::default()
+        };
+        let mut loader = DataLoader::with_config(&[file.path()], config).unwrap();
+        loader.next_indices(1);
+        assert_eq!(loader.iter().count(), 2);
+
+        let config = DataLoaderConfig {
+            prefetch_ahead: 2,
+            ..Default::default()
+        };
+        let mut loader = DataLoader::with_config(&[file.path()], config).unwrap();
+        // マップは残るが、プリフェッチはファイルを開き直せずに失敗する
+        std::fs::remove_file(file.path()).unwrap();
+        assert!(loader.prefetch_samples(&[0]).is_err());
+        assert_eq!(loader.iter().map(Result::unwrap).collect::<Vec<_>>(), [b"sample1", b"sample2", b"sample3"]);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_decoded_samples() {
+        let file = NamedTempFile::new().unwrap();
+        let config = crate::format::WriterConfig {
+            codec: Codec::Lz4,
+            ..Default::default()
+        };
+        let compressible = b"pixel".repeat(100);
+        let mut writer = ShardWriter::with_config(file.path(), config).unwrap();
+        writer.append(&compressible).unwrap();
+        writer.append(b"raw").unwrap();
+        writer.finish().unwrap();
+
+        let loader = DataLoader::new(&[file.path()]).unwrap();
+        let decoded = loader.get_sample_decoded(0).unwrap();
+        assert!(!decoded.is_zero_copy());
+        assert_eq!(&*decoded, compressible.as_slice());
+
+        let raw = loader.get_sample_decoded(1).unwrap();
+        assert!(raw.is_zero_copy());
+        assert_eq!(&*raw, b"raw");
+    }
+}
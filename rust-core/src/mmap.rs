@@ -9,11 +9,49 @@ pub enum MmapError {
     OpenFile(#[from] std::io::Error),
     #[error("Failed to create memory map: {0}")]
     MapError(String),
+    #[error("Failed to read file: {0}")]
+    Read(std::io::Error),
+}
+
+/// システムのページサイズを取得
+pub fn page_size() -> usize {
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if size > 0 {
+        size as usize
+    } else {
+        4096
+    }
+}
+
+/// マップ全体に `madvise` で伝えるアクセスパターン
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccessPattern {
+    /// 指定しない（カーネルの既定の先読み）
+    #[default]
+    Normal,
+    /// `MADV_SEQUENTIAL`（積極的に先読みし、読み終えたページを早めに解放する）
+    Sequential,
+    /// `MADV_RANDOM`（先読みしない）
+    Random,
+}
+
+/// メモリマップの作成オプション
+///
+/// Linux以外では `populate` と `huge_pages` は無視される。
+#[derive(Debug, Clone, Default)]
+pub struct MmapConfig {
+    /// `MAP_POPULATE` でマップ時にページを読み込んでおく
+    pub populate: bool,
+    /// `MADV_HUGEPAGE` で透過的ヒュージページを使う
+    pub huge_pages: bool,
+    /// アクセスパターン
+    pub access: AccessPattern,
+    /// `mlock` でページをメモリに固定する（頻繁に読むシャード向け。`RLIMIT_MEMLOCK` に注意）
+    pub lock: bool,
 }
 
 /// メモリマップされたファイルを管理する
 pub struct MmapManager {
-    #[allow(dead_code)] // ファイルを開いたまま保持するため
     file: File,
     mmap: Mmap,
     path: PathBuf,
@@ -22,18 +60,74 @@ pub struct MmapManager {
 impl MmapManager {
     /// ファイルを開いてメモリマップを作成
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, MmapError> {
+        Self::with_config(path, &MmapConfig::default())
+    }
+
+    /// オプションを指定してファイルを開き、メモリマップを作成
+    pub fn with_config<P: AsRef<Path>>(path: P, config: &MmapConfig) -> Result<Self, MmapError> {
         let path_buf = path.as_ref().to_path_buf();
         let file = File::open(&path_buf)?;
+        let mut options = MmapOptions::new();
+        if config.populate {
+            options.populate();
+        }
         let mmap = unsafe {
-            MmapOptions::new()
+            options
                 .map(&file)
                 .map_err(|e| MmapError::MapError(e.to_string()))?
         };
-        Ok(Self {
+        let manager = Self {
             file,
             mmap,
             path: path_buf,
-        })
+        };
+        if !manager.is_empty() {
+            manager.apply(config)?;
+        }
+        Ok(manager)
+    }
+
+    #[cfg(unix)]
+    fn apply(&self, config: &MmapConfig) -> Result<(), MmapError> {
+        let advise = |advice| {
+            self.mmap
+                .advise(advice)
+                .map_err(|e| MmapError::MapError(format!("madvise failed: {}", e)))
+        };
+        #[cfg(target_os = "linux")]
+        if config.huge_pages {
+            advise(memmap2::Advice::HugePage)?;
+        }
+        match config.access {
+            AccessPattern::Normal => {}
+            AccessPattern::Sequential => advise(memmap2::Advice::Sequential)?,
+            AccessPattern::Random => advise(memmap2::Advice::Random)?,
+        }
+        if config.lock {
+            self.lock()?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn apply(&self, _config: &MmapConfig) -> Result<(), MmapError> {
+        Ok(())
+    }
+
+    /// ページをメモリに固定する（`mlock`）
+    #[cfg(unix)]
+    pub fn lock(&self) -> Result<(), MmapError> {
+        self.mmap
+            .lock()
+            .map_err(|e| MmapError::MapError(format!("mlock failed: {}", e)))
+    }
+
+    /// ページの固定を解除する（`munlock`）
+    #[cfg(unix)]
+    pub fn unlock(&self) -> Result<(), MmapError> {
+        self.mmap
+            .unlock()
+            .map_err(|e| MmapError::MapError(format!("munlock failed: {}", e)))
     }
 
     /// メモリマップされたデータへのスライス参照を取得
@@ -57,11 +151,92 @@ impl MmapManager {
         Ok(&self.mmap[offset..end])
     }
 
+    /// 指定された範囲に `MADV_WILLNEED` を発行し、ページの先読みを依頼する
+    #[cfg(unix)]
+    pub fn advise_willneed(&self, offset: usize, len: usize) -> Result<(), MmapError> {
+        self.get_range(offset, len)?;
+        if len == 0 {
+            return Ok(());
+        }
+        self.mmap
+            .advise_range(memmap2::Advice::WillNeed, offset, len)
+            .map_err(|e| MmapError::MapError(e.to_string()))
+    }
+
+    /// 指定された範囲のページをこのマップとページキャッシュから追い出す
+    ///
+    /// `MADV_DONTNEED` でマップから外し、Linuxでは `POSIX_FADV_DONTNEED` でページキャッシュからも
+    /// 落とす。範囲に完全に含まれるページだけが対象になる。読み取り専用の共有マップなので、
+    /// 追い出した後に読んでもファイルから読み直されるだけで内容は変わらない。
+    #[cfg(unix)]
+    pub fn evict(&self, offset: usize, len: usize) -> Result<(), MmapError> {
+        self.get_range(offset, len)?;
+        let page = page_size();
+        let start = offset.div_ceil(page) * page;
+        let end = (offset + len) / page * page;
+        if start >= end {
+            return Ok(());
+        }
+        unsafe {
+            self.mmap
+                .unchecked_advise_range(memmap2::UncheckedAdvice::DontNeed, start, end - start)
+                .map_err(|e| MmapError::MapError(e.to_string()))?;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            let ret = unsafe {
+                libc::posix_fadvise(
+                    self.file.as_raw_fd(),
+                    start as libc::off_t,
+                    (end - start) as libc::off_t,
+                    libc::POSIX_FADV_DONTNEED,
+                )
+            };
+            if ret != 0 {
+                return Err(MmapError::MapError(std::io::Error::from_raw_os_error(ret).to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 指定された範囲のページのうち、ページキャッシュに載っている数と総数を返す（`mincore`）
+    #[cfg(unix)]
+    pub fn resident_pages(&self, offset: usize, len: usize) -> Result<(usize, usize), MmapError> {
+        self.get_range(offset, len)?;
+        if len == 0 {
+            return Ok((0, 0));
+        }
+        // マップの先頭はページ境界にあるので、範囲をページ境界に広げる
+        let page = page_size();
+        let start = offset / page * page;
+        let end = offset + len;
+        let pages = (end - start).div_ceil(page);
+        let mut vec = vec![0u8; pages];
+        let ret = unsafe {
+            libc::mincore(
+                self.mmap.as_ptr().add(start) as *mut libc::c_void,
+                end - start,
+                vec.as_mut_ptr() as *mut _,
+            )
+        };
+        if ret != 0 {
+            return Err(MmapError::MapError(std::io::Error::last_os_error().to_string()));
+        }
+        Ok((vec.iter().filter(|&&v| v & 1 != 0).count(), pages))
+    }
+
     /// ファイルパスを取得
     pub fn path(&self) -> &Path {
         &self.path
     }
 
+    /// マップ元のファイルを取得
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
     /// ファイルサイズを取得
     pub fn len(&self) -> usize {
         self.mmap.len()
@@ -73,20 +248,91 @@ impl MmapManager {
     }
 }
 
+/// 順次読み込みで読み終えた範囲を、直近の一定バイト数だけ残して追い出す対象を決める
+///
+/// 範囲は (ファイル番号, オフセット, 長さ) で渡す。同じファイルで隣接する範囲はまとめて扱う。
+#[derive(Debug, Clone)]
+pub struct EvictionWindow {
+    window: u64,
+    consumed: std::collections::VecDeque<(usize, u64, u64)>, // (ファイル番号, 開始, 終了)
+    retained: u64,
+}
+
+impl EvictionWindow {
+    /// 直近 `window` バイトを残すウィンドウを作成
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            consumed: std::collections::VecDeque::new(),
+            retained: 0,
+        }
+    }
+
+    /// 読み終えた範囲を追加し、ウィンドウからはみ出して追い出すべき範囲を返す
+    pub fn push(&mut self, file: usize, offset: u64, len: u64) -> Vec<(usize, u64, u64)> {
+        let end = offset + len;
+        match self.consumed.back_mut() {
+            Some((last, _, last_end)) if *last == file && offset <= *last_end => {
+                self.retained += end.saturating_sub(*last_end);
+                *last_end = (*last_end).max(end);
+            }
+            _ => {
+                self.consumed.push_back((file, offset, end));
+                self.retained += len;
+            }
+        }
+
+        let mut evicted = Vec::new();
+        while self.retained > self.window {
+            let Some((file, start, end)) = self.consumed.front_mut() else {
+                break;
+            };
+            let excess = (self.retained - self.window).min(*end - *start);
+            evicted.push((*file, *start, excess));
+            *start += excess;
+            self.retained -= excess;
+            if *start == *end {
+                self.consumed.pop_front();
+            }
+        }
+        evicted
+    }
+
+    /// 記録をすべて捨てる（シークした後など）
+    pub fn clear(&mut self) {
+        self.consumed.clear();
+        self.retained = 0;
+    }
+
+    /// 残しているバイト数
+    pub fn retained(&self) -> u64 {
+        self.retained
+    }
+}
+
 /// 複数のメモリマップを管理する
 pub struct MmapPool {
     maps: Vec<MmapManager>,
+    config: MmapConfig,
 }
 
 impl MmapPool {
     pub fn new() -> Self {
-        Self { maps: Vec::new() }
+        Self::with_config(MmapConfig::default())
+    }
+
+    /// 追加するすべてのマップに使うオプションを指定して作成
+    pub fn with_config(config: MmapConfig) -> Self {
+        Self {
+            maps: Vec::new(),
+            config,
+        }
     }
 
     /// ファイルを追加してメモリマップを作成
     pub fn add<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, MmapError> {
         let index = self.maps.len();
-        let manager = MmapManager::new(path)?;
+        let manager = MmapManager::with_config(path, &self.config)?;
         self.maps.push(manager);
         Ok(index)
     }
@@ -153,6 +399,68 @@ mod tests {
         assert_eq!(slice, b"Hello");
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_resident_pages() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&vec![1u8; 3 * page_size()]).unwrap();
+        file.flush().unwrap();
+
+        let manager = MmapManager::new(file.path()).unwrap();
+        // 読んだページは必ずページキャッシュに載っている
+        let sum: u64 = manager.as_slice().iter().map(|&b| b as u64).sum();
+        assert_eq!(sum, 3 * page_size() as u64);
+        assert_eq!(manager.resident_pages(0, manager.len()).unwrap(), (3, 3));
+        assert_eq!(manager.resident_pages(page_size() + 1, 10).unwrap(), (1, 1));
+        assert!(manager.resident_pages(0, manager.len() + 1).is_err());
+
+        // 追い出した後も内容は変わらない
+        manager.evict(1, 2 * page_size()).unwrap();
+        assert!(manager.as_slice().iter().all(|&b| b == 1));
+        assert!(manager.evict(0, manager.len() + 1).is_err());
+    }
+
+    #[test]
+    fn test_eviction_window() {
+        let mut window = EvictionWindow::new(100);
+        assert!(window.push(0, 0, 60).is_empty());
+        assert!(window.push(0, 60, 40).is_empty());
+        // 直近100バイトを残して先頭から追い出す
+        assert_eq!(window.push(0, 100, 30), [(0, 0, 30)]);
+        assert_eq!(window.push(1, 0, 150), [(0, 30, 100), (1, 0, 50)]);
+        assert_eq!(window.retained(), 100);
+        window.clear();
+        assert!(window.push(1, 150, 10).is_empty());
+    }
+
+    #[test]
+    fn test_mmap_config() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&vec![5u8; 2 * page_size()]).unwrap();
+        file.flush().unwrap();
+
+        for access in [AccessPattern::Normal, AccessPattern::Sequential, AccessPattern::Random] {
+            let config = MmapConfig {
+                populate: true,
+                access,
+                ..Default::default()
+            };
+            let manager = MmapManager::with_config(file.path(), &config).unwrap();
+            assert_eq!(manager.get_range(page_size(), 1).unwrap(), [5]);
+        }
+
+        // 空のファイルにはオプションを適用しない
+        let empty = NamedTempFile::new().unwrap();
+        let config = MmapConfig {
+            lock: true,
+            access: AccessPattern::Random,
+            ..Default::default()
+        };
+        let mut pool = MmapPool::with_config(config);
+        pool.add(empty.path()).unwrap();
+        assert!(pool.get(0).unwrap().is_empty());
+    }
+
     #[test]
     fn test_mmap_pool() {
         let mut file1 = NamedTempFile::new().unwrap();
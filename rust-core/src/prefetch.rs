@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -11,123 +12,980 @@ pub enum PrefetchError {
     Prefetch(String),
 }
 
+/// プリフェッチの実装方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefetchBackend {
+    /// io_uringの `IORING_OP_FADVISE`（Linux、`io_uring` フィーチャー）
+    IoUring,
+    /// `posix_fadvise` / `madvise` の `WILLNEED`
+    Fadvise,
+    /// ワーカースレッドからの読み込み（ネットワークファイルシステムやFUSE向け）
+    ThreadPool,
+    /// 何もしない
+    NoOp,
+}
+
+impl PrefetchBackend {
+    /// 表示用の名前
+    pub fn name(self) -> &'static str {
+        match self {
+            PrefetchBackend::IoUring => "io_uring",
+            PrefetchBackend::Fadvise => "fadvise",
+            PrefetchBackend::ThreadPool => "thread_pool",
+            PrefetchBackend::NoOp => "noop",
+        }
+    }
+
+    /// 名前から実装方式を取得
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::IoUring, Self::Fadvise, Self::ThreadPool, Self::NoOp]
+            .into_iter()
+            .find(|backend| backend.name() == name)
+    }
+}
+
+/// プリフェッチャーの設定
+#[derive(Debug, Clone)]
+pub struct PrefetchConfig {
+    /// 使う実装方式（`None` なら io_uring → fadvise → no-op の順に自動選択）
+    pub backend: Option<PrefetchBackend>,
+    /// io_uringのキュー深度
+    pub queue_depth: u32,
+    /// スレッドプールのワーカー数
+    pub threads: usize,
+    /// スレッドプールで同時に読み込む最大バイト数
+    pub max_in_flight_bytes: u64,
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            queue_depth: 32,
+            threads: 4,
+            max_in_flight_bytes: 64 << 20,
+        }
+    }
+}
+
+impl std::fmt::Display for PrefetchBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// プリフェッチするファイル内の範囲
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefetchRange {
+    pub path: PathBuf,
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl PrefetchRange {
+    pub fn new<P: Into<PathBuf>>(path: P, offset: u64, len: u64) -> Self {
+        Self {
+            path: path.into(),
+            offset,
+            len,
+        }
+    }
+}
+
+/// プリフェッチ要求1回分を識別するチケット
+///
+/// `Prefetcher::poll` / `Prefetcher::wait_ticket` で個別に完了を確認できる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PrefetchTicket(u64);
+
+impl PrefetchTicket {
+    /// 数値表現から復元する（言語バインディング向け）
+    pub fn from_id(id: u64) -> Self {
+        Self(id)
+    }
+
+    /// 数値表現を取得
+    pub fn id(self) -> u64 {
+        self.0
+    }
+}
+
+/// チケットごとの未完了の要求数と失敗を記録する
+///
+/// 失敗せずに完了したチケットはその時点で片付け、失敗したチケットだけを
+/// 結果が受け取られるまで残す。
+#[derive(Debug, Default)]
+struct TicketTracker {
+    next: u64,
+    pending: HashMap<u64, (usize, Vec<String>)>,
+}
+
+impl TicketTracker {
+    /// `requests` 個の要求からなるチケットを発行する
+    fn issue(&mut self, requests: usize) -> PrefetchTicket {
+        let ticket = PrefetchTicket(self.next);
+        self.next += 1;
+        if requests > 0 {
+            self.pending.insert(ticket.0, (requests, Vec::new()));
+        }
+        ticket
+    }
+
+    /// チケットの要求を1つ完了させる
+    fn complete(&mut self, ticket: PrefetchTicket, error: Option<String>) {
+        if let Some((remaining, errors)) = self.pending.get_mut(&ticket.0) {
+            *remaining = remaining.saturating_sub(1);
+            errors.extend(error);
+            // 記録のないチケットは成功として扱われるので、ポーリングされなくても残さない
+            if *remaining == 0 && errors.is_empty() {
+                self.pending.remove(&ticket.0);
+            }
+        }
+    }
+
+    fn is_done(&self, ticket: PrefetchTicket) -> bool {
+        self.pending.get(&ticket.0).is_none_or(|(remaining, _)| *remaining == 0)
+    }
+
+    /// 完了していればチケットを片付けて結果を返す（未完了なら `None`）
+    ///
+    /// 結果を受け取り済みのチケットは成功として扱う。
+    fn take(&mut self, ticket: PrefetchTicket, what: &str) -> Option<Result<(), PrefetchError>> {
+        if ticket.0 >= self.next {
+            return Some(Err(PrefetchError::Prefetch(format!("Unknown prefetch ticket: {}", ticket.0))));
+        }
+        if !self.is_done(ticket) {
+            return None;
+        }
+        let errors = self.pending.remove(&ticket.0).map(|(_, errors)| errors).unwrap_or_default();
+        Some(failures(what, errors))
+    }
+
+    /// すべてのチケットを片付け、失敗をまとめて返す
+    fn take_all(&mut self, what: &str) -> Result<(), PrefetchError> {
+        let errors = self.pending.drain().flat_map(|(_, (_, errors))| errors).collect();
+        failures(what, errors)
+    }
+}
+
+fn failures(what: &str, errors: Vec<String>) -> Result<(), PrefetchError> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(PrefetchError::Prefetch(format!(
+        "{} {} failed: {}",
+        errors.len(),
+        what,
+        errors.join("; ")
+    )))
+}
+
+/// エポックごとのシャードの読み込み順を返す関数
+pub type ShardOrderFn = Box<dyn Fn(u64) -> Vec<usize> + Send + Sync>;
+
+/// シャードの読み込み順をエポックをまたいで追うプリフェッチカーソル
+///
+/// エポックの終わりに達すると次のエポックの順序を求めて先頭から続ける。
+/// 順序は関数で与えるので、エポックごとに変わるサンプラーの順序にも追従できる。
+pub struct PrefetchCursor {
+    order_fn: ShardOrderFn,
+    order: Vec<usize>,
+    epoch: u64,
+    position: usize,
+}
+
+impl PrefetchCursor {
+    /// ファイル順に `num_shards` 個のシャードを巡るカーソルを作成
+    pub fn new(num_shards: usize) -> Self {
+        Self::with_order(Box::new(move |_| (0..num_shards).collect()))
+    }
+
+    /// エポックごとの順序を指定してカーソルを作成
+    pub fn with_order(order_fn: ShardOrderFn) -> Self {
+        let order = order_fn(0);
+        Self {
+            order_fn,
+            order,
+            epoch: 0,
+            position: 0,
+        }
+    }
+
+    /// 次にプリフェッチする最大 `count` 個のシャードを返してカーソルを進める
+    ///
+    /// 1回の呼び出しで同じエポックのシャードを重複して返さないよう、
+    /// `count` は1エポックのシャード数までに抑える。
+    pub fn advance(&mut self, count: usize) -> Vec<usize> {
+        let count = count.min(self.order.len());
+        let mut shards = Vec::with_capacity(count);
+        while shards.len() < count {
+            if self.position >= self.order.len() {
+                self.seek(self.epoch + 1, 0);
+                if self.order.is_empty() {
+                    break;
+                }
+            }
+            shards.push(self.order[self.position]);
+            self.position += 1;
+        }
+        shards
+    }
+
+    /// 指定したエポックの指定位置へ移動する
+    pub fn seek(&mut self, epoch: u64, position: usize) {
+        if epoch != self.epoch {
+            self.order = (self.order_fn)(epoch);
+            self.epoch = epoch;
+        }
+        self.position = position.min(self.order.len());
+    }
+
+    /// 最初のエポックの先頭に戻す
+    pub fn reset(&mut self) {
+        self.order = (self.order_fn)(0);
+        self.epoch = 0;
+        self.position = 0;
+    }
+
+    /// 現在のエポック
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// 現在のエポック内で次にプリフェッチする位置
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// 現在のエポックのシャードの順序
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+}
+
+impl std::fmt::Debug for PrefetchCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PrefetchCursor")
+            .field("order", &self.order)
+            .field("epoch", &self.epoch)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
 /// プリフェッチトレイト（プラットフォーム非依存のインターフェース）
 pub trait Prefetcher: Send + Sync {
     /// 次のN個のファイルをプリフェッチ
-    fn prefetch_files(&mut self, paths: &[PathBuf]) -> Result<(), PrefetchError>;
+    fn prefetch_files(&mut self, paths: &[PathBuf]) -> Result<PrefetchTicket, PrefetchError>;
+
+    /// ファイル内の範囲をプリフェッチ
+    fn prefetch_ranges(&mut self, ranges: &[PrefetchRange]) -> Result<PrefetchTicket, PrefetchError>;
+
+    /// チケットの要求が完了したかどうか（完了していれば失敗もここで返す）
+    fn poll(&mut self, ticket: PrefetchTicket) -> Result<bool, PrefetchError>;
 
-    /// プリフェッチの完了を待つ
+    /// チケットの要求の完了を待つ
+    fn wait_ticket(&mut self, ticket: PrefetchTicket) -> Result<(), PrefetchError>;
+
+    /// すべてのプリフェッチの完了を待つ
     fn wait(&mut self) -> Result<(), PrefetchError>;
+
+    /// 実装方式を取得
+    fn backend(&self) -> PrefetchBackend;
 }
 
 #[cfg(all(target_os = "linux", feature = "io_uring"))]
 mod linux {
     use super::*;
-    use io_uring::IoUring;
+    use io_uring::{opcode, types, IoUring, Probe};
+    use std::collections::VecDeque;
     use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+
+    /// 1回の先読み要求で扱う最大バイト数
+    const CHUNK_SIZE: u64 = 8 << 20;
+
+    /// 先読み中のファイル（同じパスへの要求で共有し、最後の要求が完了したら閉じる）
+    struct OpenFile {
+        path: PathBuf,
+        file: File,
+        requests: usize,
+    }
+
+    /// 先読み要求（開いたファイルの番号と範囲）
+    struct ReadAhead {
+        file: usize,
+        ticket: PrefetchTicket,
+        offset: u64,
+        len: u64,
+    }
 
     /// io_uringを使ったプリフェッチャー（Linux専用）
     ///
-    /// 注意: io_uringの完全な実装は複雑なため、ここでは基本的な構造のみを提供します。
-    /// 実際のプロダクション使用では、バッファのライフタイム管理と
-    /// 適切な完了処理が必要です。
+    /// ファイルの範囲ごとに `IORING_OP_FADVISE`（`POSIX_FADV_WILLNEED`）を発行し、
+    /// カーネルにページキャッシュへの先読みを依頼する。同時に発行する要求は
+    /// キュー深度までに抑え、残りは完了に合わせて順次発行する。
     pub struct IoUringPrefetcher {
-        #[allow(dead_code)] // 将来の実装のために保持
         ring: IoUring,
-        pending_ops: usize,
-        open_files: Vec<File>, // ファイルを開いたまま保持
+        queue_depth: usize,
+        queued: VecDeque<ReadAhead>,
+        in_flight: HashMap<u64, ReadAhead>, // user_data → 発行済みの要求
+        next_request: u64,
+        open_files: Vec<Option<OpenFile>>, // 要求が残っている間だけ開いておく（空きは再利用）
+        tickets: TicketTracker,
     }
 
     impl IoUringPrefetcher {
         pub fn new(queue_depth: u32) -> Result<Self, PrefetchError> {
+            let queue_depth = queue_depth.max(1);
             let ring = IoUring::new(queue_depth)
                 .map_err(|e| PrefetchError::Prefetch(format!("Failed to create io_uring: {}", e)))?;
+
+            // FADVISEに対応していないカーネル（5.6未満）では使わない
+            let mut probe = Probe::new();
+            ring.submitter()
+                .register_probe(&mut probe)
+                .map_err(|e| PrefetchError::Prefetch(format!("Failed to probe io_uring: {}", e)))?;
+            if !probe.is_supported(opcode::Fadvise::CODE) {
+                return Err(PrefetchError::NotSupported);
+            }
+
             Ok(Self {
                 ring,
-                pending_ops: 0,
+                queue_depth: queue_depth as usize,
+                queued: VecDeque::new(),
+                in_flight: HashMap::new(),
+                next_request: 0,
                 open_files: Vec::new(),
+                tickets: TicketTracker::default(),
             })
         }
 
-        pub fn prefetch_files(&mut self, paths: &[PathBuf]) -> Result<(), PrefetchError> {
-            // io_uringの実装は複雑で、バッファのライフタイム管理が必要です。
-            // 現在の実装では、ファイルを開いてOSのページキャッシュに
-            // プリロードするだけの簡略版とします。
-            // 実際のio_uring操作は、より高度な実装が必要です。
-
+        pub fn prefetch_files(&mut self, paths: &[PathBuf]) -> Result<PrefetchTicket, PrefetchError> {
+            let mut requests = Vec::new();
             for path in paths {
-                let file = File::open(path)?;
-                // ファイルを開くことで、OSがページキャッシュに読み込む可能性がある
-                self.open_files.push(file);
-                self.pending_ops += 1;
+                let index = self.open(path)?;
+                let len = self.file(index).file.metadata()?.len();
+                requests.push((index, 0, len));
+            }
+            self.queue(requests)
+        }
+
+        pub fn prefetch_ranges(&mut self, ranges: &[PrefetchRange]) -> Result<PrefetchTicket, PrefetchError> {
+            let mut requests = Vec::new();
+            for range in ranges {
+                requests.push((self.open(&range.path)?, range.offset, range.len));
+            }
+            self.queue(requests)
+        }
+
+        /// (ファイル番号, オフセット, 長さ) の列を1枚のチケットとして発行する
+        fn queue(&mut self, requests: Vec<(usize, u64, u64)>) -> Result<PrefetchTicket, PrefetchError> {
+            let chunks: usize = requests.iter().map(|&(_, _, len)| len.div_ceil(CHUNK_SIZE) as usize).sum();
+            let ticket = self.tickets.issue(chunks);
+            for (index, offset, len) in requests {
+                self.queue_range(index, ticket, offset, len);
+            }
+            self.close_idle();
+            self.submit()?;
+            Ok(ticket)
+        }
+
+        /// パスのファイルを開く（要求が残っているファイルがあれば共有する）
+        fn open(&mut self, path: &Path) -> Result<usize, PrefetchError> {
+            if let Some(index) = self
+                .open_files
+                .iter()
+                .position(|open| open.as_ref().is_some_and(|open| open.path == path))
+            {
+                return Ok(index);
+            }
+            let open = OpenFile {
+                path: path.to_path_buf(),
+                file: File::open(path)?,
+                requests: 0,
+            };
+            match self.open_files.iter().position(Option::is_none) {
+                Some(index) => {
+                    self.open_files[index] = Some(open);
+                    Ok(index)
+                }
+                None => {
+                    self.open_files.push(Some(open));
+                    Ok(self.open_files.len() - 1)
+                }
+            }
+        }
+
+        fn file(&self, index: usize) -> &OpenFile {
+            // 要求が残っている間は閉じないので、発行した番号は有効
+            self.open_files[index].as_ref().unwrap()
+        }
+
+        /// 要求が残っていないファイルを閉じる
+        fn close_idle(&mut self) {
+            for open in &mut self.open_files {
+                if open.as_ref().is_some_and(|open| open.requests == 0) {
+                    *open = None;
+                }
+            }
+        }
+
+        /// 開いているファイル数
+        pub fn open_files(&self) -> usize {
+            self.open_files.iter().flatten().count()
+        }
+
+        /// ファイルの範囲を先読み要求に分割してキューに積む
+        fn queue_range(&mut self, index: usize, ticket: PrefetchTicket, offset: u64, len: u64) {
+            let end = offset + len;
+            let mut chunk_start = offset;
+            while chunk_start < end {
+                let chunk_len = CHUNK_SIZE.min(end - chunk_start);
+                if let Some(open) = &mut self.open_files[index] {
+                    open.requests += 1;
+                }
+                self.queued.push_back(ReadAhead {
+                    file: index,
+                    ticket,
+                    offset: chunk_start,
+                    len: chunk_len,
+                });
+                chunk_start += chunk_len;
             }
+        }
+
+        /// キュー深度の空きの分だけ要求を発行する
+        fn submit(&mut self) -> Result<(), PrefetchError> {
+            self.reap();
+            let mut pushed = 0;
+            while self.in_flight.len() < self.queue_depth {
+                let Some(request) = self.queued.pop_front() else {
+                    break;
+                };
+                let fd = types::Fd(self.file(request.file).file.as_raw_fd());
+                let entry = opcode::Fadvise::new(fd, request.len as libc::off_t, libc::POSIX_FADV_WILLNEED)
+                    .offset(request.offset)
+                    .build()
+                    .user_data(self.next_request);
+                // ファイルは完了を回収するまで open_files に保持される
+                if unsafe { self.ring.submission().push(&entry) }.is_err() {
+                    self.queued.push_front(request);
+                    break;
+                }
+                self.in_flight.insert(self.next_request, request);
+                self.next_request += 1;
+                pushed += 1;
+            }
+            if pushed > 0 {
+                self.ring.submit()?;
+            }
+            Ok(())
+        }
 
-            // 実際のio_uring操作は、バッファ管理と完了処理が必要なため、
-            // ここでは簡略化しています。
-            // 完全な実装では、以下のような処理が必要です:
-            // 1. バッファの確保とライフタイム管理
-            // 2. Submission Queueへのエントリ追加
-            // 3. submit()の呼び出し
-            // 4. Completion Queueからの完了確認
+        /// 完了キューに届いている完了をすべて回収する
+        fn reap(&mut self) {
+            for cqe in self.ring.completion() {
+                let Some(request) = self.in_flight.remove(&cqe.user_data()) else {
+                    continue;
+                };
+                let error = (cqe.result() < 0).then(|| {
+                    let err = std::io::Error::from_raw_os_error(-cqe.result());
+                    match &self.open_files[request.file] {
+                        Some(open) => format!("{}: {}", open.path.display(), err),
+                        None => err.to_string(),
+                    }
+                });
+                self.tickets.complete(request.ticket, error);
+                // そのファイルへの最後の要求が完了したら閉じる
+                let open = &mut self.open_files[request.file];
+                if let Some(file) = open {
+                    file.requests -= 1;
+                    if file.requests == 0 {
+                        *open = None;
+                    }
+                }
+            }
+        }
 
+        /// 1つ以上の完了を待って回収する
+        fn wait_one(&mut self) -> Result<(), PrefetchError> {
+            self.submit()?;
+            if !self.in_flight.is_empty() {
+                self.ring.submit_and_wait(1)?;
+            }
+            self.reap();
             Ok(())
         }
 
+        pub fn poll(&mut self, ticket: PrefetchTicket) -> Result<bool, PrefetchError> {
+            self.submit()?;
+            self.reap();
+            match self.tickets.take(ticket, "readahead request(s)") {
+                Some(result) => result.map(|_| true),
+                None => Ok(false),
+            }
+        }
+
+        pub fn wait_ticket(&mut self, ticket: PrefetchTicket) -> Result<(), PrefetchError> {
+            loop {
+                if let Some(result) = self.tickets.take(ticket, "readahead request(s)") {
+                    return result;
+                }
+                self.wait_one()?;
+            }
+        }
+
         pub fn wait(&mut self) -> Result<(), PrefetchError> {
-            if self.pending_ops == 0 {
-                return Ok(());
+            while !self.in_flight.is_empty() || !self.queued.is_empty() {
+                self.wait_one()?;
             }
+            self.tickets.take_all("readahead request(s)")
+        }
 
-            // 実際の実装では、Completion Queueから完了を待つ必要があります
-            // ここでは簡略化のため、ファイルを開いただけで完了とみなします
-            self.pending_ops = 0;
-            self.open_files.clear();
-            Ok(())
+        /// 発行済みで未完了の要求数
+        pub fn in_flight(&self) -> usize {
+            self.in_flight.len()
+        }
+    }
+
+    impl Drop for IoUringPrefetcher {
+        fn drop(&mut self) {
+            // 発行済みの要求が参照するファイルを閉じる前に完了を待つ
+            self.queued.clear();
+            while !self.in_flight.is_empty() {
+                if self.ring.submit_and_wait(1).is_err() {
+                    break;
+                }
+                self.reap();
+            }
         }
     }
 
     impl Prefetcher for IoUringPrefetcher {
-        fn prefetch_files(&mut self, paths: &[PathBuf]) -> Result<(), PrefetchError> {
+        fn prefetch_files(&mut self, paths: &[PathBuf]) -> Result<PrefetchTicket, PrefetchError> {
             self.prefetch_files(paths)
         }
 
+        fn prefetch_ranges(&mut self, ranges: &[PrefetchRange]) -> Result<PrefetchTicket, PrefetchError> {
+            self.prefetch_ranges(ranges)
+        }
+
+        fn poll(&mut self, ticket: PrefetchTicket) -> Result<bool, PrefetchError> {
+            self.poll(ticket)
+        }
+
+        fn wait_ticket(&mut self, ticket: PrefetchTicket) -> Result<(), PrefetchError> {
+            self.wait_ticket(ticket)
+        }
+
         fn wait(&mut self) -> Result<(), PrefetchError> {
             self.wait()
         }
+
+        fn backend(&self) -> PrefetchBackend {
+            PrefetchBackend::IoUring
+        }
     }
 }
 
 #[cfg(all(target_os = "linux", feature = "io_uring"))]
 pub use linux::IoUringPrefetcher;
 
+/// `posix_fadvise(POSIX_FADV_WILLNEED)` を使ったプリフェッチャー
+///
+/// カーネルに先読みを依頼するだけで、読み込みの完了は待たない。`posix_fadvise` のない
+/// プラットフォームでは、ファイルをマップして `madvise(MADV_WILLNEED)` を発行する。
+/// すでにマップ済みの領域には `MmapManager::advise_willneed` を使う。
+#[cfg(unix)]
+#[derive(Default)]
+pub struct FadvisePrefetcher {
+    tickets: TicketTracker,
+}
+
+#[cfg(unix)]
+impl FadvisePrefetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ファイルの範囲に先読みを依頼する（`len` が0ならファイル末尾まで）
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    fn advise_file(file: &std::fs::File, offset: u64, len: u64) -> std::io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let ret = unsafe {
+            libc::posix_fadvise(
+                file.as_raw_fd(),
+                offset as libc::off_t,
+                len as libc::off_t,
+                libc::POSIX_FADV_WILLNEED,
+            )
+        };
+        if ret != 0 {
+            return Err(std::io::Error::from_raw_os_error(ret));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+    fn advise_file(file: &std::fs::File, offset: u64, len: u64) -> std::io::Result<()> {
+        let file_len = file.metadata()?.len();
+        if offset >= file_len {
+            return Ok(());
+        }
+        let len = if len == 0 { file_len - offset } else { len.min(file_len - offset) };
+        let mmap = unsafe { memmap2::Mmap::map(file)? };
+        mmap.advise_range(memmap2::Advice::WillNeed, offset as usize, len as usize)
+    }
+}
+
+#[cfg(unix)]
+impl Prefetcher for FadvisePrefetcher {
+    fn prefetch_files(&mut self, paths: &[PathBuf]) -> Result<PrefetchTicket, PrefetchError> {
+        for path in paths {
+            let file = std::fs::File::open(path)?;
+            Self::advise_file(&file, 0, 0)?;
+        }
+        Ok(self.tickets.issue(0))
+    }
+
+    fn prefetch_ranges(&mut self, ranges: &[PrefetchRange]) -> Result<PrefetchTicket, PrefetchError> {
+        let mut file: Option<(&PathBuf, std::fs::File)> = None;
+        for range in ranges {
+            if range.len == 0 {
+                continue;
+            }
+            // 連続する同じファイルへの範囲では開き直さない
+            if file.as_ref().is_none_or(|(path, _)| **path != range.path) {
+                file = Some((&range.path, std::fs::File::open(&range.path)?));
+            }
+            if let Some((_, file)) = &file {
+                Self::advise_file(file, range.offset, range.len)?;
+            }
+        }
+        Ok(self.tickets.issue(0))
+    }
+
+    // 依頼は発行した時点で完了しており、読み込みはカーネルが非同期に行う
+    fn poll(&mut self, ticket: PrefetchTicket) -> Result<bool, PrefetchError> {
+        self.tickets.take(ticket, "fadvise call(s)").unwrap_or(Ok(())).map(|_| true)
+    }
+
+    fn wait_ticket(&mut self, ticket: PrefetchTicket) -> Result<(), PrefetchError> {
+        self.poll(ticket).map(|_| ())
+    }
+
+    fn wait(&mut self) -> Result<(), PrefetchError> {
+        Ok(())
+    }
+
+    fn backend(&self) -> PrefetchBackend {
+        PrefetchBackend::Fadvise
+    }
+}
+
+/// スレッドプールの1回の読み込みで扱う最大バイト数
+const THREAD_POOL_CHUNK_SIZE: u64 = 4 << 20;
+
+/// ワーカーが読み込む範囲
+struct ReadJob {
+    path: PathBuf,
+    ticket: PrefetchTicket,
+    offset: u64,
+    len: u64,
+}
+
+/// スレッドプールの共有状態
+#[derive(Default)]
+struct PoolState {
+    queue: std::collections::VecDeque<ReadJob>,
+    in_flight_bytes: u64,
+    peak_in_flight_bytes: u64,
+    active: usize,
+    tickets: TicketTracker,
+    shutdown: bool,
+}
+
+type SharedPoolState = std::sync::Arc<(std::sync::Mutex<PoolState>, std::sync::Condvar)>;
+
+/// ワーカースレッドからファイルを読み込んでページキャッシュを温めるプリフェッチャー
+///
+/// io_uringやfadviseの効かないファイルシステム（ネットワークマウント、FUSE）向け。
+/// 範囲はチャンクに分けてキューに積まれ、読み込み中のバイト数が上限を超えない
+/// ようにワーカーが順に取り出す。
+pub struct ThreadPoolPrefetcher {
+    state: SharedPoolState,
+    workers: Vec<std::thread::JoinHandle<()>>,
+    chunk_size: u64,
+}
+
+impl ThreadPoolPrefetcher {
+    pub fn new(threads: usize, max_in_flight_bytes: u64) -> Result<Self, PrefetchError> {
+        let max_in_flight_bytes = max_in_flight_bytes.max(1);
+        let state = SharedPoolState::default();
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let state = state.clone();
+                std::thread::Builder::new()
+                    .name(format!("zcl-prefetch-{}", i))
+                    .spawn(move || Self::worker(&state, max_in_flight_bytes))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            state,
+            workers,
+            chunk_size: THREAD_POOL_CHUNK_SIZE.min(max_in_flight_bytes),
+        })
+    }
+
+    fn worker(state: &SharedPoolState, max_in_flight_bytes: u64) {
+        let (lock, cvar) = &**state;
+        let mut buf = vec![0u8; 256 << 10];
+        let mut guard = lock.lock().unwrap();
+        loop {
+            if guard.shutdown {
+                return;
+            }
+            // 上限に収まるか、他に読み込み中のものがなければ次の範囲を取り出す
+            let fits = guard.queue.front().is_some_and(|job| {
+                guard.in_flight_bytes == 0 || guard.in_flight_bytes + job.len <= max_in_flight_bytes
+            });
+            if !fits {
+                guard = cvar.wait(guard).unwrap();
+                continue;
+            }
+            let job = guard.queue.pop_front().unwrap();
+            guard.in_flight_bytes += job.len;
+            guard.peak_in_flight_bytes = guard.peak_in_flight_bytes.max(guard.in_flight_bytes);
+            guard.active += 1;
+            drop(guard);
+
+            let result = Self::read_range(&job, &mut buf);
+
+            guard = lock.lock().unwrap();
+            guard.in_flight_bytes -= job.len;
+            guard.active -= 1;
+            let error = result.err().map(|e| format!("{}: {}", job.path.display(), e));
+            guard.tickets.complete(job.ticket, error);
+            cvar.notify_all();
+        }
+    }
+
+    fn read_range(job: &ReadJob, buf: &mut [u8]) -> std::io::Result<()> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = std::fs::File::open(&job.path)?;
+        file.seek(SeekFrom::Start(job.offset))?;
+        let mut remaining = job.len;
+        while remaining > 0 {
+            let want = remaining.min(buf.len() as u64) as usize;
+            let n = file.read(&mut buf[..want])?;
+            if n == 0 {
+                break; // ファイル末尾
+            }
+            remaining -= n as u64;
+        }
+        Ok(())
+    }
+
+    /// (パス, オフセット, 長さ) の列をチャンクに分けて1枚のチケットとしてキューに積む
+    fn schedule(&mut self, ranges: Vec<(PathBuf, u64, u64)>) -> PrefetchTicket {
+        let (lock, cvar) = &*self.state;
+        let mut guard = lock.lock().unwrap();
+        let chunks = ranges.iter().map(|(_, _, len)| len.div_ceil(self.chunk_size) as usize).sum();
+        let ticket = guard.tickets.issue(chunks);
+        for (path, start, len) in ranges {
+            let end = start + len;
+            let mut offset = start;
+            while offset < end {
+                let len = self.chunk_size.min(end - offset);
+                guard.queue.push_back(ReadJob {
+                    path: path.clone(),
+                    ticket,
+                    offset,
+                    len,
+                });
+                offset += len;
+            }
+        }
+        cvar.notify_all();
+        ticket
+    }
+
+    /// 読み込み中のバイト数の最大値（上限の確認用）
+    pub fn peak_in_flight_bytes(&self) -> u64 {
+        self.state.0.lock().unwrap().peak_in_flight_bytes
+    }
+}
+
+impl Prefetcher for ThreadPoolPrefetcher {
+    fn prefetch_files(&mut self, paths: &[PathBuf]) -> Result<PrefetchTicket, PrefetchError> {
+        let mut ranges = Vec::with_capacity(paths.len());
+        for path in paths {
+            ranges.push((path.clone(), 0, std::fs::metadata(path)?.len()));
+        }
+        Ok(self.schedule(ranges))
+    }
+
+    fn prefetch_ranges(&mut self, ranges: &[PrefetchRange]) -> Result<PrefetchTicket, PrefetchError> {
+        Ok(self.schedule(
+            ranges
+                .iter()
+                .map(|range| (range.path.clone(), range.offset, range.len))
+                .collect(),
+        ))
+    }
+
+    fn poll(&mut self, ticket: PrefetchTicket) -> Result<bool, PrefetchError> {
+        let mut guard = self.state.0.lock().unwrap();
+        match guard.tickets.take(ticket, "prefetch read(s)") {
+            Some(result) => result.map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    fn wait_ticket(&mut self, ticket: PrefetchTicket) -> Result<(), PrefetchError> {
+        let (lock, cvar) = &*self.state;
+        let mut guard = cvar
+            .wait_while(lock.lock().unwrap(), |state| !state.tickets.is_done(ticket))
+            .unwrap();
+        guard.tickets.take(ticket, "prefetch read(s)").unwrap_or(Ok(()))
+    }
+
+    fn wait(&mut self) -> Result<(), PrefetchError> {
+        let (lock, cvar) = &*self.state;
+        let mut guard = cvar
+            .wait_while(lock.lock().unwrap(), |state| !state.queue.is_empty() || state.active > 0)
+            .unwrap();
+        guard.tickets.take_all("prefetch read(s)")
+    }
+
+    fn backend(&self) -> PrefetchBackend {
+        PrefetchBackend::ThreadPool
+    }
+}
+
+impl Drop for ThreadPoolPrefetcher {
+    fn drop(&mut self) {
+        {
+            let (lock, cvar) = &*self.state;
+            let mut guard = lock.lock().unwrap();
+            guard.shutdown = true;
+            guard.queue.clear();
+            cvar.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
 /// フォールバックプリフェッチャー（何もしない）
-pub struct NoOpPrefetcher;
+#[derive(Default)]
+pub struct NoOpPrefetcher {
+    tickets: TicketTracker,
+}
 
 impl Prefetcher for NoOpPrefetcher {
-    fn prefetch_files(&mut self, _paths: &[PathBuf]) -> Result<(), PrefetchError> {
+    fn prefetch_files(&mut self, _paths: &[PathBuf]) -> Result<PrefetchTicket, PrefetchError> {
         // 何もしない（フォールバック）
-        Ok(())
+        Ok(self.tickets.issue(0))
+    }
+
+    fn prefetch_ranges(&mut self, _ranges: &[PrefetchRange]) -> Result<PrefetchTicket, PrefetchError> {
+        Ok(self.tickets.issue(0))
+    }
+
+    fn poll(&mut self, ticket: PrefetchTicket) -> Result<bool, PrefetchError> {
+        self.tickets.take(ticket, "request(s)").unwrap_or(Ok(())).map(|_| true)
+    }
+
+    fn wait_ticket(&mut self, ticket: PrefetchTicket) -> Result<(), PrefetchError> {
+        self.poll(ticket).map(|_| ())
     }
 
     fn wait(&mut self) -> Result<(), PrefetchError> {
         Ok(())
     }
+
+    fn backend(&self) -> PrefetchBackend {
+        PrefetchBackend::NoOp
+    }
 }
 
-/// プラットフォームに応じたプリフェッチャーを作成
-pub fn create_prefetcher(queue_depth: u32) -> Result<Box<dyn Prefetcher>, PrefetchError> {
+/// 使えなかったために飛ばした実装方式とその理由
+#[derive(Debug, Clone)]
+pub struct PrefetchFallback {
+    pub backend: PrefetchBackend,
+    pub reason: String,
+}
+
+/// 設定に従ってプリフェッチャーを作成し、自動選択で飛ばした方式も返す
+///
+/// 実装方式を明示した場合、その方式が使えなければエラーになる。
+pub fn create_prefetcher_with_config(
+    config: &PrefetchConfig,
+) -> Result<(Box<dyn Prefetcher>, Vec<PrefetchFallback>), PrefetchError> {
+    let prefetcher: Box<dyn Prefetcher> = match config.backend {
+        None => return Ok(create_prefetcher_with_fallbacks(config.queue_depth)),
+        #[cfg(all(target_os = "linux", feature = "io_uring"))]
+        Some(PrefetchBackend::IoUring) => Box::new(IoUringPrefetcher::new(config.queue_depth)?),
+        #[cfg(unix)]
+        Some(PrefetchBackend::Fadvise) => Box::new(FadvisePrefetcher::new()),
+        Some(PrefetchBackend::ThreadPool) => {
+            Box::new(ThreadPoolPrefetcher::new(config.threads, config.max_in_flight_bytes)?)
+        }
+        Some(PrefetchBackend::NoOp) => Box::new(NoOpPrefetcher::default()),
+        #[allow(unreachable_patterns)]
+        Some(_) => return Err(PrefetchError::NotSupported),
+    };
+    Ok((prefetcher, Vec::new()))
+}
+
+/// io_uring → fadvise → no-op の順に使える実装方式を選び、飛ばした方式も返す
+pub fn create_prefetcher_with_fallbacks(queue_depth: u32) -> (Box<dyn Prefetcher>, Vec<PrefetchFallback>) {
+    let mut fallbacks = Vec::new();
+
     #[cfg(all(target_os = "linux", feature = "io_uring"))]
+    match IoUringPrefetcher::new(queue_depth) {
+        Ok(prefetcher) => return (Box::new(prefetcher), fallbacks),
+        Err(e) => fallbacks.push(PrefetchFallback {
+            backend: PrefetchBackend::IoUring,
+            reason: e.to_string(),
+        }),
+    }
+    #[cfg(not(all(target_os = "linux", feature = "io_uring")))]
     {
-        match IoUringPrefetcher::new(queue_depth) {
-            Ok(prefetcher) => Ok(Box::new(prefetcher)),
-            Err(_) => Ok(Box::new(NoOpPrefetcher)), // io_uringが使えない場合はフォールバック
-        }
+        let _ = queue_depth;
+        fallbacks.push(PrefetchFallback {
+            backend: PrefetchBackend::IoUring,
+            reason: "io_uring is not enabled in this build".to_string(),
+        });
     }
 
-    #[cfg(not(all(target_os = "linux", feature = "io_uring")))]
+    #[cfg(unix)]
     {
-        Ok(Box::new(NoOpPrefetcher))
+        (Box::new(FadvisePrefetcher::new()), fallbacks)
     }
+
+    #[cfg(not(unix))]
+    {
+        fallbacks.push(PrefetchFallback {
+            backend: PrefetchBackend::Fadvise,
+            reason: "fadvise is not available on this platform".to_string(),
+        });
+        (Box::new(NoOpPrefetcher::default()), fallbacks)
+    }
+}
+
+/// プラットフォームに応じたプリフェッチャーを作成
+///
+/// io_uring → fadvise → no-op の順にフォールバックする。選ばれた方式は
+/// `Prefetcher::backend` で確認できる。
+pub fn create_prefetcher(queue_depth: u32) -> Result<Box<dyn Prefetcher>, PrefetchError> {
+    Ok(create_prefetcher_with_fallbacks(queue_depth).0)
 }
 
 #[cfg(test)]
@@ -136,10 +994,164 @@ mod tests {
 
     #[test]
     fn test_noop_prefetcher() {
-        let mut prefetcher = NoOpPrefetcher;
+        let mut prefetcher = NoOpPrefetcher::default();
         let paths = vec![PathBuf::from("/nonexistent")];
         // エラーにならないことを確認
         assert!(prefetcher.prefetch_files(&paths).is_ok());
         assert!(prefetcher.wait().is_ok());
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fadvise_prefetcher() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), vec![1u8; 8192]).unwrap();
+
+        let mut prefetcher = FadvisePrefetcher::new();
+        assert_eq!(prefetcher.backend(), PrefetchBackend::Fadvise);
+        prefetcher.prefetch_files(&[file.path().to_path_buf()]).unwrap();
+        prefetcher
+            .prefetch_ranges(&[PrefetchRange::new(file.path(), 4096, 100), PrefetchRange::new(file.path(), 0, 10)])
+            .unwrap();
+        prefetcher.wait().unwrap();
+        assert!(prefetcher.prefetch_files(&[PathBuf::from("/nonexistent")]).is_err());
+    }
+
+    #[test]
+    fn test_fallback_chain() {
+        let (prefetcher, fallbacks) = create_prefetcher_with_fallbacks(4);
+        // 選ばれた方式より前の方式はすべて理由付きで報告される
+        let order = [PrefetchBackend::IoUring, PrefetchBackend::Fadvise, PrefetchBackend::NoOp];
+        let chosen = order.iter().position(|&b| b == prefetcher.backend()).unwrap();
+        assert_eq!(fallbacks.len(), chosen);
+        for (fallback, expected) in fallbacks.iter().zip(order) {
+            assert_eq!(fallback.backend, expected);
+        }
+    }
+
+    #[test]
+    fn test_thread_pool_prefetcher() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), vec![3u8; 1 << 20]).unwrap();
+
+        let config = PrefetchConfig {
+            backend: Some(PrefetchBackend::ThreadPool),
+            threads: 4,
+            max_in_flight_bytes: 64 << 10,
+            ..Default::default()
+        };
+        let (mut prefetcher, _) = create_prefetcher_with_config(&config).unwrap();
+        assert_eq!(prefetcher.backend(), PrefetchBackend::ThreadPool);
+        prefetcher.prefetch_files(&[file.path().to_path_buf()]).unwrap();
+        prefetcher
+            .prefetch_ranges(&[PrefetchRange::new(file.path(), 1000, 200 << 10)])
+            .unwrap();
+        prefetcher.wait().unwrap();
+
+        let mut pool = ThreadPoolPrefetcher::new(2, 64 << 10).unwrap();
+        pool.prefetch_files(&[file.path().to_path_buf()]).unwrap();
+        pool.wait().unwrap();
+        assert!(pool.peak_in_flight_bytes() <= 64 << 10);
+
+        // 読み込みの失敗はそのチケットにだけ報告される
+        let good = pool.prefetch_files(&[file.path().to_path_buf()]).unwrap();
+        let bad = pool.prefetch_ranges(&[PrefetchRange::new("/nonexistent", 0, 10)]).unwrap();
+        assert!(pool.wait_ticket(bad).is_err());
+        assert!(pool.poll(bad).unwrap());
+        pool.wait_ticket(good).unwrap();
+        pool.wait().unwrap();
+
+        pool.prefetch_ranges(&[PrefetchRange::new("/nonexistent", 0, 10)]).unwrap();
+        assert!(pool.wait().is_err());
+        pool.wait().unwrap();
+    }
+
+    #[test]
+    fn test_prefetch_cursor() {
+        let mut cursor = PrefetchCursor::new(3);
+        assert_eq!(cursor.advance(2), [0, 1]);
+        // エポックの境界で先頭に戻る
+        assert_eq!(cursor.advance(2), [2, 0]);
+        assert_eq!((cursor.epoch(), cursor.position()), (1, 1));
+        assert_eq!(cursor.advance(10), [1, 2, 0]);
+        cursor.reset();
+        assert_eq!((cursor.epoch(), cursor.position()), (0, 0));
+
+        // エポックごとに順序が変わる
+        let mut cursor = PrefetchCursor::with_order(Box::new(|epoch| {
+            if epoch % 2 == 0 {
+                vec![0, 1, 2]
+            } else {
+                vec![2, 1, 0]
+            }
+        }));
+        assert_eq!(cursor.advance(2), [0, 1]);
+        assert_eq!(cursor.advance(2), [2, 2]);
+        assert_eq!(cursor.advance(3), [1, 0, 0]);
+        cursor.seek(1, 2);
+        assert_eq!(cursor.advance(1), [0]);
+        assert!(PrefetchCursor::new(0).advance(3).is_empty());
+    }
+
+    #[test]
+    fn test_tickets() {
+        let mut tracker = TicketTracker::default();
+        let empty = tracker.issue(0);
+        let ticket = tracker.issue(2);
+        assert!(tracker.take(empty, "request(s)").unwrap().is_ok());
+        assert!(tracker.take(ticket, "request(s)").is_none());
+        tracker.complete(ticket, None);
+        tracker.complete(ticket, Some("a: failed".to_string()));
+        assert!(tracker.take(ticket, "request(s)").unwrap().is_err());
+        // 結果を受け取ったチケットは完了扱い、未発行のチケットはエラー
+        assert!(tracker.take(ticket, "request(s)").unwrap().is_ok());
+        assert!(tracker.take(PrefetchTicket::from_id(99), "request(s)").unwrap().is_err());
+
+        // 失敗せずに完了したチケットは、ポーリングしなくても片付けられる
+        let ticket = tracker.issue(2);
+        tracker.complete(ticket, None);
+        assert_eq!(tracker.pending.len(), 1);
+        tracker.complete(ticket, None);
+        assert!(tracker.pending.is_empty());
+        assert!(tracker.take(ticket, "request(s)").unwrap().is_ok());
+
+        let mut prefetcher = NoOpPrefetcher::default();
+        let ticket = prefetcher.prefetch_files(&[]).unwrap();
+        assert!(prefetcher.poll(ticket).unwrap());
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io_uring"))]
+    #[test]
+    fn test_io_uring_prefetcher() {
+        use std::io::Write;
+
+        // io_uringが使えない環境（古いカーネルやseccomp）では何もしない
+        let Ok(mut prefetcher) = IoUringPrefetcher::new(2) else {
+            return;
+        };
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&vec![7u8; 20 << 20]).unwrap();
+
+        // 3つの要求をキュー深度2で発行する
+        prefetcher.prefetch_files(&[file.path().to_path_buf()]).unwrap();
+        assert!(prefetcher.in_flight() <= 2);
+        prefetcher.wait().unwrap();
+        assert_eq!(prefetcher.in_flight(), 0);
+
+        let ranges = [
+            PrefetchRange::new(file.path(), 0, 4096),
+            PrefetchRange::new(file.path(), 10 << 20, 1 << 20),
+        ];
+        let first = prefetcher.prefetch_ranges(&ranges).unwrap();
+        let second = prefetcher.prefetch_files(&[file.path().to_path_buf()]).unwrap();
+        // 同じパスへの要求は1つのファイルを共有し、完了したら閉じる
+        assert_eq!(prefetcher.open_files(), 1);
+        prefetcher.wait_ticket(first).unwrap();
+        assert!(prefetcher.poll(first).unwrap());
+        prefetcher.wait_ticket(second).unwrap();
+        prefetcher.wait().unwrap();
+        assert_eq!(prefetcher.open_files(), 0);
+
+        assert!(prefetcher.prefetch_files(&[PathBuf::from("/nonexistent")]).is_err());
+    }
 }
\ No newline at end of file
@@ -1,7 +1,17 @@
-use crate::format::{ShardHeader, ShardMetadata};
-use crate::mmap::{MmapError, MmapManager};
+use crate::checksum::VerifyMode;
+use crate::codec::Codec;
+use crate::format::{
+    IndexLayout, KeyTable, PackedIndex, SampleMetadata, ShardHeader, ShardMetadata,
+    FORMAT_VERSION_V1,
+};
+use crate::mmap::{page_size, MmapConfig, MmapError, MmapManager};
+use crate::schema::{Schema, TypedView};
+use crate::storage::{open_storage, ShardStorage, StorageBackend, StorageBytes};
+use std::fmt;
 use std::io::Cursor;
-use std::path::Path;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,81 +24,625 @@ pub enum ReaderError {
     InvalidFormat(String),
     #[error("Sample index out of bounds: {0}")]
     IndexOutOfBounds(usize),
+    #[error("Sample {0} is compressed; use a decoded accessor")]
+    Compressed(usize),
+    #[error("Sample {0} is not borrowed from a memory map (lazily opened shard or direct I/O); use a decoded accessor")]
+    NotBorrowed(usize),
+    #[error("Sample key not found: {0}")]
+    KeyNotFound(String),
+    #[error("Decompression error: {0}")]
+    Decompress(String),
+    #[error("Checksum mismatch in {target}: expected 0x{expected:016X}, got 0x{actual:016X}")]
+    ChecksumMismatch {
+        target: String,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Schema mismatch: {0}")]
+    SchemaMismatch(String),
+    #[error("Shard index out of bounds: {0}")]
+    ShardOutOfBounds(usize),
+}
+
+/// シャード読み込みの設定
+#[derive(Debug, Clone, Default)]
+pub struct ReaderConfig {
+    /// チェックサム検証のタイミング（チェックサムを持たないシャードでは無視される）
+    pub verify: VerifyMode,
+    /// 学習ジョブが期待するスキーマ（互換でないシャードは開く時点でエラーになる）
+    pub expected_schema: Option<Schema>,
+    /// `MultiShardReader` が同時にマップしておくシャード数の上限
+    /// （`None` なら全シャードを開いたままにする）
+    pub max_open_shards: Option<usize>,
+    /// シャードのメモリマップのオプション（ヒュージページ、`MAP_POPULATE` など）
+    pub mmap: MmapConfig,
+    /// サンプルの読み込み方式（mmapまたは `O_DIRECT`）
+    pub storage: StorageBackend,
+}
+
+/// 名前付きフィールドを持つサンプルへのビュー
+///
+/// 各フィールドはサンプルのバイト列（mmapまたは展開用バッファ）へのスライス。
+#[derive(Debug, Clone)]
+pub struct SampleView<'a> {
+    data: &'a [u8],
+    fields: Vec<(&'a str, &'a [u8])>,
+}
+
+impl<'a> SampleView<'a> {
+    /// サンプル全体のバイト列を取得
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// 名前でフィールドを取得（欠けていれば `None`）
+    pub fn field(&self, name: &str) -> Option<&'a [u8]> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, bytes)| *bytes)
+    }
+
+    /// 存在するフィールドを宣言順に取得
+    pub fn fields(&self) -> &[(&'a str, &'a [u8])] {
+        &self.fields
+    }
+
+    /// 存在するフィールドの数
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// フィールドを1つも持たないかどうか
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// リーダーから読んだサンプルのバイト列
+///
+/// mmapではマップからの借用。`O_DIRECT` では読み込んだバッファを保持し、破棄した時点で
+/// バッファを再利用に回す。遅延オープンでは読んだシャードのリーダーを共有して保持するので、
+/// LRUから外れてもこの値が残っている間はマップが解放されない。
+pub struct SampleRef<'a> {
+    // `bytes` はリーダーのストレージを参照しうるので、`reader` より先に破棄する
+    bytes: StorageBytes<'a>,
+    reader: Option<Arc<ShardReader>>,
+}
+
+impl<'a> SampleRef<'a> {
+    /// リーダーから借用したバイト列を包む
+    pub fn borrowed(data: &'a [u8]) -> Self {
+        Self::new(StorageBytes::Borrowed(data))
+    }
+
+    fn new(bytes: StorageBytes<'a>) -> Self {
+        Self { bytes, reader: None }
+    }
+
+    /// 共有したリーダーから読んだバイト列を、リーダーごと保持する
+    fn shared<F>(reader: Arc<ShardReader>, read: F) -> Result<Self, ReaderError>
+    where
+        F: FnOnce(&ShardReader) -> Result<SampleRef<'_>, ReaderError>,
+    {
+        let bytes = read(&reader)?.into_bytes();
+        // Arcの中身はアドレスが変わらず、`reader` を保持している間は解放されない。
+        // 寿命を延ばした参照は `Deref` で `&self` の寿命に縮めてしか渡さない。
+        let bytes = unsafe { std::mem::transmute::<StorageBytes<'_>, StorageBytes<'a>>(bytes) };
+        Ok(Self {
+            bytes,
+            reader: Some(reader),
+        })
+    }
+
+    fn into_bytes(self) -> StorageBytes<'a> {
+        self.bytes
+    }
+
+    /// リーダーの寿命まで有効な借用であれば取り出す（遅延オープンのシャードや `O_DIRECT` では `None`）
+    pub fn as_borrowed(&self) -> Option<&'a [u8]> {
+        match self.reader {
+            None => self.bytes.as_borrowed(),
+            Some(_) => None,
+        }
+    }
+}
+
+impl Deref for SampleRef<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsRef<[u8]> for SampleRef<'_> {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl fmt::Debug for SampleRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.bytes, f)
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized> PartialEq<T> for SampleRef<'_> {
+    fn eq(&self, other: &T) -> bool {
+        *self.bytes == *other.as_ref()
+    }
+}
+
+/// サンプルインデックスの保持方法
+enum SampleTable {
+    /// v1: JSONメタデータをデシリアライズしたエントリ（`ShardMetadata::samples`）
+    Json,
+    /// v2: ファイル上のバイナリインデックス（開始位置とエントリ数のみ保持）
+    Packed {
+        offset: usize,
+        num_samples: usize,
+        layout: IndexLayout,
+    },
 }
 
 /// シャードファイルを読み込むリーダー
 pub struct ShardReader {
-    mmap: MmapManager,
+    storage: Box<dyn ShardStorage>,
     header: ShardHeader,
     metadata: ShardMetadata,
+    table: SampleTable,
     data_start: usize,
+    data_end: usize,
+    key_table: Option<(usize, usize)>,
+    verify: VerifyMode,
 }
 
 impl ShardReader {
     /// シャードファイルを開く
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, ReaderError> {
-        let mmap = MmapManager::new(path)?;
-        let data = mmap.as_slice();
+        Self::with_config(path, ReaderConfig::default())
+    }
+
+    /// 設定を指定してシャードファイルを開く
+    pub fn with_config<P: AsRef<Path>>(path: P, config: ReaderConfig) -> Result<Self, ReaderError> {
+        let storage = open_storage(path, &config.storage, &config.mmap)?;
 
         // ヘッダーを読み込む
-        let mut cursor = Cursor::new(&data[..ShardHeader::SIZE]);
-        let header = ShardHeader::read(&mut cursor)?;
-        header.validate().map_err(|e| ReaderError::InvalidFormat(e))?;
+        let header_bytes = storage.read_range(0, storage.len().min(ShardHeader::SIZE))?;
+        let header = ShardHeader::read(&mut &header_bytes[..])?;
+        header.validate().map_err(ReaderError::InvalidFormat)?;
+
+        let (metadata, table, data_end) = if header.version == FORMAT_VERSION_V1 {
+            Self::read_v1_index(storage.as_ref(), &header)?
+        } else {
+            Self::read_packed_index(storage.as_ref(), &header)?
+        };
+
+        if let Some(expected) = &config.expected_schema {
+            let schema = metadata.schema.as_ref().ok_or_else(|| {
+                ReaderError::SchemaMismatch("Shard has no schema".to_string())
+            })?;
+            schema.check_compatible(expected).map_err(ReaderError::SchemaMismatch)?;
+        }
 
-        // メタデータを読み込む
+        // データセクションの開始位置
+        let data_start = header.data_offset as usize;
+        if data_start > data_end {
+            return Err(ReaderError::InvalidFormat("Invalid data offset".to_string()));
+        }
+
+        // キーテーブルの範囲を検証する
+        let key_table = match header.key_table_range() {
+            Some((start, end)) => {
+                let (start, end) = (start as usize, end as usize);
+                if end > storage.len() || start > end {
+                    return Err(ReaderError::InvalidFormat("Invalid key table offset".to_string()));
+                }
+                KeyTable::new(storage.read_range(start, end - start)?, header.num_samples as usize)?;
+                Some((start, end))
+            }
+            None => None,
+        };
+
+        let reader = Self {
+            storage,
+            header,
+            metadata,
+            table,
+            data_start,
+            data_end,
+            key_table,
+            verify: config.verify,
+        };
+        match config.verify {
+            VerifyMode::Off => {}
+            VerifyMode::OnOpen => reader.verify()?,
+            VerifyMode::OnRead => reader.verify_header_and_index()?,
+        }
+        Ok(reader)
+    }
+
+    /// v1: データセクションの前にあるJSONメタデータを読み込む
+    fn read_v1_index(
+        storage: &dyn ShardStorage,
+        header: &ShardHeader,
+    ) -> Result<(ShardMetadata, SampleTable, usize), ReaderError> {
         let metadata_start = header.metadata_offset as usize;
         let metadata_end = header.data_offset as usize;
-        if metadata_end > data.len() || metadata_start >= metadata_end {
+        if metadata_end > storage.len() || metadata_start >= metadata_end {
             return Err(ReaderError::InvalidFormat(
                 "Invalid metadata offset".to_string(),
             ));
         }
 
-        let mut cursor = Cursor::new(&data[metadata_start..metadata_end]);
+        storage.pin(0, metadata_end)?;
+        let mut cursor = Cursor::new(storage.read_range(metadata_start, metadata_end - metadata_start)?);
         let metadata = ShardMetadata::read(&mut cursor)?;
+        if metadata.num_samples != metadata.samples.len() as u64 {
+            return Err(ReaderError::InvalidFormat(
+                "Sample count does not match metadata".to_string(),
+            ));
+        }
+        Ok((metadata, SampleTable::Json, storage.len()))
+    }
 
-        // データセクションの開始位置
-        let data_start = header.data_offset as usize;
-        if data_start > data.len() {
-            return Err(ReaderError::InvalidFormat("Invalid data offset".to_string()));
+    /// v2: バイナリインデックスの位置を検証し、メタデータを読み込む
+    fn read_packed_index(
+        storage: &dyn ShardStorage,
+        header: &ShardHeader,
+    ) -> Result<(ShardMetadata, SampleTable, usize), ReaderError> {
+        let metadata_start = header.metadata_offset as usize;
+        if metadata_start > storage.len() {
+            return Err(ReaderError::InvalidFormat(
+                "Invalid metadata offset".to_string(),
+            ));
+        }
+        // インデックス以降（インデックス・メタデータ・キーテーブル）は繰り返し読む
+        let index_start = header.index_offset as usize;
+        storage.pin(index_start, storage.len() - index_start)?;
+        let mut cursor = Cursor::new(storage.read_range(metadata_start, storage.len() - metadata_start)?);
+        let metadata = ShardMetadata::read(&mut cursor)?;
+        if metadata.num_samples != header.num_samples {
+            return Err(ReaderError::InvalidFormat(
+                "Sample count does not match header".to_string(),
+            ));
+        }
+        if metadata.fields.len() != header.field_count() {
+            return Err(ReaderError::InvalidFormat(
+                "Field names do not match header".to_string(),
+            ));
         }
 
-        Ok(Self {
-            mmap,
-            header,
-            metadata,
-            data_start,
-        })
+        // validate()でインデックスがメタデータより前にあることは確認済み
+        let index_size = header.index_size().unwrap_or(0) as usize;
+        PackedIndex::new(storage.read_range(index_start, index_size)?, header.index_layout())?;
+
+        let table = SampleTable::Packed {
+            offset: index_start,
+            num_samples: header.num_samples as usize,
+            layout: header.index_layout(),
+        };
+        Ok((metadata, table, index_start))
     }
 
     /// サンプル数を取得
     pub fn num_samples(&self) -> usize {
-        self.metadata.num_samples as usize
+        match &self.table {
+            SampleTable::Json => self.metadata.samples.len(),
+            SampleTable::Packed { num_samples, .. } => *num_samples,
+        }
+    }
+
+    fn packed_index(&self) -> Result<Option<PackedIndex<'_>>, ReaderError> {
+        match &self.table {
+            SampleTable::Json => Ok(None),
+            SampleTable::Packed {
+                offset,
+                num_samples,
+                layout,
+            } => {
+                let bytes = self.storage.read_range(*offset, num_samples * layout.entry_size)?;
+                Ok(Some(PackedIndex::new(bytes, *layout)?))
+            }
+        }
+    }
+
+    /// 指定されたインデックスのサンプルのメタデータを取得
+    pub fn sample_metadata(&self, index: usize) -> Result<SampleMetadata, ReaderError> {
+        match self.packed_index()? {
+            None => self.metadata.samples.get(index).copied(),
+            Some(packed) => packed.get(index).transpose()?,
+        }
+        .ok_or(ReaderError::IndexOutOfBounds(index))
+    }
+
+    /// サンプル・フィールドの先頭がメモリ上で揃っている境界（バイト）
+    ///
+    /// mmapの先頭と `O_DIRECT` のバッファはページ境界なので、ページサイズまでのアライメントは
+    /// メモリ上でも保証される。
+    /// 圧縮されたサンプルを展開したバッファには適用されない。
+    pub fn alignment(&self) -> usize {
+        (self.header.data_alignment() as usize).min(page_size())
+    }
+
+    /// 指定されたサンプルの格納範囲をファイル先頭からのオフセットとサイズで取得
+    pub fn sample_range(&self, index: usize) -> Result<(u64, u64), ReaderError> {
+        let sample_meta = self.sample_metadata(index)?;
+        check_sample_range(
+            index,
+            &sample_meta,
+            self.header.data_alignment(),
+            self.data_start,
+            self.data_end,
+        )?;
+        Ok((self.data_start as u64 + sample_meta.offset, sample_meta.size))
     }
 
-    /// 指定されたインデックスのサンプルを取得（ゼロコピー）
-    pub fn get_sample(&self, index: usize) -> Result<&[u8], ReaderError> {
-        if index >= self.metadata.samples.len() {
-            return Err(ReaderError::IndexOutOfBounds(index));
+    /// 指定されたサンプルのフィールドの範囲（展開後のサンプル先頭からの相対位置）を取得
+    pub fn field_range(&self, index: usize, field: usize) -> Option<(u64, u64)> {
+        self.packed_index().ok()??.field(index, field)
+    }
+
+    /// メモリマップを取得（mmapで読んでいなければ `None`）
+    pub fn mmap(&self) -> Option<&MmapManager> {
+        self.storage.as_mmap()
+    }
+
+    /// シャードファイルのストレージを取得
+    pub fn storage(&self) -> &dyn ShardStorage {
+        self.storage.as_ref()
+    }
+
+    /// サンプルのフィールド名を取得（フィールドを持たないシャードでは空）
+    pub fn field_names(&self) -> &[String] {
+        &self.metadata.fields
+    }
+
+    /// 指定されたサンプルのフィールドビューを取得（ゼロコピー）
+    ///
+    /// 圧縮されたサンプルは `ReaderError::Compressed`、`O_DIRECT` で読むシャードでは
+    /// `ReaderError::NotBorrowed` になる（`get_view_decoded` を使う）。
+    pub fn get_view(&self, index: usize) -> Result<SampleView<'_>, ReaderError> {
+        if self.sample_metadata(index)?.codec != Codec::None {
+            return Err(ReaderError::Compressed(index));
+        }
+        let data = self.get_sample(index)?.as_borrowed().ok_or(ReaderError::NotBorrowed(index))?;
+        self.build_view(index, data)
+    }
+
+    /// 指定されたサンプルを展開してフィールドビューを取得
+    pub fn get_view_decoded<'a>(
+        &'a self,
+        index: usize,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<SampleView<'a>, ReaderError> {
+        let data = self.get_sample_decoded(index, buf)?;
+        self.build_view(index, data)
+    }
+
+    /// 型スキーマを取得（スキーマを持たないシャードでは `None`）
+    pub fn schema(&self) -> Option<&Schema> {
+        self.metadata.schema.as_ref()
+    }
+
+    /// 指定されたサンプルのフィールドを型付きビューとして取得（ゼロコピー）
+    ///
+    /// `field` が空文字列ならサンプル全体を対象とする。サンプルにフィールドが
+    /// 欠けていれば `None` を返す。
+    pub fn get_typed(&self, index: usize, field: &str) -> Result<Option<TypedView<'_>>, ReaderError> {
+        let view = self.get_view(index)?;
+        self.typed_field(&view, field)
+    }
+
+    /// 指定されたサンプルを展開し、フィールドを型付きビューとして取得
+    pub fn get_typed_decoded<'a>(
+        &'a self,
+        index: usize,
+        field: &str,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<Option<TypedView<'a>>, ReaderError> {
+        let view = self.get_view_decoded(index, buf)?;
+        self.typed_field(&view, field)
+    }
+
+    /// ビューのフィールドにスキーマの型情報を付ける
+    pub fn typed_field<'a>(
+        &self,
+        view: &SampleView<'a>,
+        field: &str,
+    ) -> Result<Option<TypedView<'a>>, ReaderError> {
+        typed_field(self.schema(), view, field)
+    }
+
+    fn build_view<'a>(&'a self, index: usize, data: &'a [u8]) -> Result<SampleView<'a>, ReaderError> {
+        build_view(index, data, &self.metadata.fields, self.packed_index()?)
+    }
+
+    /// 指定されたインデックスのサンプルを取得（mmapではゼロコピー）
+    ///
+    /// 圧縮されたサンプルは格納されたバイト列のまま返す。展開には `get_sample_decoded` を使う。
+    pub fn get_sample(&self, index: usize) -> Result<SampleRef<'_>, ReaderError> {
+        let sample_meta = self.sample_metadata(index)?;
+        let sample = self.sample_bytes(index, &sample_meta)?;
+        if self.verify == VerifyMode::OnRead {
+            self.verify_sample(index, &sample_meta, &sample)?;
+        }
+        Ok(SampleRef::new(sample))
+    }
+
+    /// 指定されたインデックスのサンプルを展開して取得
+    ///
+    /// 無圧縮のサンプルはmmapへの参照を返し、`buf` は使わない（`O_DIRECT` では `buf` にコピーする）。
+    /// 圧縮されたサンプルは `buf` に展開し、その内容を返す。
+    pub fn get_sample_decoded<'a>(
+        &'a self,
+        index: usize,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<&'a [u8], ReaderError> {
+        let sample_meta = self.sample_metadata(index)?;
+        let sample = self.get_sample(index)?;
+        if sample_meta.codec == Codec::None {
+            if let Some(data) = sample.as_borrowed() {
+                return Ok(data);
+            }
+        }
+        Self::decode_sample(index, &sample_meta, &sample, buf)?;
+        Ok(buf.as_slice())
+    }
+
+    /// サンプルを展開して `buf` に書き込む（無圧縮のサンプルもコピーする）
+    fn decode_into(&self, index: usize, buf: &mut Vec<u8>) -> Result<(), ReaderError> {
+        let sample_meta = self.sample_metadata(index)?;
+        let sample = self.get_sample(index)?;
+        Self::decode_sample(index, &sample_meta, &sample, buf)
+    }
+
+    /// 読み込み済みのサンプルを展開して `buf` に書き込む
+    fn decode_sample(
+        index: usize,
+        sample_meta: &SampleMetadata,
+        sample: &[u8],
+        buf: &mut Vec<u8>,
+    ) -> Result<(), ReaderError> {
+        if sample_meta.codec == Codec::None {
+            buf.clear();
+            buf.extend_from_slice(sample);
+            return Ok(());
+        }
+        let decoded_size = usize::try_from(sample_meta.decoded_size()).unwrap_or(usize::MAX);
+        sample_meta
+            .codec
+            .decompress(sample, decoded_size, buf)
+            .map_err(|e| ReaderError::Decompress(format!("sample {}: {}", index, e)))
+    }
+
+    fn sample_bytes(&self, index: usize, sample_meta: &SampleMetadata) -> Result<StorageBytes<'_>, ReaderError> {
+        let (offset, size) = check_sample_range(
+            index,
+            sample_meta,
+            self.header.data_alignment(),
+            self.data_start,
+            self.data_end,
+        )?;
+        self.storage.read(offset, size).map_err(ReaderError::Mmap)
+    }
+
+    /// マップを閉じても残しておくシャードの要約（遅延オープン用）
+    fn summary(&self) -> Result<ShardSummary, ReaderError> {
+        let index = match &self.table {
+            SampleTable::Json => ResidentIndex::Json(self.metadata.samples.clone()),
+            SampleTable::Packed {
+                offset,
+                num_samples,
+                layout,
+            } => ResidentIndex::Packed {
+                // 範囲はnewで検証済み
+                bytes: self.storage.read_range(*offset, num_samples * layout.entry_size)?.to_vec(),
+                layout: *layout,
+            },
+        };
+        Ok(ShardSummary {
+            path: self.path().to_path_buf(),
+            num_samples: self.num_samples(),
+            fields: self.metadata.fields.clone(),
+            data_alignment: self.header.data_alignment(),
+            data_start: self.data_start,
+            data_end: self.data_end,
+            alignment: self.alignment(),
+            index,
+            keys: self.key_bytes()?.map(<[u8]>::to_vec),
+        })
+    }
+
+    /// ヘッダー・インデックス・全サンプルのチェックサムを検証
+    ///
+    /// チェックサムを持たないシャード（v1を含む）では何もしない。
+    pub fn verify(&self) -> Result<(), ReaderError> {
+        self.verify_header_and_index()?;
+        for index in 0..self.num_samples() {
+            let sample_meta = self.sample_metadata(index)?;
+            let sample = self.sample_bytes(index, &sample_meta)?;
+            self.verify_sample(index, &sample_meta, &sample)?;
         }
+        Ok(())
+    }
 
-        let sample_meta = &self.metadata.samples[index];
-        let offset = self.data_start + sample_meta.offset as usize;
-        let size = sample_meta.size as usize;
+    fn verify_header_and_index(&self) -> Result<(), ReaderError> {
+        let kind = self.header.checksum_kind;
+        if !kind.is_enabled() {
+            return Ok(());
+        }
+        check("header", self.header.header_checksum, self.header.compute_checksum())?;
+        let index_start = self.header.index_offset as usize;
+        let tail = self.storage.read_range(index_start, self.storage.len() - index_start)?;
+        check("index", self.header.index_checksum, kind.compute(tail))
+    }
 
-        self.mmap
-            .get_range(offset, size)
-            .map_err(|e| ReaderError::Mmap(e))
+    fn verify_sample(
+        &self,
+        index: usize,
+        sample_meta: &SampleMetadata,
+        sample: &[u8],
+    ) -> Result<(), ReaderError> {
+        match sample_meta.checksum {
+            Some(expected) => check(
+                &format!("sample {}", index),
+                expected,
+                self.header.checksum_kind.compute(sample),
+            ),
+            None => Ok(()),
+        }
     }
 
     /// 複数のサンプルを一度に取得
-    pub fn get_batch(&self, indices: &[usize]) -> Result<Vec<&[u8]>, ReaderError> {
+    pub fn get_batch(&self, indices: &[usize]) -> Result<Vec<SampleRef<'_>>, ReaderError> {
         indices.iter().map(|&idx| self.get_sample(idx)).collect()
     }
 
+    fn key_bytes(&self) -> Result<Option<&[u8]>, ReaderError> {
+        let Some((start, end)) = self.key_table else {
+            return Ok(None);
+        };
+        // 範囲はnewで検証済み
+        Ok(Some(self.storage.read_range(start, end - start)?))
+    }
+
+    fn keys(&self) -> Result<Option<KeyTable<'_>>, ReaderError> {
+        match self.key_bytes()? {
+            Some(bytes) => Ok(Some(KeyTable::new(bytes, self.num_samples())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// シャードがサンプルキーを持つかどうか
+    pub fn has_keys(&self) -> bool {
+        self.key_table.is_some()
+    }
+
+    /// 指定されたサンプルのキーを取得
+    pub fn sample_key(&self, index: usize) -> Option<&str> {
+        self.keys().ok()??.key(index)
+    }
+
+    /// キーからサンプルのインデックスを検索（O(log n)）
+    ///
+    /// キーが見つからなければ `Ok(None)`、キーテーブルを読めなければエラーを返す。
+    pub fn find_key(&self, key: &str) -> Result<Option<usize>, ReaderError> {
+        Ok(self.keys()?.and_then(|keys| keys.find(key)))
+    }
+
+    /// キーでサンプルを取得（mmapではゼロコピー）
+    pub fn get_by_key(&self, key: &str) -> Result<SampleRef<'_>, ReaderError> {
+        let index = self
+            .find_key(key)?
+            .ok_or_else(|| ReaderError::KeyNotFound(key.to_string()))?;
+        self.get_sample(index)
+    }
+
     /// ファイルパスを取得
     pub fn path(&self) -> &Path {
-        self.mmap.path()
+        self.storage.path()
     }
 
     /// ヘッダーを取得
@@ -97,50 +651,528 @@ impl ShardReader {
     }
 
     /// メタデータを取得
+    ///
+    /// v2のシャードではサンプルごとのエントリは含まれない（`sample_metadata` を使用）。
     pub fn metadata(&self) -> &ShardMetadata {
         &self.metadata
     }
 }
 
+/// サンプルの格納位置がアライメントとデータセクションの範囲に収まっていることを確かめ、
+/// ファイル先頭からのオフセットとサイズを返す
+fn check_sample_range(
+    index: usize,
+    sample_meta: &SampleMetadata,
+    data_alignment: u64,
+    data_start: usize,
+    data_end: usize,
+) -> Result<(usize, usize), ReaderError> {
+    if !sample_meta.offset.is_multiple_of(data_alignment) {
+        return Err(ReaderError::InvalidFormat(format!(
+            "Sample {} is not aligned to {} bytes",
+            index, data_alignment
+        )));
+    }
+    // 壊れたインデックスの巨大な値で桁あふれしないよう、すべて検査付きで足す
+    usize::try_from(sample_meta.offset)
+        .ok()
+        .and_then(|offset| data_start.checked_add(offset))
+        .zip(usize::try_from(sample_meta.size).ok())
+        .filter(|&(offset, size)| offset.checked_add(size).is_some_and(|end| end <= data_end))
+        .ok_or_else(|| ReaderError::InvalidFormat(format!("Sample {} lies outside the data section", index)))
+}
+
+/// サンプルのバイト列とインデックスのフィールド範囲からビューを作る
+fn build_view<'a>(
+    index: usize,
+    data: &'a [u8],
+    names: &'a [String],
+    packed: Option<PackedIndex<'_>>,
+) -> Result<SampleView<'a>, ReaderError> {
+    let mut fields = Vec::with_capacity(names.len());
+    if let Some(packed) = packed {
+        for (field, name) in names.iter().enumerate() {
+            let Some((offset, size)) = packed.field(index, field) else {
+                continue;
+            };
+            let bytes = offset
+                .checked_add(size)
+                .and_then(|end| data.get(offset as usize..end as usize))
+                .ok_or_else(|| {
+                    ReaderError::InvalidFormat(format!(
+                        "Field {} of sample {} lies outside the sample",
+                        name, index
+                    ))
+                })?;
+            fields.push((name.as_str(), bytes));
+        }
+    }
+    Ok(SampleView { data, fields })
+}
+
+fn typed_field<'a>(
+    schema: Option<&Schema>,
+    view: &SampleView<'a>,
+    field: &str,
+) -> Result<Option<TypedView<'a>>, ReaderError> {
+    let field_schema = schema
+        .and_then(|schema| schema.field(field))
+        .ok_or_else(|| ReaderError::SchemaMismatch(format!("No schema for field '{}'", field)))?;
+    let bytes = if field.is_empty() {
+        Some(view.data())
+    } else {
+        view.field(field)
+    };
+    bytes
+        .map(|bytes| TypedView::new(field_schema, bytes).map_err(ReaderError::SchemaMismatch))
+        .transpose()
+}
+
+fn check(target: &str, expected: u64, actual: u64) -> Result<(), ReaderError> {
+    if expected != actual {
+        return Err(ReaderError::ChecksumMismatch {
+            target: target.to_string(),
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// 閉じたシャードについてメモリに残すサンプルインデックス
+enum ResidentIndex {
+    Json(Vec<SampleMetadata>),
+    Packed { bytes: Vec<u8>, layout: IndexLayout },
+}
+
+/// 遅延オープンで、マップしていないシャードについて常駐させる情報
+struct ShardSummary {
+    path: PathBuf,
+    num_samples: usize,
+    fields: Vec<String>,
+    data_alignment: u64,
+    data_start: usize,
+    data_end: usize,
+    alignment: usize,
+    index: ResidentIndex,
+    /// キーテーブルのバイト列（キーの検索でシャードをマップしないため）
+    keys: Option<Vec<u8>>,
+}
+
+impl ShardSummary {
+    fn sample_metadata(&self, index: usize) -> Result<SampleMetadata, ReaderError> {
+        match &self.index {
+            ResidentIndex::Json(samples) => samples.get(index).copied(),
+            ResidentIndex::Packed { bytes, layout } => PackedIndex::new(bytes, *layout)?.get(index).transpose()?,
+        }
+        .ok_or(ReaderError::IndexOutOfBounds(index))
+    }
+
+    fn packed_index(&self) -> Result<Option<PackedIndex<'_>>, ReaderError> {
+        match &self.index {
+            ResidentIndex::Json(_) => Ok(None),
+            ResidentIndex::Packed { bytes, layout } => Ok(Some(PackedIndex::new(bytes, *layout)?)),
+        }
+    }
+
+    fn sample_range(&self, index: usize) -> Result<(u64, u64), ReaderError> {
+        let sample_meta = self.sample_metadata(index)?;
+        check_sample_range(index, &sample_meta, self.data_alignment, self.data_start, self.data_end)?;
+        Ok((self.data_start as u64 + sample_meta.offset, sample_meta.size))
+    }
+
+    fn find_key(&self, key: &str) -> Result<Option<usize>, ReaderError> {
+        match &self.keys {
+            Some(bytes) => Ok(KeyTable::new(bytes, self.num_samples)?.find(key)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// 開いているシャードのLRU
+///
+/// LRUから外れたリーダーは、返した `SampleRef` などが保持している間だけ `evicted` から辿れ、
+/// 同じシャードを読むときに再利用する。参照がなくなった時点でマップは解放される。
+struct ShardLru {
+    open: Vec<(usize, Arc<ShardReader>)>, // 最近使ったものが末尾
+    evicted: Vec<(usize, Weak<ShardReader>)>,
+}
+
+impl ShardLru {
+    /// 開いているリーダーを探す（LRUから外れても参照が残っていれば見つかる）
+    fn find(&self, shard_index: usize) -> Option<Arc<ShardReader>> {
+        match self.open.iter().find(|(index, _)| *index == shard_index) {
+            Some((_, reader)) => Some(Arc::clone(reader)),
+            None => self
+                .evicted
+                .iter()
+                .find(|(index, _)| *index == shard_index)
+                .and_then(|(_, reader)| reader.upgrade()),
+        }
+    }
+}
+
+/// `MultiShardReader::shard` が返すシャードのリーダー
+pub enum ShardRef<'a> {
+    /// すべてのシャードを開いたままのリーダーへの借用
+    Borrowed(&'a ShardReader),
+    /// 遅延オープンで開いたリーダー（保持している間はLRUから外れても開いたまま）
+    Shared(Arc<ShardReader>),
+}
+
+impl Deref for ShardRef<'_> {
+    type Target = ShardReader;
+
+    fn deref(&self) -> &ShardReader {
+        match self {
+            ShardRef::Borrowed(reader) => reader,
+            ShardRef::Shared(reader) => reader,
+        }
+    }
+}
+
+/// シャードの保持方法
+enum ShardSet {
+    /// すべてのシャードを開いたまま保持する
+    Eager(Vec<ShardReader>),
+    /// インデックスだけを常駐させ、マップは最大 `max_open` 個まで必要に応じて開く
+    Lazy {
+        summaries: Vec<ShardSummary>,
+        schema: Option<Schema>,
+        verify: VerifyMode,
+        mmap: MmapConfig,
+        storage: StorageBackend,
+        max_open: usize,
+        lru: Mutex<ShardLru>,
+    },
+}
+
 /// 複数のシャードを管理するリーダー
+///
+/// `ReaderConfig::max_open_shards` を指定すると、シャードを遅延して開き、同時にマップする
+/// シャード数を抑える。このときサンプルは読んだシャードを保持する `SampleRef` で返し、
+/// LRUから外れたシャードのマップは返した値がすべて破棄された時点で解放される。
+/// ゼロコピーのフィールドビューは取得できないので、展開用バッファを取るメソッドを使う。
 pub struct MultiShardReader {
-    readers: Vec<ShardReader>,
+    shards: ShardSet,
     global_index: Vec<(usize, usize)>, // (shard_index, sample_index_in_shard)
 }
 
 impl MultiShardReader {
     /// 複数のシャードファイルからリーダーを作成
     pub fn new<P: AsRef<Path>>(paths: &[P]) -> Result<Self, ReaderError> {
+        Self::with_config(paths, ReaderConfig::default())
+    }
+
+    /// 設定を指定して複数のシャードファイルからリーダーを作成
+    ///
+    /// 遅延オープンでも、検証とスキーマの確認のために各シャードを一度ずつ開く。
+    pub fn with_config<P: AsRef<Path>>(paths: &[P], config: ReaderConfig) -> Result<Self, ReaderError> {
         let mut readers = Vec::new();
+        let mut summaries = Vec::new();
+        let mut first_schema: Option<(PathBuf, Option<Schema>)> = None;
         let mut global_index = Vec::new();
 
-        for path in paths {
-            let reader = ShardReader::new(path)?;
-            let num_samples = reader.num_samples();
-            let shard_index = readers.len();
-            for sample_idx in 0..num_samples {
+        for (shard_index, path) in paths.iter().enumerate() {
+            let reader = ShardReader::with_config(path, config.clone())?;
+            for sample_idx in 0..reader.num_samples() {
                 global_index.push((shard_index, sample_idx));
             }
-            readers.push(reader);
+
+            // 期待するスキーマがなければ、全シャードのスキーマが一致していることを要求する
+            match &first_schema {
+                None => first_schema = Some((reader.path().to_path_buf(), reader.schema().cloned())),
+                Some((first_path, schema)) => {
+                    if config.expected_schema.is_none() && reader.schema() != schema.as_ref() {
+                        return Err(ReaderError::SchemaMismatch(format!(
+                            "{} has a different schema from {}",
+                            reader.path().display(),
+                            first_path.display()
+                        )));
+                    }
+                }
+            }
+
+            if config.max_open_shards.is_some() {
+                summaries.push(reader.summary()?);
+            } else {
+                readers.push(reader);
+            }
         }
 
+        let shards = match config.max_open_shards {
+            None => ShardSet::Eager(readers),
+            Some(max_open) => ShardSet::Lazy {
+                summaries,
+                schema: first_schema.and_then(|(_, schema)| schema),
+                // 開いた時点の検証は済んでいるので、開き直すときは繰り返さない
+                verify: match config.verify {
+                    VerifyMode::OnOpen => VerifyMode::Off,
+                    verify => verify,
+                },
+                mmap: config.mmap,
+                storage: config.storage,
+                max_open: max_open.max(1),
+                lru: Mutex::new(ShardLru {
+                    open: Vec::new(),
+                    evicted: Vec::new(),
+                }),
+            },
+        };
         Ok(Self {
-            readers,
+            shards,
             global_index,
         })
     }
 
     /// グローバルインデックスからサンプルを取得
-    pub fn get_sample(&self, global_index: usize) -> Result<&[u8], ReaderError> {
-        let (shard_idx, sample_idx) = self
-            .global_index
+    ///
+    /// すべてのシャードをmmapで開いたままならゼロコピーの借用、遅延オープンではシャードの
+    /// リーダーを保持した参照を返す。
+    pub fn get_sample(&self, global_index: usize) -> Result<SampleRef<'_>, ReaderError> {
+        let (shard_idx, sample_idx) = self.locate(global_index)?;
+        match self.shard(shard_idx)? {
+            ShardRef::Borrowed(reader) => reader.get_sample(sample_idx),
+            ShardRef::Shared(reader) => SampleRef::shared(reader, |reader| reader.get_sample(sample_idx)),
+        }
+    }
+
+    /// グローバルインデックスからサンプルのメタデータを取得
+    ///
+    /// 遅延オープンでは常駐させたインデックスを使い、シャードをマップしない。
+    pub fn sample_metadata(&self, global_index: usize) -> Result<SampleMetadata, ReaderError> {
+        let (shard_idx, sample_idx) = self.locate(global_index)?;
+        match &self.shards {
+            ShardSet::Eager(readers) => readers[shard_idx].sample_metadata(sample_idx),
+            ShardSet::Lazy { summaries, .. } => summaries[shard_idx].sample_metadata(sample_idx),
+        }
+    }
+
+    /// グローバルインデックスからシャード番号とシャード内のインデックスを取得
+    pub fn locate(&self, global_index: usize) -> Result<(usize, usize), ReaderError> {
+        self.global_index
             .get(global_index)
-            .ok_or_else(|| ReaderError::IndexOutOfBounds(global_index))?;
-        self.readers[*shard_idx].get_sample(*sample_idx)
+            .copied()
+            .ok_or(ReaderError::IndexOutOfBounds(global_index))
+    }
+
+    /// グローバルインデックスからサンプルの格納範囲（シャード番号、ファイル内オフセット、サイズ）を取得
+    ///
+    /// 遅延オープンでは常駐させたインデックスを使い、シャードをマップしない。
+    pub fn sample_range(&self, global_index: usize) -> Result<(usize, u64, u64), ReaderError> {
+        let (shard_idx, sample_idx) = self.locate(global_index)?;
+        let (offset, len) = match &self.shards {
+            ShardSet::Eager(readers) => readers[shard_idx].sample_range(sample_idx)?,
+            ShardSet::Lazy { summaries, .. } => summaries[shard_idx].sample_range(sample_idx)?,
+        };
+        Ok((shard_idx, offset, len))
+    }
+
+    /// シャードのリーダーを取得（遅延オープンでは必要に応じて開く）
+    pub fn shard(&self, shard_index: usize) -> Result<ShardRef<'_>, ReaderError> {
+        let (summaries, verify, mmap, storage, max_open, lru) = match &self.shards {
+            ShardSet::Eager(readers) => {
+                return readers
+                    .get(shard_index)
+                    .map(ShardRef::Borrowed)
+                    .ok_or(ReaderError::ShardOutOfBounds(shard_index));
+            }
+            ShardSet::Lazy {
+                summaries,
+                verify,
+                mmap,
+                storage,
+                max_open,
+                lru,
+                ..
+            } => (summaries, *verify, mmap, storage, *max_open, lru),
+        };
+        let summary = summaries.get(shard_index).ok_or(ReaderError::ShardOutOfBounds(shard_index))?;
+
+        let mut lru = lru.lock().unwrap();
+        lru.evicted.retain(|(_, reader)| reader.strong_count() > 0);
+        let reopened = match lru.open.iter().position(|(index, _)| *index == shard_index) {
+            Some(pos) => Some(lru.open.remove(pos).1),
+            None => lru
+                .evicted
+                .iter()
+                .position(|(index, _)| *index == shard_index)
+                .and_then(|pos| lru.evicted.swap_remove(pos).1.upgrade()),
+        };
+        let reader = match reopened {
+            Some(reader) => reader,
+            None => {
+                let config = ReaderConfig {
+                    verify,
+                    mmap: mmap.clone(),
+                    storage: storage.clone(),
+                    ..Default::default()
+                };
+                Arc::new(ShardReader::with_config(&summary.path, config)?)
+            }
+        };
+        lru.open.push((shard_index, Arc::clone(&reader)));
+        if lru.open.len() > max_open {
+            let (index, evicted) = lru.open.remove(0);
+            lru.evicted.push((index, Arc::downgrade(&evicted)));
+        }
+        Ok(ShardRef::Shared(reader))
+    }
+
+    /// 開いているシャードのリーダーを取得（遅延オープンで開いていなければ `None`）
+    ///
+    /// `shard` と異なり、開いていないシャードを開いたりLRUの順序を変えたりしない。
+    pub fn open_shard(&self, shard_index: usize) -> Result<Option<ShardRef<'_>>, ReaderError> {
+        match &self.shards {
+            ShardSet::Eager(readers) => readers
+                .get(shard_index)
+                .map(|reader| Some(ShardRef::Borrowed(reader)))
+                .ok_or(ReaderError::ShardOutOfBounds(shard_index)),
+            ShardSet::Lazy { summaries, lru, .. } => {
+                if shard_index >= summaries.len() {
+                    return Err(ReaderError::ShardOutOfBounds(shard_index));
+                }
+                Ok(lru.lock().unwrap().find(shard_index).map(ShardRef::Shared))
+            }
+        }
+    }
+
+    /// 現在マップしているシャード数（LRUから外れても、返した参照が保持しているものを含む）
+    pub fn open_shards(&self) -> usize {
+        match &self.shards {
+            ShardSet::Eager(readers) => readers.len(),
+            ShardSet::Lazy { lru, .. } => {
+                let lru = lru.lock().unwrap();
+                let evicted = lru.evicted.iter().filter(|(_, reader)| reader.strong_count() > 0);
+                lru.open.len() + evicted.count()
+            }
+        }
+    }
+
+    /// グローバルインデックスからサンプルを展開して取得
+    ///
+    /// 遅延オープンでは、無圧縮のサンプルも `buf` にコピーして返す。
+    pub fn get_sample_decoded<'a>(
+        &'a self,
+        global_index: usize,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<&'a [u8], ReaderError> {
+        let (shard_idx, sample_idx) = self.locate(global_index)?;
+        match self.shard(shard_idx)? {
+            ShardRef::Borrowed(reader) => reader.get_sample_decoded(sample_idx, buf),
+            ShardRef::Shared(reader) => {
+                reader.decode_into(sample_idx, buf)?;
+                Ok(buf.as_slice())
+            }
+        }
+    }
+
+    /// キーからグローバルインデックスを検索
+    ///
+    /// 各シャードのキーテーブルを順に二分探索する。キーが複数のシャードにある場合は
+    /// 先頭のシャードのものを返す。遅延オープンでは常駐させたキーテーブルを使い、
+    /// シャードをマップしない。
+    pub fn find_key(&self, key: &str) -> Result<Option<usize>, ReaderError> {
+        let mut shard_start = 0;
+        for shard_idx in 0..self.num_shards() {
+            let (found, num_samples) = match &self.shards {
+                ShardSet::Eager(readers) => (readers[shard_idx].find_key(key)?, readers[shard_idx].num_samples()),
+                ShardSet::Lazy { summaries, .. } => {
+                    (summaries[shard_idx].find_key(key)?, summaries[shard_idx].num_samples)
+                }
+            };
+            if let Some(index) = found {
+                return Ok(Some(shard_start + index));
+            }
+            shard_start += num_samples;
+        }
+        Ok(None)
+    }
+
+    /// キーでサンプルを取得（ゼロコピー）
+    pub fn get_by_key(&self, key: &str) -> Result<SampleRef<'_>, ReaderError> {
+        let index = self
+            .find_key(key)?
+            .ok_or_else(|| ReaderError::KeyNotFound(key.to_string()))?;
+        self.get_sample(index)
+    }
+
+    /// グローバルインデックスからフィールドビューを取得（ゼロコピー）
+    ///
+    /// 遅延オープンや `O_DIRECT` では `ReaderError::NotBorrowed` を返すので、`get_view_decoded` を使う。
+    pub fn get_view(&self, global_index: usize) -> Result<SampleView<'_>, ReaderError> {
+        let (shard_idx, sample_idx) = self.locate(global_index)?;
+        match self.shard(shard_idx)? {
+            ShardRef::Borrowed(reader) => reader.get_view(sample_idx),
+            ShardRef::Shared(_) => Err(ReaderError::NotBorrowed(global_index)),
+        }
+    }
+
+    /// グローバルインデックスからサンプルを展開してフィールドビューを取得
+    ///
+    /// 遅延オープンでは、無圧縮のサンプルも `buf` にコピーしてビューを作る。
+    pub fn get_view_decoded<'a>(
+        &'a self,
+        global_index: usize,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<SampleView<'a>, ReaderError> {
+        let (shard_idx, sample_idx) = self.locate(global_index)?;
+        match self.shard(shard_idx)? {
+            ShardRef::Borrowed(reader) => reader.get_view_decoded(sample_idx, buf),
+            ShardRef::Shared(reader) => {
+                reader.decode_into(sample_idx, buf)?;
+                // フィールド名と範囲は、リーダーを閉じても残る要約から取る
+                let summary = self.summary(shard_idx).ok_or(ReaderError::ShardOutOfBounds(shard_idx))?;
+                build_view(sample_idx, buf, &summary.fields, summary.packed_index()?)
+            }
+        }
+    }
+
+    fn summary(&self, shard_index: usize) -> Option<&ShardSummary> {
+        match &self.shards {
+            ShardSet::Eager(_) => None,
+            ShardSet::Lazy { summaries, .. } => summaries.get(shard_index),
+        }
+    }
+
+    /// 全シャードで保証されるサンプルのアライメント（バイト）
+    pub fn alignment(&self) -> usize {
+        match &self.shards {
+            ShardSet::Eager(readers) => readers.iter().map(ShardReader::alignment).min(),
+            ShardSet::Lazy { summaries, .. } => summaries.iter().map(|summary| summary.alignment).min(),
+        }
+        .unwrap_or(1)
+    }
+
+    /// 型スキーマを取得（先頭のシャードのもの）
+    pub fn schema(&self) -> Option<&Schema> {
+        match &self.shards {
+            ShardSet::Eager(readers) => readers.first()?.schema(),
+            ShardSet::Lazy { schema, .. } => schema.as_ref(),
+        }
+    }
+
+    /// グローバルインデックスからフィールドを型付きビューとして取得（ゼロコピー）
+    ///
+    /// 遅延オープンや `O_DIRECT` では `ReaderError::NotBorrowed` を返すので、`get_typed_decoded` を使う。
+    pub fn get_typed(&self, global_index: usize, field: &str) -> Result<Option<TypedView<'_>>, ReaderError> {
+        let view = self.get_view(global_index)?;
+        typed_field(self.schema(), &view, field)
+    }
+
+    /// グローバルインデックスからサンプルを展開し、フィールドを型付きビューとして取得
+    pub fn get_typed_decoded<'a>(
+        &'a self,
+        global_index: usize,
+        field: &str,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<Option<TypedView<'a>>, ReaderError> {
+        let view = self.get_view_decoded(global_index, buf)?;
+        typed_field(self.schema(), &view, field)
     }
 
     /// バッチでサンプルを取得
-    pub fn get_batch(&self, indices: &[usize]) -> Result<Vec<&[u8]>, ReaderError> {
+    pub fn get_batch(&self, indices: &[usize]) -> Result<Vec<SampleRef<'_>>, ReaderError> {
         indices.iter().map(|&idx| self.get_sample(idx)).collect()
     }
 
@@ -151,63 +1183,70 @@ impl MultiShardReader {
 
     /// シャード数を取得
     pub fn num_shards(&self) -> usize {
-        self.readers.len()
+        match &self.shards {
+            ShardSet::Eager(readers) => readers.len(),
+            ShardSet::Lazy { summaries, .. } => summaries.len(),
+        }
+    }
+
+    /// 各シャードのサンプル数を取得
+    pub fn shard_sizes(&self) -> Vec<usize> {
+        let mut sizes = vec![0; self.num_shards()];
+        for &(shard, _) in &self.global_index {
+            sizes[shard] += 1;
+        }
+        sizes
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::format::{ShardHeader, ShardMetadata, SampleMetadata};
-    use std::io::Write;
+    use crate::checksum::ChecksumKind;
+    use crate::format::{ShardWriter, WriterConfig};
+    use crate::schema::{DType, FieldSchema};
     use tempfile::NamedTempFile;
 
     fn create_test_shard(data: &[&[u8]]) -> NamedTempFile {
-        use crate::format::SampleMetadata;
-        let mut file = NamedTempFile::new().unwrap();
-        let mut buf = Vec::new();
+        let file = NamedTempFile::new().unwrap();
+        let mut writer = ShardWriter::new(file.path()).unwrap();
+        for sample_data in data {
+            writer.append(sample_data).unwrap();
+        }
+        writer.finish().unwrap();
+        file
+    }
 
-        // ヘッダー用のスペースを確保（後で更新）
-        let header_offset = 0;
-        buf.resize(ShardHeader::SIZE, 0);
+    /// v1形式（JSONインデックス）のシャードを作成
+    fn create_v1_shard(data: &[&[u8]]) -> NamedTempFile {
+        use std::io::Write;
 
-        // メタデータを準備
         let mut samples = Vec::new();
         let mut current_offset = 0u64;
         for sample_data in data {
-            samples.push(SampleMetadata {
-                offset: current_offset,
-                size: sample_data.len() as u64,
-            });
+            samples.push(SampleMetadata::new(current_offset, sample_data.len() as u64));
             current_offset += sample_data.len() as u64;
         }
-
         let metadata = ShardMetadata {
             num_samples: samples.len() as u64,
             samples,
+            fields: Vec::new(),
+            schema: None,
         };
 
-        // メタデータを書き込む
-        let metadata_start = buf.len();
-        metadata.write(&mut buf).unwrap();
-        let metadata_end = buf.len();
-        let metadata_offset = metadata_start as u64;
-
-        // データセクションの開始位置
-        let data_offset = metadata_end as u64;
-
-        // ヘッダーを書き込む
-        let header = ShardHeader::new(metadata_offset, data_offset);
-        let mut header_buf = Vec::new();
-        header.write(&mut header_buf).unwrap();
-        buf[header_offset..header_offset + ShardHeader::SIZE]
-            .copy_from_slice(&header_buf);
+        let mut metadata_buf = Vec::new();
+        metadata.write(&mut metadata_buf).unwrap();
+        let metadata_offset = ShardHeader::V1_SIZE as u64;
+        let data_offset = metadata_offset + metadata_buf.len() as u64;
 
-        // データを書き込む
+        let mut buf = Vec::new();
+        ShardHeader::v1(metadata_offset, data_offset).write(&mut buf).unwrap();
+        buf.extend_from_slice(&metadata_buf);
         for sample_data in data {
             buf.extend_from_slice(sample_data);
         }
 
+        let mut file = NamedTempFile::new().unwrap();
         file.write_all(&buf).unwrap();
         file.flush().unwrap();
         file
@@ -224,6 +1263,324 @@ mod tests {
         assert_eq!(reader.get_sample(2).unwrap(), b"sample3");
     }
 
+    #[test]
+    fn test_v1_shard_reader() {
+        let file = create_v1_shard(&[b"legacy1", b"legacy2"]);
+        let reader = ShardReader::new(file.path()).unwrap();
+
+        assert_eq!(reader.header().version, crate::format::FORMAT_VERSION_V1);
+        assert_eq!(reader.num_samples(), 2);
+        assert_eq!(reader.get_sample(1).unwrap(), b"legacy2");
+        assert!(matches!(reader.get_sample(2), Err(ReaderError::IndexOutOfBounds(2))));
+    }
+
+    /// チェックサム付きのシャードを作成し、ファイルの内容を返す
+    fn create_checksummed_shard(data: &[&[u8]], kind: ChecksumKind) -> (NamedTempFile, Vec<u8>) {
+        let file = NamedTempFile::new().unwrap();
+        let config = WriterConfig {
+            checksum: kind,
+            ..Default::default()
+        };
+        let mut writer = ShardWriter::with_config(file.path(), config).unwrap();
+        for sample_data in data {
+            writer.append(sample_data).unwrap();
+        }
+        writer.finish().unwrap();
+        let bytes = std::fs::read(file.path()).unwrap();
+        (file, bytes)
+    }
+
+    fn verify_config(verify: VerifyMode) -> ReaderConfig {
+        ReaderConfig {
+            verify,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_checksum_verification() {
+        for kind in [ChecksumKind::Crc32c, ChecksumKind::XxHash64] {
+            let (file, _) = create_checksummed_shard(&[b"sample1", b"sample2"], kind);
+            let reader = ShardReader::with_config(file.path(), verify_config(VerifyMode::OnOpen)).unwrap();
+            assert_eq!(reader.get_sample(1).unwrap(), b"sample2");
+            assert!(reader.sample_metadata(0).unwrap().checksum.is_some());
+        }
+    }
+
+    #[test]
+    fn test_corrupted_sample() {
+        let (file, mut bytes) = create_checksummed_shard(&[b"sample1", b"sample2"], ChecksumKind::Crc32c);
+        // 2番目のサンプルの1バイトを壊す
+        let header = ShardHeader::read(&mut &bytes[..]).unwrap();
+        bytes[header.data_offset as usize + 7] ^= 0xFF;
+        std::fs::write(file.path(), &bytes).unwrap();
+
+        // 検証なしでは壊れたデータがそのまま返る
+        let reader = ShardReader::new(file.path()).unwrap();
+        assert_ne!(reader.get_sample(1).unwrap(), b"sample2");
+
+        let result = ShardReader::with_config(file.path(), verify_config(VerifyMode::OnOpen));
+        assert!(matches!(result, Err(ReaderError::ChecksumMismatch { .. })));
+
+        let reader = ShardReader::with_config(file.path(), verify_config(VerifyMode::OnRead)).unwrap();
+        assert_eq!(reader.get_sample(0).unwrap(), b"sample1");
+        assert!(matches!(reader.get_sample(1), Err(ReaderError::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn test_corrupted_index() {
+        let (file, mut bytes) = create_checksummed_shard(&[b"sample1"], ChecksumKind::XxHash64);
+        let header = ShardHeader::read(&mut &bytes[..]).unwrap();
+        bytes[header.index_offset as usize] ^= 0x01;
+        std::fs::write(file.path(), &bytes).unwrap();
+
+        let result = ShardReader::with_config(file.path(), verify_config(VerifyMode::OnRead));
+        match result {
+            Err(ReaderError::ChecksumMismatch { target, .. }) => assert_eq!(target, "index"),
+            _ => panic!("expected an index checksum mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_compressed_samples() {
+        let compressible = b"token ".repeat(64);
+        for codec in [Codec::Zstd, Codec::Lz4] {
+            if !codec.is_available() {
+                continue;
+            }
+            let file = NamedTempFile::new().unwrap();
+            let config = WriterConfig {
+                checksum: ChecksumKind::Crc32c,
+                codec,
+                ..Default::default()
+            };
+            let mut writer = ShardWriter::with_config(file.path(), config).unwrap();
+            writer.append(&compressible).unwrap();
+            writer.append(b"tiny").unwrap(); // 圧縮で小さくならないので無圧縮で格納
+            writer.finish().unwrap();
+
+            let reader = ShardReader::with_config(file.path(), verify_config(VerifyMode::OnOpen)).unwrap();
+            let meta = reader.sample_metadata(0).unwrap();
+            assert_eq!(meta.codec, codec);
+            assert_eq!(meta.decoded_size(), compressible.len() as u64);
+            assert!(meta.size < meta.decoded_size());
+
+            let mut buf = Vec::new();
+            assert_eq!(reader.get_sample_decoded(0, &mut buf).unwrap(), compressible.as_slice());
+
+            // 無圧縮のサンプルはゼロコピーで返る
+            let mut buf = Vec::new();
+            assert_eq!(reader.sample_metadata(1).unwrap().codec, Codec::None);
+            assert_eq!(reader.get_sample_decoded(1, &mut buf).unwrap(), b"tiny");
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_keyed_samples() {
+        let file1 = NamedTempFile::new().unwrap();
+        let mut writer = ShardWriter::new(file1.path()).unwrap();
+        writer.append_with_key("doc/b", b"beta").unwrap();
+        writer.append(b"unkeyed").unwrap();
+        writer.append_with_key("doc/a", b"alpha").unwrap();
+        writer.finish().unwrap();
+
+        let file2 = NamedTempFile::new().unwrap();
+        let mut writer = ShardWriter::new(file2.path()).unwrap();
+        writer.append_with_key("doc/c", b"gamma").unwrap();
+        writer.finish().unwrap();
+
+        let reader = ShardReader::new(file1.path()).unwrap();
+        assert!(reader.has_keys());
+        assert_eq!(reader.get_by_key("doc/a").unwrap(), b"alpha");
+        assert_eq!(reader.find_key("doc/b").unwrap(), Some(0));
+        assert_eq!(reader.sample_key(1), None);
+        assert!(matches!(reader.get_by_key("missing"), Err(ReaderError::KeyNotFound(_))));
+
+        let multi = MultiShardReader::new(&[file1.path(), file2.path()]).unwrap();
+        assert_eq!(multi.find_key("doc/c").unwrap(), Some(3));
+        assert_eq!(multi.find_key("missing").unwrap(), None);
+        assert_eq!(multi.get_by_key("doc/c").unwrap(), b"gamma");
+        assert_eq!(multi.get_by_key("doc/b").unwrap(), b"beta");
+
+        // 遅延オープンでは常駐させたキーテーブルで検索し、シャードを開かない
+        let config = ReaderConfig {
+            max_open_shards: Some(1),
+            ..Default::default()
+        };
+        let lazy = MultiShardReader::with_config(&[file1.path(), file2.path()], config).unwrap();
+        assert_eq!(lazy.find_key("doc/c").unwrap(), Some(3));
+        assert_eq!(lazy.find_key("doc/a").unwrap(), Some(2));
+        assert_eq!(lazy.open_shards(), 0);
+    }
+
+    #[test]
+    fn test_duplicate_keys_rejected() {
+        let file = NamedTempFile::new().unwrap();
+        let mut writer = ShardWriter::new(file.path()).unwrap();
+        writer.append_with_key("same", b"1").unwrap();
+        writer.append_with_key("same", b"2").unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn test_multi_field_samples() {
+        let file = NamedTempFile::new().unwrap();
+        let config = WriterConfig {
+            fields: vec!["jpg".to_string(), "cls".to_string(), "txt".to_string()],
+            ..Default::default()
+        };
+        let mut writer = ShardWriter::with_config(file.path(), config).unwrap();
+        writer
+            .append_fields_with_key("img-0", &[("jpg", b"\xFF\xD8jpeg"), ("cls", b"7"), ("txt", b"a cat")])
+            .unwrap();
+        writer.append_fields(&[("txt", b"caption only")]).unwrap();
+        assert!(writer.append_fields(&[("png", b"?")]).is_err());
+        writer.finish().unwrap();
+
+        let reader = ShardReader::new(file.path()).unwrap();
+        assert_eq!(reader.field_names(), ["jpg", "cls", "txt"]);
+
+        let view = reader.get_view(0).unwrap();
+        assert_eq!(view.len(), 3);
+        assert_eq!(view.field("cls").unwrap(), b"7");
+        assert_eq!(view.field("txt").unwrap(), b"a cat");
+        assert_eq!(reader.get_by_key("img-0").unwrap(), view.data());
+
+        let view = reader.get_view(1).unwrap();
+        assert_eq!(view.fields(), &[("txt", &b"caption only"[..])]);
+        assert!(view.field("jpg").is_none());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_compressed_multi_field_samples() {
+        let file = NamedTempFile::new().unwrap();
+        let config = WriterConfig {
+            codec: Codec::Zstd,
+            fields: vec!["a".to_string(), "b".to_string()],
+            ..Default::default()
+        };
+        let long = b"x".repeat(512);
+        let mut writer = ShardWriter::with_config(file.path(), config).unwrap();
+        writer.append_fields(&[("a", &long), ("b", b"tail")]).unwrap();
+        writer.finish().unwrap();
+
+        let reader = ShardReader::new(file.path()).unwrap();
+        assert!(matches!(reader.get_view(0), Err(ReaderError::Compressed(0))));
+
+        let mut buf = Vec::new();
+        let view = reader.get_view_decoded(0, &mut buf).unwrap();
+        assert_eq!(view.field("a").unwrap(), long.as_slice());
+        assert_eq!(view.field("b").unwrap(), b"tail");
+    }
+
+    fn create_typed_shard(schema: Schema) -> NamedTempFile {
+        let file = NamedTempFile::new().unwrap();
+        let config = WriterConfig {
+            fields: vec!["img".to_string(), "cls".to_string()],
+            schema: Some(schema),
+            ..Default::default()
+        };
+        let mut writer = ShardWriter::with_config(file.path(), config).unwrap();
+        writer
+            .append_fields(&[("img", &[0u8; 12]), ("cls", &7i64.to_le_bytes())])
+            .unwrap();
+        writer.finish().unwrap();
+        file
+    }
+
+    fn image_schema() -> Schema {
+        Schema {
+            fields: vec![
+                FieldSchema::new("img", DType::Uint8, &[2, 2, 3]),
+                FieldSchema::new("cls", DType::Int64, &[]),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_typed_samples() {
+        let file = create_typed_shard(image_schema());
+        let reader = ShardReader::new(file.path()).unwrap();
+        assert_eq!(reader.schema(), Some(&image_schema()));
+
+        let img = reader.get_typed(0, "img").unwrap().unwrap();
+        assert_eq!(img.dtype, DType::Uint8);
+        assert_eq!(img.shape, vec![2, 2, 3]);
+        let cls = reader.get_typed(0, "cls").unwrap().unwrap();
+        assert_eq!(cls.shape, vec![1]);
+        assert_eq!(cls.data, 7i64.to_le_bytes());
+        assert!(matches!(reader.get_typed(0, "txt"), Err(ReaderError::SchemaMismatch(_))));
+
+        // 形状に合わないサンプルは書き込み時に拒否される
+        let other = NamedTempFile::new().unwrap();
+        let config = WriterConfig {
+            fields: vec!["img".to_string(), "cls".to_string()],
+            schema: Some(image_schema()),
+            ..Default::default()
+        };
+        let mut writer = ShardWriter::with_config(other.path(), config).unwrap();
+        assert!(writer.append_fields(&[("img", &[0u8; 5])]).is_err());
+    }
+
+    #[test]
+    fn test_expected_schema() {
+        let file = create_typed_shard(image_schema());
+        let expected = Schema {
+            fields: vec![FieldSchema::new("cls", DType::Int64, &[])],
+        };
+        let config = ReaderConfig {
+            expected_schema: Some(expected),
+            ..Default::default()
+        };
+        assert!(ShardReader::with_config(file.path(), config).is_ok());
+
+        let config = ReaderConfig {
+            expected_schema: Some(Schema::single(DType::Float32, &[])),
+            ..Default::default()
+        };
+        assert!(matches!(
+            ShardReader::with_config(file.path(), config.clone()),
+            Err(ReaderError::SchemaMismatch(_))
+        ));
+        let untyped = create_test_shard(&[b"sample"]);
+        assert!(matches!(
+            ShardReader::with_config(untyped.path(), config),
+            Err(ReaderError::SchemaMismatch(_))
+        ));
+
+        // スキーマの異なるシャードは1つのリーダーにまとめられない
+        assert!(matches!(
+            MultiShardReader::new(&[file.path(), untyped.path()]),
+            Err(ReaderError::SchemaMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_aligned_samples() {
+        let file = NamedTempFile::new().unwrap();
+        let config = WriterConfig {
+            alignment: 64,
+            ..Default::default()
+        };
+        let mut writer = ShardWriter::with_config(file.path(), config).unwrap();
+        writer.append(b"odd").unwrap();
+        writer.append(&1.5f64.to_le_bytes()).unwrap();
+        writer.finish().unwrap();
+
+        let reader = ShardReader::new(file.path()).unwrap();
+        assert_eq!(reader.alignment(), 64);
+        let sample = reader.get_sample(1).unwrap();
+        assert!((sample.as_ptr() as usize).is_multiple_of(64));
+        let values = crate::buffer::ZeroCopyBuffer::from_slice(&sample).as_f64().unwrap();
+        assert_eq!(values, &[1.5]);
+
+        // アライメント指定のないシャードは1バイト境界
+        let plain = create_test_shard(&[b"sample"]);
+        assert_eq!(ShardReader::new(plain.path()).unwrap().alignment(), 1);
+    }
+
     #[test]
     fn test_multi_shard_reader() {
         let file1 = create_test_shard(&[b"shard1_sample1", b"shard1_sample2"]);
@@ -235,4 +1592,133 @@ mod tests {
         assert_eq!(reader.get_sample(1).unwrap(), b"shard1_sample2");
         assert_eq!(reader.get_sample(2).unwrap(), b"shard2_sample1");
     }
+
+    #[test]
+    fn test_lazy_multi_shard_reader() {
+        let file1 = create_test_shard(&[b"s1a", b"s1b"]);
+        let file2 = create_v1_shard(&[b"s2a"]);
+        let file3 = create_test_shard(&[b"s3a"]);
+        let config = ReaderConfig {
+            max_open_shards: Some(1),
+            ..Default::default()
+        };
+        let paths = [file1.path(), file2.path(), file3.path()];
+        let reader = MultiShardReader::with_config(&paths, config).unwrap();
+        assert_eq!(reader.num_shards(), 3);
+        assert_eq!(reader.open_shards(), 0);
+
+        // インデックスは常駐しているので、範囲の取得ではシャードを開かない
+        let data_offset = ShardHeader::SIZE as u64;
+        assert_eq!(reader.sample_range(1).unwrap(), (0, data_offset + 3, 3));
+        assert_eq!(reader.sample_metadata(2).unwrap().size, 3);
+        assert_eq!(reader.open_shards(), 0);
+
+        // LRUから外れたシャードのサンプルも、保持している間は有効
+        let first = reader.get_sample(0).unwrap();
+        assert!(first.as_borrowed().is_none());
+        assert_eq!(reader.get_sample(2).unwrap(), b"s2a");
+        assert_eq!(reader.get_sample(3).unwrap(), b"s3a");
+        assert_eq!(first, b"s1a");
+        assert_eq!(reader.open_shards(), 2);
+        drop(first);
+        assert_eq!(reader.open_shards(), 1);
+        assert_eq!(reader.get_sample(1).unwrap(), b"s1b");
+        assert!(matches!(reader.shard(3), Err(ReaderError::ShardOutOfBounds(3))));
+
+        // ゼロコピーのビューは取れないので、バッファにコピーして作る
+        assert!(matches!(reader.get_view(0), Err(ReaderError::NotBorrowed(0))));
+        let mut buf = Vec::new();
+        assert_eq!(reader.get_view_decoded(0, &mut buf).unwrap().data(), b"s1a");
+        assert_eq!(reader.get_sample_decoded(3, &mut buf).unwrap(), b"s3a");
+
+        // 開いていないシャードは開かずに `None` を返す
+        let open = reader.open_shards();
+        assert!(reader.open_shard(2).unwrap().is_some());
+        assert!(reader.open_shard(0).unwrap().is_none());
+        assert_eq!(reader.open_shards(), open);
+        assert!(matches!(reader.open_shard(3), Err(ReaderError::ShardOutOfBounds(3))));
+    }
+
+    #[test]
+    fn test_lazy_reader_stays_bounded() {
+        let files: Vec<NamedTempFile> = (0..6u8).map(|i| create_test_shard(&[&[i][..], &[i, i][..]])).collect();
+        let paths: Vec<&Path> = files.iter().map(|file| file.path()).collect();
+        let config = ReaderConfig {
+            max_open_shards: Some(2),
+            ..Default::default()
+        };
+        let reader = MultiShardReader::with_config(&paths, config).unwrap();
+
+        // &self だけで全シャードを何周か読んでも、マップするシャード数は上限を超えない
+        let mut buf = Vec::new();
+        for _ in 0..3 {
+            for index in 0..reader.total_samples() {
+                let shard = (index / 2) as u8;
+                assert_eq!(reader.get_sample(index).unwrap()[0], shard);
+                assert_eq!(reader.get_sample_decoded(index, &mut buf).unwrap()[0], shard);
+                assert_eq!(reader.get_batch(&[index]).unwrap()[0][0], shard);
+                assert!(reader.open_shards() <= 2);
+            }
+        }
+
+        // 保持しているサンプルのシャードだけが上限を超えて残り、破棄すれば解放される
+        let held = reader.get_sample(0).unwrap();
+        for index in 2..reader.total_samples() {
+            reader.get_sample(index).unwrap();
+        }
+        assert_eq!(reader.open_shards(), 3);
+        assert_eq!(held, [0]);
+        drop(held);
+        assert_eq!(reader.open_shards(), 2);
+    }
+
+    #[test]
+    fn test_sample_range_overflow() {
+        let outside = |offset, size| {
+            let sample_meta = SampleMetadata::new(offset, size);
+            matches!(check_sample_range(0, &sample_meta, 8, 64, 128), Err(ReaderError::InvalidFormat(_)))
+        };
+        assert!(outside(u64::MAX - 7, 1));
+        assert!(outside(8, u64::MAX));
+        assert!(outside(64, 8));
+        assert_eq!(check_sample_range(0, &SampleMetadata::new(8, 56), 8, 64, 128).unwrap(), (72, 56));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_direct_storage_reader() {
+        use crate::storage::DirectConfig;
+
+        let large = vec![7u8; 3 * page_size()];
+        let (file1, _) = create_checksummed_shard(&[b"sample1", b"sample2", &large], ChecksumKind::Crc32c);
+        let file2 = create_v1_shard(&[b"v1"]);
+        let config = ReaderConfig {
+            verify: VerifyMode::OnOpen,
+            storage: StorageBackend::Direct(DirectConfig::default()),
+            ..Default::default()
+        };
+        let reader = ShardReader::with_config(file1.path(), config.clone()).unwrap();
+        assert!(reader.mmap().is_none());
+        assert_eq!(reader.get_sample(1).unwrap(), b"sample2");
+        assert_eq!(reader.get_sample(0).unwrap(), b"sample1");
+
+        // ヘッダーと同じページにないサンプルはバッファに読み込むので、借用として返せない
+        let mut buf = Vec::new();
+        assert!(reader.get_sample(2).unwrap().as_borrowed().is_none());
+        assert!(matches!(reader.get_view(2), Err(ReaderError::NotBorrowed(2))));
+        assert_eq!(reader.get_view_decoded(2, &mut buf).unwrap().data(), large);
+        assert_eq!(reader.get_sample_decoded(2, &mut buf).unwrap(), large);
+
+        let config = ReaderConfig {
+            max_open_shards: Some(1),
+            ..config
+        };
+        let reader = MultiShardReader::with_config(&[file1.path(), file2.path()], config).unwrap();
+        let first = reader.get_sample(2).unwrap();
+        assert_eq!(reader.get_sample(3).unwrap(), b"v1");
+        assert_eq!(first, large);
+        drop(first);
+        assert_eq!(reader.open_shards(), 1);
+        assert_eq!(reader.get_sample(1).unwrap(), b"sample2");
+    }
 }
\ No newline at end of file
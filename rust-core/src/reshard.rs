@@ -0,0 +1,385 @@
+use crate::format::{ShardHeader, ShardWriter, WriterConfig};
+use crate::reader::{ReaderError, ShardReader};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ReshardError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Reader error: {0}")]
+    Reader(#[from] ReaderError),
+    #[error("Incompatible shards: {0}")]
+    Incompatible(String),
+    #[error("Output {0} is also an input shard")]
+    OutputIsInput(PathBuf),
+}
+
+/// 出力シャード1つあたりの上限（どちらも指定しなければ上限なし）
+///
+/// バイト数は格納されたサンプルの合計で数える。上限を超える単独のサンプルは
+/// それだけで1つのシャードになる。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShardLimit {
+    pub max_samples: Option<usize>,
+    pub max_bytes: Option<u64>,
+}
+
+impl ShardLimit {
+    /// サンプル数で区切る
+    pub fn samples(max_samples: usize) -> Self {
+        Self {
+            max_samples: Some(max_samples.max(1)),
+            max_bytes: None,
+        }
+    }
+
+    /// 格納バイト数で区切る
+    pub fn bytes(max_bytes: u64) -> Self {
+        Self {
+            max_samples: None,
+            max_bytes: Some(max_bytes),
+        }
+    }
+
+    fn exceeded_by(&self, samples: usize, bytes: u64) -> bool {
+        self.max_samples.is_some_and(|max| samples > max) || self.max_bytes.is_some_and(|max| bytes > max)
+    }
+}
+
+/// 複数のシャードを1つにまとめる
+///
+/// キー・フィールド・スキーマ・圧縮済みのバイト列とチェックサムをそのまま引き継ぐ。
+/// 出力先が入力のいずれかと同じファイルならエラーを返す。
+pub fn merge<P: AsRef<Path>, Q: AsRef<Path>>(inputs: &[P], output: Q) -> Result<ShardHeader, ReshardError> {
+    let readers = open_all(inputs)?;
+    check_outputs(&readers, &[output.as_ref()])?;
+    let all: Runs = readers
+        .iter()
+        .enumerate()
+        .map(|(i, reader)| (i, 0..reader.num_samples()))
+        .collect();
+    write_shard(output.as_ref(), &readers, &all)
+}
+
+/// 1つのシャードを上限ごとに分割し、作成したシャードのパスを返す
+///
+/// 出力は `output_dir/<入力ファイル名>-00000.bin` のように連番で作成される。
+pub fn split<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output_dir: Q,
+    limit: ShardLimit,
+) -> Result<Vec<PathBuf>, ReshardError> {
+    let prefix = input
+        .as_ref()
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "shard".to_string());
+    reshard(&[input], output_dir, &prefix, limit)
+}
+
+/// シャードの集合を上限ごとに詰め直し、作成したシャードのパスを返す
+///
+/// サンプルの順序は入力の順に保たれ、出力は `output_dir/<prefix>-00000.bin` から連番になる。
+/// 出力先のいずれかが入力と同じファイルなら、何も書き込まずにエラーを返す。
+pub fn reshard<P: AsRef<Path>, Q: AsRef<Path>>(
+    inputs: &[P],
+    output_dir: Q,
+    prefix: &str,
+    limit: ShardLimit,
+) -> Result<Vec<PathBuf>, ReshardError> {
+    let readers = open_all(inputs)?;
+    let plans = plan(&readers, limit)?;
+    let outputs: Vec<PathBuf> = (0..plans.len())
+        .map(|i| output_dir.as_ref().join(format!("{}-{:05}.bin", prefix, i)))
+        .collect();
+    check_outputs(&readers, &outputs)?;
+    for (path, runs) in outputs.iter().zip(plans) {
+        write_shard(path, &readers, &runs)?;
+    }
+    Ok(outputs)
+}
+
+/// 出力先が入力シャードと同じファイルでないことを確認する
+///
+/// 入力はマップして読むため、上書きすると読み出し中にSIGBUSになりうる。
+fn check_outputs<P: AsRef<Path>>(readers: &[ShardReader], outputs: &[P]) -> Result<(), ReshardError> {
+    let inputs = readers
+        .iter()
+        .map(|reader| std::fs::canonicalize(reader.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    for output in outputs {
+        // まだ存在しない出力は入力と重ならない
+        let Ok(output) = std::fs::canonicalize(output) else {
+            continue;
+        };
+        if inputs.contains(&output) {
+            return Err(ReshardError::OutputIsInput(output));
+        }
+    }
+    Ok(())
+}
+
+fn open_all<P: AsRef<Path>>(inputs: &[P]) -> Result<Vec<ShardReader>, ReshardError> {
+    if inputs.is_empty() {
+        return Err(ReshardError::Incompatible("No input shards".to_string()));
+    }
+    let readers = inputs
+        .iter()
+        .map(ShardReader::new)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let first = &readers[0];
+    for reader in &readers[1..] {
+        if reader.field_names() != first.field_names() || reader.schema() != first.schema() {
+            return Err(ReshardError::Incompatible(format!(
+                "{} has different fields or schema from {}",
+                reader.path().display(),
+                first.path().display()
+            )));
+        }
+    }
+    Ok(readers)
+}
+
+/// 出力シャード1つ分の (入力シャード, サンプル範囲) の列
+type Runs = Vec<(usize, Range<usize>)>;
+
+/// 出力シャードごとに、入力シャードの連続したサンプル範囲の列を決める
+fn plan(readers: &[ShardReader], limit: ShardLimit) -> Result<Vec<Runs>, ReshardError> {
+    let mut shards = Vec::new();
+    let mut runs = Runs::new();
+    let (mut samples, mut bytes) = (0, 0);
+    for (shard, reader) in readers.iter().enumerate() {
+        for index in 0..reader.num_samples() {
+            let size = reader.sample_metadata(index)?.size;
+            if samples > 0 && limit.exceeded_by(samples + 1, bytes + size) {
+                shards.push(std::mem::take(&mut runs));
+                (samples, bytes) = (0, 0);
+            }
+            match runs.last_mut() {
+                Some((last, range)) if *last == shard => range.end = index + 1,
+                _ => runs.push((shard, index..index + 1)),
+            }
+            samples += 1;
+            bytes += size;
+        }
+    }
+    if !runs.is_empty() {
+        shards.push(runs);
+    }
+    Ok(shards)
+}
+
+fn write_shard(
+    path: &Path,
+    readers: &[ShardReader],
+    runs: &[(usize, Range<usize>)],
+) -> Result<ShardHeader, ReshardError> {
+    let first = &readers[0];
+    // サンプルはフィールドの位置を変えずにコピーするので、どの入力のアライメントも割り切る
+    // 最小のもの（2の冪なので最小値）を出力のアライメントにする
+    let alignment = runs
+        .iter()
+        .map(|(shard, _)| readers[*shard].header().data_alignment())
+        .min()
+        .unwrap_or(1);
+    let config = WriterConfig {
+        checksum: first.header().checksum_kind,
+        fields: first.field_names().to_vec(),
+        schema: first.schema().cloned(),
+        alignment: alignment as u32,
+        ..Default::default()
+    };
+    let mut writer = ShardWriter::with_config(path, config)?;
+    for (shard, range) in runs {
+        copy_range(&mut writer, &readers[*shard], range.clone())?;
+    }
+    Ok(writer.finish()?)
+}
+
+/// 入力シャードのサンプル範囲を出力へコピーする
+///
+/// 入力のアライメントが出力のものの倍数なら、範囲全体をまとめて1回でコピーする。
+fn copy_range(writer: &mut ShardWriter, reader: &ShardReader, range: Range<usize>) -> Result<(), ReshardError> {
+    let samples = range
+        .clone()
+        .map(|index| Ok((reader.sample_metadata(index)?, reader.sample_range(index)?)))
+        .collect::<Result<Vec<_>, ReaderError>>()?;
+    let Some(start) = samples.iter().map(|(_, (offset, _))| *offset).min() else {
+        return Ok(());
+    };
+    let end = samples.iter().map(|(_, (offset, size))| offset + size).max().unwrap_or(start);
+
+    let alignment = writer.alignment() as u64;
+    let bulk = reader.header().data_alignment().is_multiple_of(alignment);
+    let base = if bulk {
+        let bytes = reader.storage().read(start as usize, (end - start) as usize).map_err(ReaderError::Mmap)?;
+        Some(writer.copy_data(reader.storage().file(), start, &bytes)?)
+    } else {
+        None
+    };
+
+    let source_kind = reader.header().checksum_kind;
+    let target_kind = writer.checksum_kind();
+    let num_fields = reader.field_names().len();
+    for (index, (mut sample, (offset, size))) in range.zip(samples) {
+        let bytes = reader.storage().read(offset as usize, size as usize).map_err(ReaderError::Mmap)?;
+        sample.offset = match base {
+            Some(base) => base + (offset - start),
+            None => writer.copy_data(reader.storage().file(), offset, &bytes)?,
+        };
+        if source_kind != target_kind {
+            sample.checksum = target_kind.is_enabled().then(|| target_kind.compute(&bytes));
+        }
+        let fields: Vec<_> = (0..num_fields).map(|field| reader.field_range(index, field)).collect();
+        writer.append_stored(sample, &fields, reader.sample_key(index));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::{ChecksumKind, VerifyMode};
+    use crate::codec::Codec;
+    use crate::reader::{MultiShardReader, ReaderConfig};
+
+    fn write_test_shard(path: &Path, config: WriterConfig, samples: &[(&str, &[u8])]) {
+        let mut writer = ShardWriter::with_config(path, config).unwrap();
+        for (key, data) in samples {
+            writer.append_fields_with_key(key, &[("a", data), ("b", key.as_bytes())]).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn field_config(checksum: ChecksumKind, codec: Codec, alignment: u32) -> WriterConfig {
+        WriterConfig {
+            checksum,
+            codec,
+            fields: vec!["a".to_string(), "b".to_string()],
+            alignment,
+            ..Default::default()
+        }
+    }
+
+    /// サンプルとフィールドの先頭がすべて `alignment()` の倍数であることを確かめる
+    fn assert_aligned(reader: &ShardReader) {
+        let alignment = reader.alignment() as u64;
+        for index in 0..reader.num_samples() {
+            let (offset, _) = reader.sample_range(index).unwrap();
+            assert!(offset.is_multiple_of(alignment));
+            for field in 0..reader.field_names().len() {
+                if let Some((offset, _)) = reader.field_range(index, field) {
+                    assert!(offset.is_multiple_of(alignment), "sample {} field {}", index, field);
+                }
+            }
+        }
+    }
+
+    fn verify_config() -> ReaderConfig {
+        ReaderConfig {
+            verify: VerifyMode::OnOpen,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let long = b"z".repeat(400);
+        let (first, second, merged) = (dir.path().join("1.bin"), dir.path().join("2.bin"), dir.path().join("m.bin"));
+        // 圧縮したサンプルは格納されたまま写す（zstdのないビルドでは無圧縮で確かめる）
+        let codec = if Codec::Zstd.is_available() { Codec::Zstd } else { Codec::None };
+        write_test_shard(
+            &first,
+            field_config(ChecksumKind::Crc32c, codec, 64),
+            &[("k0", &long), ("k1", b"one")],
+        );
+        // チェックサムとアライメントが異なるシャードもまとめられる
+        write_test_shard(&second, field_config(ChecksumKind::XxHash64, Codec::None, 1), &[("k2", b"two")]);
+
+        let header = merge(&[&first, &second], &merged).unwrap();
+        assert_eq!(header.num_samples, 3);
+        assert_eq!(header.alignment, 1);
+
+        let reader = ShardReader::with_config(&merged, verify_config()).unwrap();
+        assert_aligned(&reader);
+        assert_eq!(reader.field_names(), ["a", "b"]);
+        assert_eq!(reader.sample_metadata(0).unwrap().codec, codec);
+        let mut buf = Vec::new();
+        assert_eq!(reader.get_view_decoded(0, &mut buf).unwrap().field("a").unwrap(), long.as_slice());
+        assert_eq!(reader.get_view(2).unwrap().field("a").unwrap(), b"two");
+        assert_eq!(reader.get_view(2).unwrap().field("b").unwrap(), b"k2");
+        assert_eq!(reader.find_key("k1").unwrap(), Some(1));
+        assert_eq!(reader.sample_key(2), Some("k2"));
+    }
+
+    #[test]
+    fn test_merge_alignment() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second, merged) = (dir.path().join("1.bin"), dir.path().join("2.bin"), dir.path().join("m.bin"));
+        write_test_shard(&first, field_config(ChecksumKind::None, Codec::None, 64), &[("k0", b"abc"), ("k1", b"d")]);
+        write_test_shard(&second, field_config(ChecksumKind::None, Codec::None, 16), &[("k2", b"efgh")]);
+
+        // 入力のアライメントのうち小さいほうに揃え、フィールドもその境界に置かれたまま
+        let header = merge(&[&first, &second], &merged).unwrap();
+        assert_eq!(header.alignment, 16);
+        let reader = ShardReader::new(&merged).unwrap();
+        assert_eq!(reader.alignment(), 16);
+        assert_aligned(&reader);
+        assert_eq!(reader.get_view(2).unwrap().field("b").unwrap(), b"k2");
+    }
+
+    #[test]
+    fn test_split_and_reshard() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.bin");
+        let samples: Vec<(String, Vec<u8>)> = (0..10).map(|i| (format!("k{}", i), vec![i as u8; 100])).collect();
+        let refs: Vec<(&str, &[u8])> = samples.iter().map(|(k, d)| (k.as_str(), d.as_slice())).collect();
+        write_test_shard(&input, field_config(ChecksumKind::Crc32c, Codec::None, 1), &refs);
+
+        let out = dir.path().join("by_samples");
+        std::fs::create_dir(&out).unwrap();
+        let parts = split(&input, &out, ShardLimit::samples(4)).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert!(parts[0].ends_with("input-00000.bin"));
+        let reader = MultiShardReader::with_config(&parts, verify_config()).unwrap();
+        assert_eq!(reader.total_samples(), 10);
+        assert_eq!(reader.find_key("k9").unwrap(), Some(9));
+        assert_eq!(reader.get_view(5).unwrap().field("a").unwrap(), [5u8; 100]);
+
+        // 格納バイト数（サンプルあたり102バイト）で詰め直す
+        let out = dir.path().join("by_bytes");
+        std::fs::create_dir(&out).unwrap();
+        let shards = reshard(&parts, &out, "train", ShardLimit::bytes(350)).unwrap();
+        assert_eq!(shards.len(), 4);
+        let reader = MultiShardReader::new(&shards).unwrap();
+        assert_eq!(reader.total_samples(), 10);
+        assert_eq!(reader.get_view(9).unwrap().field("b").unwrap(), b"k9");
+    }
+
+    #[test]
+    fn test_output_is_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, merged) = (dir.path().join("part-00001.bin"), dir.path().join("m.bin"));
+        // copy_file_range を使う大きさのサンプルと、バッファ経由で書くサンプル
+        let large = vec![7u8; 256 << 10];
+        write_test_shard(&first, field_config(ChecksumKind::Crc32c, Codec::None, 1), &[("k0", &large), ("k1", b"x")]);
+        let before = std::fs::read(&first).unwrap();
+
+        assert!(matches!(merge(&[&first], &first), Err(ReshardError::OutputIsInput(_))));
+        // 2つ目の出力が入力と重なる場合も、1つ目を書く前に拒否する
+        let err = reshard(&[&first], dir.path(), "part", ShardLimit::samples(1)).unwrap_err();
+        assert!(matches!(err, ReshardError::OutputIsInput(_)));
+        assert!(!dir.path().join("part-00000.bin").exists());
+        assert_eq!(std::fs::read(&first).unwrap(), before);
+
+        merge(&[&first], &merged).unwrap();
+        let reader = ShardReader::with_config(&merged, verify_config()).unwrap();
+        assert_eq!(reader.get_view(0).unwrap().field("a").unwrap(), large.as_slice());
+        assert_eq!(reader.get_view(1).unwrap().field("a").unwrap(), b"x");
+    }
+}
@@ -0,0 +1,802 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SamplerError {
+    #[error("Rank {rank} is out of range for world size {world_size}")]
+    InvalidRank { rank: usize, world_size: usize },
+}
+
+/// 決定的な疑似乱数生成器（SplitMix64）
+///
+/// 実装が短く、他の言語でも同じ系列を再現できるため、シャッフル順の生成に使う。
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+    /// シードから生成器を作成
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// シードとエポックから、そのエポック用の生成器を作成
+    ///
+    /// エポックごとに系列が重ならないよう、両方を混ぜてから新しいシードにする。
+    pub fn for_epoch(seed: u64, epoch: u64) -> Self {
+        let epoch_seed = Self::new(epoch).next_u64();
+        Self::new(Self::new(seed ^ epoch_seed).next_u64())
+    }
+
+    /// 次の64ビット値を取得
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(Self::GAMMA);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// `0..bound` の値を取得（`bound` は1以上）
+    pub fn below(&mut self, bound: usize) -> usize {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as usize
+    }
+
+    /// スライスをFisher-Yatesでシャッフル
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+
+    /// `0..n` のランダムな順列を取得
+    pub fn permutation(&mut self, n: usize) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..n).collect();
+        self.shuffle(&mut order);
+        order
+    }
+}
+
+/// エポックごとのサンプルの読み込み順を決める
+///
+/// 同じ設定・シード・エポックからは常に同じ順序を返す。
+pub trait Sampler: Send + Sync {
+    /// 1エポックで返すサンプル数
+    fn len(&self) -> usize;
+
+    /// 1エポックで返すサンプルがないかどうか
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 指定されたエポックのサンプル順（グローバルインデックス）を取得
+    fn order(&self, epoch: u64) -> Vec<usize>;
+
+    /// 指定されたエポックでシャードを読み始める順序（プリフェッチの順序に使う）
+    ///
+    /// シャードごとにまとまって読まない順序では `None`。
+    fn shard_order(&self, _epoch: u64) -> Option<Vec<usize>> {
+        None
+    }
+
+    /// 同時に読み進めるシャード数（プリフェッチで先に温めておくシャード数の目安）
+    fn window(&self) -> usize {
+        1
+    }
+
+    /// サンプラーの種類（状態に保存し、再開するときに同じサンプラーか確かめる）
+    ///
+    /// ビルドをまたいで変わらない名前を返す。組み込みのサンプラーは `ShuffleMode` の名前を使う。
+    fn kind(&self) -> String;
+}
+
+/// 先頭から順番に読むサンプラー
+#[derive(Debug, Clone)]
+pub struct SequentialSampler {
+    num_samples: usize,
+}
+
+impl SequentialSampler {
+    /// サンプル数を指定して作成
+    pub fn new(num_samples: usize) -> Self {
+        Self { num_samples }
+    }
+}
+
+impl Sampler for SequentialSampler {
+    fn len(&self) -> usize {
+        self.num_samples
+    }
+
+    fn order(&self, _epoch: u64) -> Vec<usize> {
+        (0..self.num_samples).collect()
+    }
+
+    fn kind(&self) -> String {
+        ShuffleMode::Sequential.name().to_string()
+    }
+}
+
+/// 全サンプルをエポックごとにランダムに並べ替えるサンプラー
+#[derive(Debug, Clone)]
+pub struct RandomSampler {
+    num_samples: usize,
+    seed: u64,
+}
+
+impl RandomSampler {
+    /// サンプル数とシードを指定して作成
+    pub fn new(num_samples: usize, seed: u64) -> Self {
+        Self { num_samples, seed }
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn len(&self) -> usize {
+        self.num_samples
+    }
+
+    fn order(&self, epoch: u64) -> Vec<usize> {
+        SplitMix64::for_epoch(self.seed, epoch).permutation(self.num_samples)
+    }
+
+    fn kind(&self) -> String {
+        ShuffleMode::Random.name().to_string()
+    }
+}
+
+/// シャードの順序をシャッフルし、各シャード内のサンプルをシャッフルするサンプラー
+///
+/// 一度に読むのは1シャードだけなので、全体の順列よりページキャッシュに優しい。
+#[derive(Debug, Clone)]
+pub struct ShardShuffleSampler {
+    shard_sizes: Vec<usize>,
+    seed: u64,
+}
+
+impl ShardShuffleSampler {
+    /// 各シャードのサンプル数とシードを指定して作成
+    pub fn new(shard_sizes: Vec<usize>, seed: u64) -> Self {
+        Self { shard_sizes, seed }
+    }
+}
+
+impl Sampler for ShardShuffleSampler {
+    fn len(&self) -> usize {
+        self.shard_sizes.iter().sum()
+    }
+
+    fn order(&self, epoch: u64) -> Vec<usize> {
+        let starts = shard_starts(&self.shard_sizes);
+        let mut rng = SplitMix64::for_epoch(self.seed, epoch);
+        let mut order = Vec::with_capacity(self.len());
+        for shard in rng.permutation(self.shard_sizes.len()) {
+            let samples = rng.permutation(self.shard_sizes[shard]);
+            order.extend(samples.into_iter().map(|sample| starts[shard] + sample));
+        }
+        order
+    }
+
+    fn shard_order(&self, epoch: u64) -> Option<Vec<usize>> {
+        Some(SplitMix64::for_epoch(self.seed, epoch).permutation(self.shard_sizes.len()))
+    }
+
+    fn kind(&self) -> String {
+        ShuffleMode::Shard.name().to_string()
+    }
+}
+
+/// シャードの順序をシャッフルし、K個のシャードのウィンドウ内でサンプルをシャッフルするサンプラー
+///
+/// シャッフルした順にシャードをウィンドウに入れ、ウィンドウ内のシャードからランダムに
+/// サンプルを取り出してシャッフルバッファに入れ、バッファからランダムに出力する。
+/// シャードを読み終えると次のシャードがウィンドウに入るので、同時に読むのはウィンドウの
+/// シャードとバッファに残ったサンプルだけになり、ページキャッシュの局所性を保てる。
+#[derive(Debug, Clone)]
+pub struct BlockShuffleSampler {
+    shard_sizes: Vec<usize>,
+    seed: u64,
+    window: usize,
+    buffer_size: usize,
+}
+
+impl BlockShuffleSampler {
+    /// 各シャードのサンプル数、シード、ウィンドウのシャード数、シャッフルバッファのサンプル数を指定して作成
+    pub fn new(shard_sizes: Vec<usize>, seed: u64, window: usize, buffer_size: usize) -> Self {
+        Self {
+            shard_sizes,
+            seed,
+            window: window.max(1),
+            buffer_size: buffer_size.max(1),
+        }
+    }
+}
+
+impl Sampler for BlockShuffleSampler {
+    fn len(&self) -> usize {
+        self.shard_sizes.iter().sum()
+    }
+
+    fn order(&self, epoch: u64) -> Vec<usize> {
+        let starts = shard_starts(&self.shard_sizes);
+        let mut rng = SplitMix64::for_epoch(self.seed, epoch);
+        let mut pending = rng.permutation(self.shard_sizes.len()).into_iter();
+        let mut active: Vec<Vec<usize>> = Vec::with_capacity(self.window);
+        let mut buffer = Vec::with_capacity(self.buffer_size);
+        let mut order = Vec::with_capacity(self.len());
+
+        // ウィンドウに入ったシャードのサンプルは、入った時点でシャード内の順序を決める
+        let mut enter = |rng: &mut SplitMix64, active: &mut Vec<Vec<usize>>| {
+            for shard in pending.by_ref() {
+                if self.shard_sizes[shard] > 0 {
+                    let samples = rng.permutation(self.shard_sizes[shard]);
+                    active.push(samples.into_iter().map(|sample| starts[shard] + sample).collect());
+                    return;
+                }
+            }
+        };
+        for _ in 0..self.window {
+            enter(&mut rng, &mut active);
+        }
+
+        while !active.is_empty() {
+            let shard = rng.below(active.len());
+            let sample = active[shard].pop().expect("active shards are not empty");
+            if active[shard].is_empty() {
+                active.swap_remove(shard);
+                enter(&mut rng, &mut active);
+            }
+            if buffer.len() < self.buffer_size {
+                buffer.push(sample);
+            } else {
+                let slot = rng.below(buffer.len());
+                order.push(std::mem::replace(&mut buffer[slot], sample));
+            }
+        }
+        while !buffer.is_empty() {
+            let slot = rng.below(buffer.len());
+            order.push(buffer.swap_remove(slot));
+        }
+        order
+    }
+
+    fn shard_order(&self, epoch: u64) -> Option<Vec<usize>> {
+        Some(SplitMix64::for_epoch(self.seed, epoch).permutation(self.shard_sizes.len()))
+    }
+
+    fn window(&self) -> usize {
+        self.window
+    }
+
+    fn kind(&self) -> String {
+        ShuffleMode::Block.name().to_string()
+    }
+}
+
+/// 分散学習でランク間にサンプルを分ける単位
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionMode {
+    /// エポックの読み込み順を連続した区間に分ける
+    #[default]
+    Samples,
+    /// シャード単位で分ける（各ランクは自分のシャードのサンプルだけを読む）
+    Shards,
+}
+
+impl PartitionMode {
+    /// 名前から単位を取得（"samples" / "shards"）
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "samples" => Some(Self::Samples),
+            "shards" => Some(Self::Shards),
+            _ => None,
+        }
+    }
+}
+
+/// 別のサンプラーの読み込み順を `world_size` 個のランクで重ならないように分けるサンプラー
+///
+/// 全ランクが同じシードで同じ順序を作り、自分の取り分だけを返す。`drop_last` なら
+/// 余りを捨て、そうでなければ順序の先頭から繰り返して埋め、全ランクのサンプル数
+/// （ステップ数）を揃える（サンプル単位ではエポック全体の順序、シャード単位では
+/// 自分の取り分の順序の先頭から）。
+pub struct DistributedSampler {
+    inner: Box<dyn Sampler>,
+    rank: usize,
+    world_size: usize,
+    drop_last: bool,
+    shard_sizes: Vec<usize>,
+    starts: Vec<usize>,
+    partition: Partition,
+}
+
+/// ランクへの割り当て
+enum Partition {
+    /// 読み込み順を `world_size` 等分した区間
+    Samples,
+    /// 各シャードを担当するランクと、各ランクのサンプル数
+    Shards { owner: Vec<usize>, counts: Vec<usize> },
+}
+
+impl DistributedSampler {
+    /// サンプル単位で分けるサンプラーを作成
+    pub fn new(
+        inner: Box<dyn Sampler>,
+        shard_sizes: &[usize],
+        rank: usize,
+        world_size: usize,
+        drop_last: bool,
+    ) -> Result<Self, SamplerError> {
+        Self::check_rank(rank, world_size)?;
+        Ok(Self {
+            inner,
+            rank,
+            world_size,
+            drop_last,
+            shard_sizes: shard_sizes.to_vec(),
+            starts: shard_starts(shard_sizes),
+            partition: Partition::Samples,
+        })
+    }
+
+    /// シャード単位で分けるサンプラーを作成
+    ///
+    /// シャードはサンプル数が偏らないように（大きい順に最も少ないランクへ）割り当て、
+    /// 割り当てはエポックによらない。各ランクの順序は `inner` の順序から自分のシャードの
+    /// サンプルを取り出したもの。
+    pub fn by_shards(
+        inner: Box<dyn Sampler>,
+        shard_sizes: &[usize],
+        rank: usize,
+        world_size: usize,
+        drop_last: bool,
+    ) -> Result<Self, SamplerError> {
+        Self::check_rank(rank, world_size)?;
+        let mut shards: Vec<usize> = (0..shard_sizes.len()).collect();
+        shards.sort_by_key(|&shard| std::cmp::Reverse(shard_sizes[shard]));
+        let mut owner = vec![0; shard_sizes.len()];
+        let mut counts = vec![0; world_size];
+        for shard in shards {
+            let rank = (0..world_size).min_by_key(|&rank| counts[rank]).unwrap_or(0);
+            owner[shard] = rank;
+            counts[rank] += shard_sizes[shard];
+        }
+        Ok(Self {
+            inner,
+            rank,
+            world_size,
+            drop_last,
+            shard_sizes: shard_sizes.to_vec(),
+            starts: shard_starts(shard_sizes),
+            partition: Partition::Shards { owner, counts },
+        })
+    }
+
+    fn check_rank(rank: usize, world_size: usize) -> Result<(), SamplerError> {
+        if rank >= world_size {
+            return Err(SamplerError::InvalidRank { rank, world_size });
+        }
+        Ok(())
+    }
+
+    /// このランクの番号
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// ランク数
+    pub fn world_size(&self) -> usize {
+        self.world_size
+    }
+
+    /// 各シャードを担当するランク（シャード単位で分ける場合のみ）
+    pub fn shard_owners(&self) -> Option<&[usize]> {
+        match &self.partition {
+            Partition::Samples => None,
+            Partition::Shards { owner, .. } => Some(owner),
+        }
+    }
+
+    /// サンプルのグローバルインデックスからシャード番号を取得
+    fn shard_of(&self, index: usize) -> usize {
+        self.starts.partition_point(|&start| start <= index) - 1
+    }
+
+    /// 余りを捨てるか埋めるかに応じて、取り分を `len` に揃える
+    ///
+    /// 埋めるサンプルは `fill` の先頭から繰り返して使う。
+    fn fit(&self, mut order: Vec<usize>, fill: &[usize]) -> Vec<usize> {
+        let len = self.len();
+        order.truncate(len);
+        if !fill.is_empty() {
+            while order.len() < len {
+                let missing = (len - order.len()).min(fill.len());
+                order.extend_from_slice(&fill[..missing]);
+            }
+        }
+        order
+    }
+}
+
+impl Sampler for DistributedSampler {
+    fn len(&self) -> usize {
+        match &self.partition {
+            Partition::Samples if self.drop_last => self.inner.len() / self.world_size,
+            Partition::Samples => self.inner.len().div_ceil(self.world_size),
+            Partition::Shards { counts, .. } if self.drop_last => counts.iter().copied().min().unwrap_or(0),
+            Partition::Shards { counts, .. } => counts.iter().copied().max().unwrap_or(0),
+        }
+    }
+
+    fn order(&self, epoch: u64) -> Vec<usize> {
+        let order = self.inner.order(epoch);
+        match &self.partition {
+            Partition::Samples => {
+                let len = self.len();
+                let start = (self.rank * len).min(order.len());
+                let end = (start + len).min(order.len());
+                self.fit(order[start..end].to_vec(), &order)
+            }
+            Partition::Shards { owner, .. } => {
+                let mine: Vec<usize> = order
+                    .iter()
+                    .copied()
+                    .filter(|&index| owner[self.shard_of(index)] == self.rank)
+                    .collect();
+                // 担当するサンプルがなければ全体の順序で埋める
+                let fill = if mine.is_empty() { order } else { mine.clone() };
+                self.fit(mine, &fill)
+            }
+        }
+    }
+
+    /// 元の順序がシャード単位なら、このランクの取り分にシャードが現れる順にする
+    ///
+    /// サンプル順は作らず、各シャードが元のシャード順でまとまって現れるとみなして、
+    /// 取り分の区間と埋める区間に重なるシャードを選ぶ（ウィンドウで混ぜる順序では近似）。
+    fn shard_order(&self, epoch: u64) -> Option<Vec<usize>> {
+        let inner = self.inner.shard_order(epoch)?;
+        let len = self.len();
+        // 取り分を切り出す順序（シャードの並び）と、その中での取り分の開始位置
+        let (shards, start) = match &self.partition {
+            Partition::Samples => (inner, self.rank * len),
+            Partition::Shards { owner, counts } if counts[self.rank] > 0 => {
+                (inner.into_iter().filter(|&shard| owner[shard] == self.rank).collect(), 0)
+            }
+            // 担当するサンプルがなければ全体の順序で埋める
+            Partition::Shards { .. } => (inner, 0),
+        };
+        let spans: Vec<(usize, usize, usize)> = shards
+            .into_iter()
+            .scan(0, |next, shard| {
+                let begin = *next;
+                *next += self.shard_sizes[shard];
+                Some((shard, begin, *next))
+            })
+            .filter(|&(_, begin, end)| begin < end)
+            .collect();
+        let total = spans.last().map_or(0, |&(_, _, end)| end);
+        let start = start.min(total);
+        let end = (start + len).min(total);
+        let fill = len - (end - start);
+
+        let in_slice = |&&(_, begin, stop): &&(usize, usize, usize)| begin < end && start < stop;
+        let mut order: Vec<usize> = spans.iter().filter(in_slice).map(|&(shard, ..)| shard).collect();
+        // 足りない分は順序の先頭から埋めるので、その区間のシャードが後に続く
+        order.extend(
+            spans
+                .iter()
+                .filter(|span| !in_slice(span) && span.1 < fill)
+                .map(|&(shard, ..)| shard),
+        );
+        Some(order)
+    }
+
+    fn window(&self) -> usize {
+        self.inner.window()
+    }
+
+    fn kind(&self) -> String {
+        format!("distributed({})", self.inner.kind())
+    }
+}
+
+/// 各シャードの先頭サンプルのグローバルインデックス
+fn shard_starts(shard_sizes: &[usize]) -> Vec<usize> {
+    shard_sizes
+        .iter()
+        .scan(0, |next, &size| {
+            let start = *next;
+            *next += size;
+            Some(start)
+        })
+        .collect()
+}
+
+/// シャッフルの方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShuffleMode {
+    /// シャッフルしない
+    #[default]
+    Sequential,
+    /// 全サンプルの順列
+    Random,
+    /// シャードの順序とシャード内の順序をシャッフル
+    Shard,
+    /// シャードの順序をシャッフルし、シャードのウィンドウ内でサンプルをシャッフル
+    Block,
+}
+
+impl ShuffleMode {
+    /// 名前から方式を取得（"sequential" / "random" / "shard" / "block"）
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sequential" => Some(Self::Sequential),
+            "random" => Some(Self::Random),
+            "shard" => Some(Self::Shard),
+            "block" => Some(Self::Block),
+            _ => None,
+        }
+    }
+
+    /// 方式の名前を取得
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sequential => "sequential",
+            Self::Random => "random",
+            Self::Shard => "shard",
+            Self::Block => "block",
+        }
+    }
+}
+
+/// サンプラーの設定
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplerConfig {
+    /// シャッフルの方式
+    pub shuffle: ShuffleMode,
+    /// シャッフルのシード（エポックごとにエポック番号と混ぜて使う）
+    pub seed: u64,
+    /// `Block` で同時に読み進めるシャード数
+    pub window_shards: usize,
+    /// `Block` のシャッフルバッファのサンプル数
+    pub shuffle_buffer: usize,
+    /// 分散学習でのこのプロセスのランク
+    pub rank: usize,
+    /// 分散学習のランク数（1なら分けない）
+    pub world_size: usize,
+    /// ランク間でサンプルを分ける単位
+    pub partition: PartitionMode,
+    /// ランク間でサンプル数を揃えるとき、余りを捨てる（`false` なら先頭から繰り返して埋める）
+    pub drop_last: bool,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            shuffle: ShuffleMode::default(),
+            seed: 0,
+            window_shards: 4,
+            shuffle_buffer: 1024,
+            rank: 0,
+            world_size: 1,
+            partition: PartitionMode::default(),
+            drop_last: false,
+        }
+    }
+}
+
+/// 設定と各シャードのサンプル数からサンプラーを作成
+///
+/// `world_size` が2以上なら、`rank` の取り分だけを返すサンプラーになる。
+pub fn create_sampler(config: &SamplerConfig, shard_sizes: &[usize]) -> Result<Box<dyn Sampler>, SamplerError> {
+    let sampler = create_shuffle_sampler(config, shard_sizes);
+    if config.world_size <= 1 && config.rank == 0 {
+        return Ok(sampler);
+    }
+    let (rank, world_size, drop_last) = (config.rank, config.world_size, config.drop_last);
+    Ok(Box::new(match config.partition {
+        PartitionMode::Samples => DistributedSampler::new(sampler, shard_sizes, rank, world_size, drop_last)?,
+        PartitionMode::Shards => DistributedSampler::by_shards(sampler, shard_sizes, rank, world_size, drop_last)?,
+    }))
+}
+
+fn create_shuffle_sampler(config: &SamplerConfig, shard_sizes: &[usize]) -> Box<dyn Sampler> {
+    let num_samples = shard_sizes.iter().sum();
+    match config.shuffle {
+        ShuffleMode::Sequential => Box::new(SequentialSampler::new(num_samples)),
+        ShuffleMode::Random => Box::new(RandomSampler::new(num_samples, config.seed)),
+        ShuffleMode::Shard => Box::new(ShardShuffleSampler::new(shard_sizes.to_vec(), config.seed)),
+        ShuffleMode::Block => Box::new(BlockShuffleSampler::new(
+            shard_sizes.to_vec(),
+            config.seed,
+            config.window_shards,
+            config.shuffle_buffer,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_permutation(order: &[usize], n: usize) -> bool {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        sorted == (0..n).collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn test_split_mix64() {
+        // 他の言語の実装と突き合わせられるよう、系列を固定しておく
+        let mut rng = SplitMix64::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+        assert_eq!(RandomSampler::new(8, 42).order(0), RandomSampler::new(8, 42).order(0));
+    }
+
+    #[test]
+    fn test_random_sampler() {
+        let sampler = RandomSampler::new(100, 7);
+        let epoch0 = sampler.order(0);
+        assert!(is_permutation(&epoch0, 100));
+        assert_eq!(sampler.order(0), epoch0);
+        assert_ne!(sampler.order(1), epoch0);
+        assert_ne!(RandomSampler::new(100, 8).order(0), epoch0);
+        assert_eq!(SequentialSampler::new(3).order(5), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_shard_shuffle_sampler() {
+        let sizes = vec![3, 0, 5, 2];
+        let sampler = ShardShuffleSampler::new(sizes.clone(), 1);
+        assert_eq!(sampler.len(), 10);
+        let order = sampler.order(0);
+        assert!(is_permutation(&order, 10));
+        assert_eq!(sampler.order(0), order);
+
+        // 各シャードのサンプルは連続して現れる
+        let starts = shard_starts(&sizes);
+        let shard_of = |index: usize| starts.iter().rposition(|&start| start <= index).unwrap();
+        let mut seen = Vec::new();
+        for index in order {
+            let shard = shard_of(index);
+            if seen.last() != Some(&shard) {
+                assert!(!seen.contains(&shard));
+                seen.push(shard);
+            }
+        }
+        assert_eq!(seen.len(), 3);
+
+        let config = SamplerConfig {
+            shuffle: ShuffleMode::from_name("shard").unwrap(),
+            seed: 1,
+            ..Default::default()
+        };
+        assert_eq!(create_sampler(&config, &sizes).unwrap().order(3), sampler.order(3));
+    }
+
+    #[test]
+    fn test_block_shuffle_sampler() {
+        let sizes = vec![4, 0, 6, 3, 5];
+        let shard_of = |index: usize| {
+            let starts = shard_starts(&sizes);
+            starts.iter().rposition(|&start| start <= index).unwrap()
+        };
+
+        // ウィンドウとバッファが1なら、シャードの順序どおりに1シャードずつ読む
+        let sampler = BlockShuffleSampler::new(sizes.clone(), 3, 1, 1);
+        let order = sampler.order(0);
+        assert!(is_permutation(&order, 18));
+        let mut shards: Vec<usize> = order.iter().map(|&index| shard_of(index)).collect();
+        shards.dedup();
+        let expected: Vec<usize> = sampler.shard_order(0).unwrap().into_iter().filter(|&s| sizes[s] > 0).collect();
+        assert_eq!(shards, expected);
+
+        // ウィンドウの外のシャードはまだ読まれない
+        let sampler = BlockShuffleSampler::new(sizes.clone(), 3, 2, 1);
+        let order = sampler.order(1);
+        assert!(is_permutation(&order, 18));
+        assert_eq!(sampler.order(1), order);
+        let rank: Vec<usize> = {
+            let shard_order = sampler.shard_order(1).unwrap();
+            (0..sizes.len()).map(|s| shard_order.iter().position(|&o| o == s).unwrap()).collect()
+        };
+        for (pos, &index) in order.iter().enumerate() {
+            // 読み終えたシャードより後ろはウィンドウ分だけ先まで
+            let finished = (0..sizes.len())
+                .filter(|&s| {
+                    let start = shard_starts(&sizes)[s];
+                    (start..start + sizes[s]).all(|i| order[..pos].contains(&i))
+                })
+                .count();
+            assert!(rank[shard_of(index)] < finished + 2 + 1);
+        }
+
+        let sampler = BlockShuffleSampler::new(sizes, 3, 2, 8);
+        assert!(is_permutation(&sampler.order(0), 18));
+        assert_eq!(sampler.window(), 2);
+    }
+
+    #[test]
+    fn test_distributed_sampler() {
+        let sizes = vec![5, 2, 3];
+        let config = |rank, partition, drop_last| SamplerConfig {
+            shuffle: ShuffleMode::Shard,
+            seed: 11,
+            rank,
+            world_size: 3,
+            partition,
+            drop_last,
+            ..Default::default()
+        };
+        let full = ShardShuffleSampler::new(sizes.clone(), 11).order(2);
+
+        // サンプル単位: 全体の順序を等分し、余りは先頭から埋める
+        for drop_last in [false, true] {
+            let orders: Vec<Vec<usize>> = (0..3)
+                .map(|rank| create_sampler(&config(rank, PartitionMode::Samples, drop_last), &sizes).unwrap().order(2))
+                .collect();
+            let len = if drop_last { 3 } else { 4 };
+            assert!(orders.iter().all(|order| order.len() == len));
+            let joined: Vec<usize> = orders.concat();
+            if drop_last {
+                assert_eq!(joined, full[..9]);
+            } else {
+                assert_eq!(joined[..10], full[..]);
+                assert_eq!(joined[10..], full[..2]);
+            }
+        }
+
+        // シャード単位: 各シャードは1つのランクだけが読む
+        let samplers: Vec<Box<dyn Sampler>> = (0..3)
+            .map(|rank| create_sampler(&config(rank, PartitionMode::Shards, true), &sizes).unwrap())
+            .collect();
+        assert!(samplers.iter().all(|sampler| sampler.len() == 2));
+        assert_eq!(samplers[0].kind(), "distributed(shard)");
+        let padded = create_sampler(&config(2, PartitionMode::Shards, false), &sizes).unwrap();
+        assert_eq!(padded.len(), 5);
+        let order = padded.order(0);
+        assert_eq!(order.len(), 5);
+        assert_eq!(order[2..4], order[..2]);
+        let mut seen: Vec<usize> = samplers.iter().flat_map(|sampler| sampler.shard_order(0).unwrap()).collect();
+        seen.sort_unstable();
+        assert_eq!(seen, vec![0, 1, 2]);
+
+        // シャード順は、取り分のサンプル順にシャードが現れる順と一致する
+        let sizes = vec![5, 0, 2, 3, 7, 1];
+        let starts = shard_starts(&sizes);
+        for partition in [PartitionMode::Samples, PartitionMode::Shards] {
+            for drop_last in [false, true] {
+                for world_size in [2, 4, 8] {
+                    for rank in 0..world_size {
+                        let config = SamplerConfig {
+                            world_size,
+                            ..config(rank, partition, drop_last)
+                        };
+                        let sampler = create_sampler(&config, &sizes).unwrap();
+                        let mut expected = Vec::new();
+                        for index in sampler.order(3) {
+                            let shard = starts.partition_point(|&start| start <= index) - 1;
+                            if !expected.contains(&shard) {
+                                expected.push(shard);
+                            }
+                        }
+                        assert_eq!(sampler.shard_order(3).unwrap(), expected);
+                    }
+                }
+            }
+        }
+
+        assert!(matches!(
+            create_sampler(&config(3, PartitionMode::Samples, false), &sizes),
+            Err(SamplerError::InvalidRank { rank: 3, world_size: 3 })
+        ));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::buffer::ZeroCopyBuffer;
+use serde::{Deserialize, Serialize};
+
+/// 要素の型（名前はNumPyのdtype名に合わせる）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DType {
+    Uint8,
+    Int8,
+    Uint16,
+    Int16,
+    Uint32,
+    Int32,
+    Uint64,
+    Int64,
+    Float16,
+    Float32,
+    Float64,
+}
+
+impl DType {
+    /// 1要素あたりのバイト数
+    pub fn size(self) -> usize {
+        match self {
+            DType::Uint8 | DType::Int8 => 1,
+            DType::Uint16 | DType::Int16 | DType::Float16 => 2,
+            DType::Uint32 | DType::Int32 | DType::Float32 => 4,
+            DType::Uint64 | DType::Int64 | DType::Float64 => 8,
+        }
+    }
+}
+
+/// 要素のバイトオーダー
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+/// 1つのフィールド（またはサンプル全体）の型情報
+///
+/// `shape` の `-1` はサンプルのサイズから推論される次元（最大1つ）で、
+/// サンプルごとに長さが変わるデータを表す。空の `shape` は `[-1]` と同じ。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    /// フィールド名（空文字列はフィールドを持たないシャードのサンプル全体）
+    pub name: String,
+    pub dtype: DType,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shape: Vec<i64>,
+    #[serde(default)]
+    pub endianness: Endianness,
+}
+
+impl FieldSchema {
+    pub fn new(name: &str, dtype: DType, shape: &[i64]) -> Self {
+        Self {
+            name: name.to_string(),
+            dtype,
+            shape: shape.to_vec(),
+            endianness: Endianness::Little,
+        }
+    }
+
+    /// 形状の定義が正しいかどうかを検証
+    pub fn validate(&self) -> Result<(), String> {
+        let inferred = self.shape.iter().filter(|&&dim| dim == -1).count();
+        if inferred > 1 || self.shape.iter().any(|&dim| dim < -1) {
+            return Err(format!("Invalid shape for field '{}': {:?}", self.name, self.shape));
+        }
+        Ok(())
+    }
+
+    /// バイト数から具体的な形状を求める
+    pub fn resolve_shape(&self, byte_len: usize) -> Result<Vec<usize>, String> {
+        let mismatch = || {
+            format!(
+                "Field '{}' has {} bytes, which does not fit {:?} x {:?}",
+                self.name, byte_len, self.dtype, self.shape
+            )
+        };
+        if !byte_len.is_multiple_of(self.dtype.size()) {
+            return Err(mismatch());
+        }
+        let count = byte_len / self.dtype.size();
+        if self.shape.is_empty() {
+            return Ok(vec![count]);
+        }
+
+        let known: usize = self
+            .shape
+            .iter()
+            .filter(|&&dim| dim != -1)
+            .map(|&dim| dim as usize)
+            .product();
+        let inferred = if self.shape.contains(&-1) {
+            if known == 0 || !count.is_multiple_of(known) {
+                return Err(mismatch());
+            }
+            count / known
+        } else {
+            if known != count {
+                return Err(mismatch());
+            }
+            0
+        };
+        Ok(self
+            .shape
+            .iter()
+            .map(|&dim| if dim == -1 { inferred } else { dim as usize })
+            .collect())
+    }
+}
+
+/// シャードに埋め込まれる型スキーマ
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Schema {
+    pub fields: Vec<FieldSchema>,
+}
+
+impl Schema {
+    /// フィールドを持たないシャード用に、サンプル全体を1つの配列として記述する
+    pub fn single(dtype: DType, shape: &[i64]) -> Self {
+        Self {
+            fields: vec![FieldSchema::new("", dtype, shape)],
+        }
+    }
+
+    /// 名前でフィールドの型情報を取得
+    pub fn field(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// 各フィールドの定義を検証
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, field) in self.fields.iter().enumerate() {
+            field.validate()?;
+            if self.fields[..i].iter().any(|other| other.name == field.name) {
+                return Err(format!("Duplicate schema field: '{}'", field.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// 学習ジョブが期待するスキーマと互換かどうか（期待される各フィールドが同じ型で存在するか）
+    pub fn check_compatible(&self, expected: &Schema) -> Result<(), String> {
+        for want in &expected.fields {
+            match self.field(&want.name) {
+                Some(have) if have == want => {}
+                Some(have) => {
+                    return Err(format!(
+                        "Field '{}' is {:?} {:?} ({:?}), expected {:?} {:?} ({:?})",
+                        want.name,
+                        have.dtype,
+                        have.shape,
+                        have.endianness,
+                        want.dtype,
+                        want.shape,
+                        want.endianness
+                    ))
+                }
+                None => return Err(format!("Field '{}' is missing from the shard schema", want.name)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 型情報付きのゼロコピービュー
+#[derive(Debug, Clone)]
+pub struct TypedView<'a> {
+    pub dtype: DType,
+    pub shape: Vec<usize>,
+    pub endianness: Endianness,
+    pub data: &'a [u8],
+}
+
+impl<'a> TypedView<'a> {
+    /// 型情報とバイト列からビューを作成（形状が合わなければエラー）
+    pub fn new(schema: &FieldSchema, data: &'a [u8]) -> Result<Self, String> {
+        Ok(Self {
+            dtype: schema.dtype,
+            shape: schema.resolve_shape(data.len())?,
+            endianness: schema.endianness,
+            data,
+        })
+    }
+
+    /// 要素数を取得
+    pub fn num_elements(&self) -> usize {
+        self.data.len() / self.dtype.size()
+    }
+
+    /// 型付きスライスへ変換するためのバッファを取得
+    pub fn as_buffer(&self) -> ZeroCopyBuffer<'a> {
+        ZeroCopyBuffer::from_slice(self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_shape() {
+        let image = FieldSchema::new("img", DType::Uint8, &[2, 2, 3]);
+        assert_eq!(image.resolve_shape(12).unwrap(), vec![2, 2, 3]);
+        assert!(image.resolve_shape(11).is_err());
+
+        let tokens = FieldSchema::new("tokens", DType::Int32, &[-1]);
+        assert_eq!(tokens.resolve_shape(40).unwrap(), vec![10]);
+        assert!(tokens.resolve_shape(6).is_err());
+
+        let rows = FieldSchema::new("rows", DType::Float32, &[-1, 4]);
+        assert_eq!(rows.resolve_shape(48).unwrap(), vec![3, 4]);
+
+        assert!(FieldSchema::new("bad", DType::Uint8, &[-1, -1]).validate().is_err());
+    }
+
+    #[test]
+    fn test_schema_compatibility() {
+        let schema = Schema {
+            fields: vec![
+                FieldSchema::new("jpg", DType::Uint8, &[]),
+                FieldSchema::new("cls", DType::Int64, &[1]),
+            ],
+        };
+        let expected = Schema {
+            fields: vec![FieldSchema::new("cls", DType::Int64, &[1])],
+        };
+        assert!(schema.check_compatible(&expected).is_ok());
+
+        let wrong_dtype = Schema {
+            fields: vec![FieldSchema::new("cls", DType::Int32, &[1])],
+        };
+        assert!(schema.check_compatible(&wrong_dtype).is_err());
+
+        let json = serde_json::to_string(&schema).unwrap();
+        assert!(json.contains("\"uint8\""));
+        assert_eq!(serde_json::from_str::<Schema>(&json).unwrap(), schema);
+    }
+}
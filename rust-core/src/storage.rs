@@ -0,0 +1,542 @@
+use crate::mmap::{page_size, MmapConfig, MmapError, MmapManager};
+use std::fs::File;
+use std::path::Path;
+
+/// シャードの読み込み方式
+#[derive(Debug, Clone, Default)]
+pub enum StorageBackend {
+    /// メモリマップ（ゼロコピー）
+    #[default]
+    Mmap,
+    /// `O_DIRECT` でアライメントされたバッファに読み込む（ページキャッシュを通さない）
+    Direct(DirectConfig),
+}
+
+/// `O_DIRECT` 読み込みの設定
+#[derive(Debug, Clone)]
+pub struct DirectConfig {
+    /// io_uringで読み込みを発行する（使えなければ `pread` にフォールバック）
+    pub io_uring: bool,
+    /// 再利用のために保持するバッファの最大数
+    pub max_pooled: usize,
+}
+
+impl Default for DirectConfig {
+    fn default() -> Self {
+        Self {
+            io_uring: false,
+            max_pooled: 64,
+        }
+    }
+}
+
+/// シャードファイルへの読み取りアクセス（mmapと `O_DIRECT` の共通インターフェース）
+///
+/// `read_range` が返すスライスは `&self` の間有効。`O_DIRECT` では読んだ範囲をメモリに
+/// 保持し続けるので、ヘッダーやインデックスのように繰り返し読む範囲に使い、サンプルのように
+/// 一度だけ読む範囲は `read` で読む。
+pub trait ShardStorage: Send + Sync {
+    /// ファイルパスを取得
+    fn path(&self) -> &Path;
+
+    /// 元のファイルを取得
+    fn file(&self) -> &File;
+
+    /// ファイルサイズを取得
+    fn len(&self) -> usize;
+
+    /// ファイルが空かどうか
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 指定された範囲のバイト列を取得
+    fn read_range(&self, offset: usize, len: usize) -> Result<&[u8], MmapError>;
+
+    /// 繰り返し読む範囲（ヘッダーやインデックス）をメモリに保持する
+    fn pin(&self, _offset: usize, _len: usize) -> Result<(), MmapError> {
+        Ok(())
+    }
+
+    /// 指定された範囲を読み込む（`O_DIRECT` では返した値を破棄するとバッファを再利用する）
+    fn read(&self, offset: usize, len: usize) -> Result<StorageBytes<'_>, MmapError> {
+        self.read_range(offset, len).map(StorageBytes::Borrowed)
+    }
+
+    /// メモリマップで読んでいればそのマップを取得
+    fn as_mmap(&self) -> Option<&MmapManager> {
+        None
+    }
+}
+
+impl ShardStorage for MmapManager {
+    fn path(&self) -> &Path {
+        MmapManager::path(self)
+    }
+
+    fn file(&self) -> &File {
+        MmapManager::file(self)
+    }
+
+    fn len(&self) -> usize {
+        MmapManager::len(self)
+    }
+
+    fn read_range(&self, offset: usize, len: usize) -> Result<&[u8], MmapError> {
+        self.get_range(offset, len)
+    }
+
+    fn as_mmap(&self) -> Option<&MmapManager> {
+        Some(self)
+    }
+}
+
+/// `ShardStorage::read` が返すバイト列
+pub enum StorageBytes<'a> {
+    /// マップや保持している範囲からの借用
+    Borrowed(&'a [u8]),
+    /// `O_DIRECT` でプールのバッファに読み込んだ範囲
+    #[cfg(unix)]
+    Buffered(DirectBytes<'a>),
+}
+
+impl<'a> StorageBytes<'a> {
+    /// ストレージの寿命まで有効な借用であれば取り出す
+    pub fn as_borrowed(&self) -> Option<&'a [u8]> {
+        match self {
+            StorageBytes::Borrowed(data) => Some(data),
+            #[cfg(unix)]
+            StorageBytes::Buffered(_) => None,
+        }
+    }
+}
+
+impl std::ops::Deref for StorageBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            StorageBytes::Borrowed(data) => data,
+            #[cfg(unix)]
+            StorageBytes::Buffered(bytes) => bytes,
+        }
+    }
+}
+
+/// 設定に従ってシャードファイルを開く
+pub fn open_storage<P: AsRef<Path>>(
+    path: P,
+    backend: &StorageBackend,
+    mmap: &MmapConfig,
+) -> Result<Box<dyn ShardStorage>, MmapError> {
+    match backend {
+        StorageBackend::Mmap => Ok(Box::new(MmapManager::with_config(path, mmap)?)),
+        #[cfg(unix)]
+        StorageBackend::Direct(config) => Ok(Box::new(DirectStorage::open(path, config)?)),
+        #[cfg(not(unix))]
+        StorageBackend::Direct(_) => Err(MmapError::MapError(
+            "Direct I/O is not supported on this platform".to_string(),
+        )),
+    }
+}
+
+/// アライメントされたヒープバッファ
+#[cfg(unix)]
+struct AlignedBuffer {
+    ptr: std::ptr::NonNull<u8>,
+    layout: std::alloc::Layout,
+}
+
+#[cfg(unix)]
+impl AlignedBuffer {
+    fn new(capacity: usize, alignment: usize) -> Self {
+        let layout = std::alloc::Layout::from_size_align(capacity.max(alignment), alignment)
+            .expect("alignment must be a power of two");
+        let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+        let ptr = std::ptr::NonNull::new(ptr).unwrap_or_else(|| std::alloc::handle_alloc_error(layout));
+        Self { ptr, layout }
+    }
+
+    fn capacity(&self) -> usize {
+        self.layout.size()
+    }
+}
+
+#[cfg(unix)]
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
+
+// バッファは所有する領域を指すだけで、共有されるのは読み込み後の読み取り専用のスライス
+#[cfg(unix)]
+unsafe impl Send for AlignedBuffer {}
+#[cfg(unix)]
+unsafe impl Sync for AlignedBuffer {}
+
+/// ファイルの `offset` から `filled` バイトを読み込んだバッファ
+#[cfg(unix)]
+struct Loaded {
+    offset: usize,
+    filled: usize,
+    buffer: AlignedBuffer,
+}
+
+#[cfg(unix)]
+impl Loaded {
+    /// 範囲を含んでいればそのスライスを返す
+    ///
+    /// バッファは `Loaded` を手放すまで動かないので、ピンした範囲は `&DirectStorage` の間、
+    /// `DirectBytes` の範囲はその値の間有効。
+    fn slice<'a>(&self, offset: usize, len: usize) -> Option<&'a [u8]> {
+        if offset < self.offset || offset + len > self.offset + self.filled {
+            return None;
+        }
+        Some(unsafe { std::slice::from_raw_parts(self.buffer.ptr.as_ptr().add(offset - self.offset), len) })
+    }
+}
+
+/// `DirectStorage::read` でプールのバッファに読み込んだ範囲（破棄するとバッファをプールに戻す）
+#[cfg(unix)]
+pub struct DirectBytes<'a> {
+    storage: &'a DirectStorage,
+    loaded: Option<Loaded>,
+    offset: usize,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl std::ops::Deref for DirectBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let loaded = self.loaded.as_ref().expect("buffer is held until drop");
+        loaded.slice(self.offset, self.len).expect("loaded range covers the request")
+    }
+}
+
+#[cfg(unix)]
+impl Drop for DirectBytes<'_> {
+    fn drop(&mut self) {
+        if let Some(loaded) = self.loaded.take() {
+            self.storage.recycle(loaded.buffer);
+        }
+    }
+}
+
+/// `O_DIRECT` でファイルを読むストレージ
+///
+/// 範囲をページ境界に広げてアライメントされたプールのバッファに読み込み、ページキャッシュと
+/// ページフォルトを避ける。`read_range` で読んだ範囲は保持し続け、`read` で読んだバッファは
+/// 返した値を破棄した時点でプールに戻す。`O_DIRECT` に対応していないファイルシステム
+/// （tmpfsなど）では通常の読み込みにフォールバックする。
+#[cfg(unix)]
+pub struct DirectStorage {
+    file: File,
+    path: std::path::PathBuf,
+    len: usize,
+    alignment: usize,
+    direct: bool,
+    pinned: std::sync::Mutex<Vec<Loaded>>,
+    pool: std::sync::Mutex<Vec<AlignedBuffer>>,
+    max_pooled: usize,
+    #[cfg(all(target_os = "linux", feature = "io_uring"))]
+    ring: Option<std::sync::Mutex<io_uring::IoUring>>,
+}
+
+#[cfg(unix)]
+impl DirectStorage {
+    /// ファイルを `O_DIRECT` で開く
+    pub fn open<P: AsRef<Path>>(path: P, config: &DirectConfig) -> Result<Self, MmapError> {
+        let path = path.as_ref().to_path_buf();
+        let (file, direct) = Self::open_file(&path)?;
+        let len = file.metadata()?.len() as usize;
+
+        #[cfg(all(target_os = "linux", feature = "io_uring"))]
+        let ring = config
+            .io_uring
+            .then(|| io_uring::IoUring::new(4).ok().map(std::sync::Mutex::new))
+            .flatten();
+
+        Ok(Self {
+            file,
+            path,
+            len,
+            alignment: page_size(),
+            direct,
+            pinned: std::sync::Mutex::new(Vec::new()),
+            pool: std::sync::Mutex::new(Vec::new()),
+            max_pooled: config.max_pooled,
+            #[cfg(all(target_os = "linux", feature = "io_uring"))]
+            ring,
+        })
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    fn open_file(path: &Path) -> std::io::Result<(File, bool)> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        match std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(path)
+        {
+            Ok(file) => Ok((file, true)),
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => Ok((File::open(path)?, false)),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+    fn open_file(path: &Path) -> std::io::Result<(File, bool)> {
+        Ok((File::open(path)?, false))
+    }
+
+    /// `O_DIRECT` で読んでいるかどうか（対応していないファイルシステムでは `false`）
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    /// io_uringで読み込みを発行しているかどうか
+    pub fn uses_io_uring(&self) -> bool {
+        #[cfg(all(target_os = "linux", feature = "io_uring"))]
+        return self.ring.is_some();
+        #[cfg(not(all(target_os = "linux", feature = "io_uring")))]
+        false
+    }
+
+    /// 範囲をページ境界に広げてバッファに読み込む
+    fn load(&self, offset: usize, len: usize) -> Result<Loaded, MmapError> {
+        let start = offset / self.alignment * self.alignment;
+        let end = (offset + len).div_ceil(self.alignment) * self.alignment;
+        let capacity = end - start;
+
+        let buffer = {
+            let mut pool = self.pool.lock().unwrap();
+            match pool.iter().position(|buffer| buffer.capacity() >= capacity) {
+                Some(pos) => pool.swap_remove(pos),
+                None => AlignedBuffer::new(capacity, self.alignment),
+            }
+        };
+
+        // ファイル末尾では短く読めるので、要求した範囲が埋まるまで読む
+        let need = offset + len - start;
+        let mut filled = 0;
+        while filled < need {
+            let n = self
+                .read_at(&buffer, filled, capacity - filled, start + filled)
+                .map_err(MmapError::Read)?;
+            if n == 0 {
+                return Err(MmapError::Read(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+            let read_from = filled;
+            filled += n;
+            // `O_DIRECT` は境界からしか読めないので、途中で短く読めたら境界まで戻って続きを読む。
+            // 境界を1つも越えていなければ、残りは通常の読み込みで埋める
+            if self.direct && filled < need && !filled.is_multiple_of(self.alignment) {
+                let aligned = filled / self.alignment * self.alignment;
+                if aligned > read_from {
+                    filled = aligned;
+                } else {
+                    self.read_buffered(&buffer, filled, need - filled, start + filled)
+                        .map_err(MmapError::Read)?;
+                    filled = need;
+                }
+            }
+        }
+        Ok(Loaded {
+            offset: start,
+            filled,
+            buffer,
+        })
+    }
+
+    fn read_at(&self, buffer: &AlignedBuffer, at: usize, len: usize, offset: usize) -> std::io::Result<usize> {
+        // io_uringの1回の読み込みは長さが `u32` に収まる範囲だけなので、4GiB以上は `pread` で読む
+        #[cfg(all(target_os = "linux", feature = "io_uring"))]
+        if let (Some(ring), Ok(len)) = (&self.ring, u32::try_from(len)) {
+            return Self::read_uring(&mut ring.lock().unwrap(), &self.file, buffer, at, len, offset);
+        }
+
+        use std::os::unix::fs::FileExt;
+        let buf = unsafe { std::slice::from_raw_parts_mut(buffer.ptr.as_ptr().add(at), len) };
+        self.file.read_at(buf, offset as u64)
+    }
+
+    /// `O_DIRECT` を付けずに開き直して、バッファの `at` から `len` バイトを埋める
+    fn read_buffered(&self, buffer: &AlignedBuffer, at: usize, len: usize, offset: usize) -> std::io::Result<()> {
+        use std::os::unix::fs::FileExt;
+        let buf = unsafe { std::slice::from_raw_parts_mut(buffer.ptr.as_ptr().add(at), len) };
+        File::open(&self.path)?.read_exact_at(buf, offset as u64)
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io_uring"))]
+    fn read_uring(
+        ring: &mut io_uring::IoUring,
+        file: &File,
+        buffer: &AlignedBuffer,
+        at: usize,
+        len: u32,
+        offset: usize,
+    ) -> std::io::Result<usize> {
+        use io_uring::{opcode, types};
+        use std::os::unix::io::AsRawFd;
+
+        let entry = opcode::Read::new(
+            types::Fd(file.as_raw_fd()),
+            unsafe { buffer.ptr.as_ptr().add(at) },
+            len,
+        )
+        .offset(offset as u64)
+        .build();
+        // 完了を待ってから返すので、バッファとファイルは読み込みの間有効
+        unsafe { ring.submission().push(&entry) }
+            .map_err(|_| std::io::Error::other("io_uring submission queue is full"))?;
+        ring.submit_and_wait(1)?;
+        let cqe = ring
+            .completion()
+            .next()
+            .ok_or_else(|| std::io::Error::other("io_uring returned no completion"))?;
+        if cqe.result() < 0 {
+            return Err(std::io::Error::from_raw_os_error(-cqe.result()));
+        }
+        Ok(cqe.result() as usize)
+    }
+
+    fn check_range(&self, offset: usize, len: usize) -> Result<(), MmapError> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(MmapError::MapError(format!(
+                "Range out of bounds: offset={}, len={}, file_size={}",
+                offset, len, self.len
+            )));
+        }
+        Ok(())
+    }
+
+    /// 使い終わったバッファをプールに戻す
+    fn recycle(&self, buffer: AlignedBuffer) {
+        let mut pool = self.pool.lock().unwrap();
+        if pool.len() < self.max_pooled {
+            pool.push(buffer);
+        }
+    }
+
+    /// 再利用のためにプールしているバッファの数
+    pub fn pooled_buffers(&self) -> usize {
+        self.pool.lock().unwrap().len()
+    }
+}
+
+#[cfg(unix)]
+impl ShardStorage for DirectStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn file(&self) -> &File {
+        &self.file
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn read_range(&self, offset: usize, len: usize) -> Result<&[u8], MmapError> {
+        self.pin(offset, len)?;
+        if len == 0 {
+            return Ok(&[]);
+        }
+        let pinned = self.pinned.lock().unwrap();
+        Ok(pinned
+            .iter()
+            .find_map(|loaded| loaded.slice(offset, len))
+            .expect("pinned range covers the request"))
+    }
+
+    fn read(&self, offset: usize, len: usize) -> Result<StorageBytes<'_>, MmapError> {
+        self.check_range(offset, len)?;
+        if len == 0 {
+            return Ok(StorageBytes::Borrowed(&[]));
+        }
+        if let Some(slice) = self.pinned.lock().unwrap().iter().find_map(|loaded| loaded.slice(offset, len)) {
+            return Ok(StorageBytes::Borrowed(slice));
+        }
+        Ok(StorageBytes::Buffered(DirectBytes {
+            storage: self,
+            loaded: Some(self.load(offset, len)?),
+            offset,
+            len,
+        }))
+    }
+
+    fn pin(&self, offset: usize, len: usize) -> Result<(), MmapError> {
+        self.check_range(offset, len)?;
+        let mut pinned = self.pinned.lock().unwrap();
+        if len == 0 || pinned.iter().any(|loaded| loaded.slice(offset, len).is_some()) {
+            return Ok(());
+        }
+        pinned.push(self.load(offset, len)?);
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn create_file(len: usize) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        file.write_all(&data).unwrap();
+        file.flush().unwrap();
+        file
+    }
+
+    #[test]
+    fn test_direct_storage() {
+        let len = 3 * page_size() + 100;
+        let file = create_file(len);
+        for io_uring in [false, true] {
+            let config = DirectConfig {
+                io_uring,
+                ..Default::default()
+            };
+            let storage = DirectStorage::open(file.path(), &config).unwrap();
+            assert_eq!(ShardStorage::len(&storage), len);
+
+            // ページ境界をまたぐ範囲とファイル末尾の範囲
+            let offset = page_size() - 10;
+            let first = storage.read(offset, 20).unwrap();
+            let tail = storage.read(len - 50, 50).unwrap();
+            assert_eq!(first[0], (offset % 251) as u8);
+            assert_eq!(tail[49], ((len - 1) % 251) as u8);
+            assert!(first.as_borrowed().is_none());
+            assert!(storage.read(len - 1, 2).is_err());
+
+            // 読み込んだバッファは破棄するとプールに戻り、次の読み込みで再利用する
+            drop((first, tail));
+            assert_eq!(storage.pooled_buffers(), 2);
+            assert_eq!(storage.read(offset, 20).unwrap()[0], (offset % 251) as u8);
+            assert_eq!(storage.pooled_buffers(), 2);
+
+            // 保持した範囲は借用で返す
+            storage.pin(0, page_size()).unwrap();
+            assert_eq!(storage.read(10, 10).unwrap().as_borrowed().unwrap()[0], 10);
+            assert_eq!(storage.read_range(1, 1).unwrap(), [1]);
+        }
+    }
+
+    #[test]
+    fn test_open_storage() {
+        let file = create_file(1000);
+        let mmap = open_storage(file.path(), &StorageBackend::Mmap, &MmapConfig::default()).unwrap();
+        assert!(mmap.as_mmap().is_some());
+        let direct = open_storage(file.path(), &StorageBackend::Direct(DirectConfig::default()), &MmapConfig::default())
+            .unwrap();
+        assert!(direct.as_mmap().is_none());
+        assert_eq!(mmap.read_range(100, 50).unwrap(), direct.read_range(100, 50).unwrap());
+    }
+}